@@ -0,0 +1,303 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+use crate::controller::PaddleController;
+use crate::rng::Rng;
+use crate::{Ball, Orientation, Player, PaddleInput, Side, Simulation};
+
+/// Maximum number of wall bounces looked at when predicting the ball
+const MAX_PREDICTED_BOUNCES: usize = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        match name.to_lowercase().as_str() {
+            "easy"   => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard"   => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    /// Number of ticks between the ball moving and the CPU noticing it
+    fn reaction_ticks(&self) -> usize {
+        match self {
+            Difficulty::Easy   => 18,
+            Difficulty::Normal => 9,
+            Difficulty::Hard   => 3,
+        }
+    }
+
+    /// Largest offset (in arena units) between the predicted and the real impact point
+    fn prediction_error(&self) -> f32 {
+        match self {
+            Difficulty::Easy   => 90.,
+            Difficulty::Normal => 45.,
+            Difficulty::Hard   => 12.,
+        }
+    }
+
+    /// Fraction of the paddle speed the CPU is allowed to use
+    fn max_speed(&self) -> f32 {
+        match self {
+            Difficulty::Easy   => 0.55,
+            Difficulty::Normal => 0.8,
+            Difficulty::Hard   => 1.,
+        }
+    }
+}
+
+/// Position and velocity of the ball as seen by the CPU
+#[derive(Debug, Copy, Clone)]
+struct Observation {
+    pos: Vec2,
+    vel: Vec2,
+}
+
+/// CPU opponent, predicts where the ball is going to cross its paddle and moves there
+pub struct AiController {
+    difficulty: Difficulty,
+    observations: VecDeque<Observation>,
+    approaching: bool,
+    error: f32,
+    rng: Rng,
+}
+
+impl AiController {
+    pub fn new(difficulty: Difficulty, seed: u64) -> Self {
+        AiController {
+            difficulty: difficulty,
+            observations: VecDeque::new(),
+            approaching: false,
+            error: 0.,
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl PaddleController for AiController {
+    fn control(&mut self, sim: &Simulation, side: Side) -> PaddleInput {
+        let player = match sim.players.iter().find(|player| player.side == side) {
+            Some(player) => player,
+            None => return PaddleInput::default(),
+        };
+
+        // Only react to what happened a few ticks ago
+        let ball = watched_ball(sim, player);
+        self.observations.push_back(Observation { pos: ball.pos, vel: ball.vel });
+        while self.observations.len() > self.difficulty.reaction_ticks() + 1 {
+            self.observations.pop_front();
+        }
+        let seen = self.observations[0];
+
+        // From here on every paddle is treated like a vertical one
+        let seen_pos = as_vertical(side, seen.pos);
+        let seen_vel = as_vertical(side, seen.vel);
+        let player_pos = as_vertical(side, player.pos);
+        let player_size = as_vertical(side, player.size);
+        let ball_size = as_vertical(side, ball.size);
+
+        // Roll a new mistake every time the ball starts coming this way
+        let approaching = match side.is_far() {
+            false => seen_vel.x < 0.,
+            true  => seen_vel.x > 0.,
+        };
+        if approaching && !self.approaching {
+            let max_error = self.difficulty.prediction_error();
+            self.error = self.rng.range(-max_error, max_error);
+        }
+        self.approaching = approaching;
+
+        // Wait in the middle while the opponent has the ball
+        let target = if approaching {
+            let face_x = player_pos.x + match side.is_far() {
+                false =>  (player_size.x + ball_size.x) / 2.,
+                true  => -(player_size.x + ball_size.x) / 2.,
+            };
+            // Four player arenas have no long walls to bounce off
+            let predicted = match sim.rules.four_players {
+                true  => predict_straight(seen_pos, seen_vel, face_x),
+                false => predict_ball_y(sim, seen_pos, seen_vel, face_x),
+            };
+            predicted + self.error
+        } else {
+            as_vertical(side, Vec2 { x: sim.arena.width, y: sim.arena.height }).y / 2.
+        };
+
+        // Move towards the target, slowing down when close to avoid jittering around it
+        let axis = (target - player_pos.y) / player.speed;
+        let max_speed = self.difficulty.max_speed();
+        PaddleInput {
+            axis: axis.clamp(-max_speed, max_speed),
+        }
+    }
+}
+
+/// Swaps the axes for top and bottom paddles, so x is always the way towards the goal
+fn as_vertical(side: Side, vec: Vec2) -> Vec2 {
+    match side.orientation() {
+        Orientation::Vertical   => vec,
+        Orientation::Horizontal => Vec2 { x: vec.y, y: vec.x },
+    }
+}
+
+/// With several balls in play, the one that reaches the paddle first is the one to catch
+fn watched_ball<'a>(sim: &'a Simulation, player: &Player) -> &'a Ball {
+    let ticks_to_paddle = |ball: &Ball| {
+        let player_pos = as_vertical(player.side, player.pos);
+        let pos = as_vertical(player.side, ball.pos);
+        let vel = as_vertical(player.side, ball.vel);
+        let ticks = (player_pos.x - pos.x) / vel.x;
+        if ticks > 0. { ticks } else { f32::INFINITY }
+    };
+    sim.balls.iter()
+        .min_by(|a, b| ticks_to_paddle(a).total_cmp(&ticks_to_paddle(b)))
+        .unwrap_or(&sim.balls[0])
+}
+
+/// Height at which a ball at `pos` moving with `vel` reaches `target_x` if nothing is in its way
+fn predict_straight(pos: Vec2, vel: Vec2, target_x: f32) -> f32 {
+    let ticks = (target_x - pos.x) / vel.x;
+    if vel.x == 0. || ticks <= 0. {
+        return pos.y;
+    }
+    pos.y + vel.y * ticks
+}
+
+/// Predicts the height at which a ball at `pos` moving with `vel` reaches `target_x`,
+/// bouncing off the walls (and losing speed on each bounce) on the way
+pub fn predict_ball_y(sim: &Simulation, pos: Vec2, vel: Vec2, target_x: f32) -> f32 {
+    if vel.x == 0. {
+        return pos.y;
+    }
+
+    // Range the center of the ball can move in between the walls
+    let center_y = sim.arena.height / 2.;
+    let half_ball = sim.balls[0].size.y / 2.;
+    let mut top = f32::NEG_INFINITY;
+    let mut bottom = f32::INFINITY;
+    for wall in &sim.walls {
+        if wall.pos.y < center_y {
+            top = top.max(wall.pos.y + wall.size.y / 2. + half_ball);
+        } else {
+            bottom = bottom.min(wall.pos.y - wall.size.y / 2. - half_ball);
+        }
+    }
+
+    let mut ticks = (target_x - pos.x) / vel.x;
+    if ticks <= 0. {
+        return pos.y;
+    }
+
+    let mut y = pos.y;
+    let mut vel_y = vel.y;
+    for _ in 0..MAX_PREDICTED_BOUNCES {
+        // Ticks until the ball hits the wall it's flying towards
+        let to_wall = if vel_y > 0. {
+            (bottom - y) / vel_y
+        } else if vel_y < 0. {
+            (top - y) / vel_y
+        } else {
+            f32::INFINITY
+        }.max(0.);
+
+        if to_wall >= ticks {
+            return y + vel_y * ticks;
+        }
+        y += vel_y * to_wall;
+        ticks -= to_wall;
+        vel_y *= -sim.balls[0].bounciness;
+    }
+    y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::rules::MatchRules;
+    use crate::{Arena, TickInput};
+
+    fn sim() -> Simulation {
+        Simulation::new(Arena::new(800., 600.), Config::default(), MatchRules::default(), 0)
+    }
+
+    #[test]
+    fn prediction_without_bounce_is_a_straight_line() {
+        let sim = sim();
+        let y = predict_ball_y(&sim, Vec2 { x: 400., y: 300. }, Vec2 { x: 5., y: 1. }, 600.);
+        assert_eq!(y, 340.);
+    }
+
+    #[test]
+    fn prediction_bounces_off_walls() {
+        let sim = sim();
+        // Inner edge of the bottom wall is at 560, the ball center stops 5 px earlier
+        let y = predict_ball_y(&sim, Vec2 { x: 400., y: 500. }, Vec2 { x: 5., y: 5. }, 600.);
+        let after_bounce = 40. - 11.;
+        assert!((y - (555. - after_bounce * 5. * sim.balls[0].bounciness)).abs() < 0.001);
+    }
+
+    #[test]
+    fn ball_moving_away_is_not_predicted() {
+        let sim = sim();
+        let y = predict_ball_y(&sim, Vec2 { x: 400., y: 300. }, Vec2 { x: -5., y: 3. }, 600.);
+        assert_eq!(y, 300.);
+    }
+
+    #[test]
+    fn hard_cpu_beats_easy_cpu() {
+        let mut sim = sim();
+        let mut easy = AiController::new(Difficulty::Easy, 1);
+        let mut hard = AiController::new(Difficulty::Hard, 2);
+
+        for _ in 0..60 * 60 * 5 {
+            let input = TickInput {
+                left: easy.control(&sim, Side::Left),
+                right: hard.control(&sim, Side::Right),
+                ..TickInput::default()
+            };
+            sim.update(&input);
+        }
+
+        assert!(sim.score.points[1] > sim.score.points[0]);
+    }
+
+    #[test]
+    fn cpu_follows_the_ball_on_a_horizontal_paddle() {
+        let rules = MatchRules { four_players: true, ..MatchRules::default() };
+        let mut sim = Simulation::new(Arena::new(800., 600.), Config::default(), rules, 0);
+        let mut cpu = AiController::new(Difficulty::Hard, 1);
+        sim.balls[0].pos = Vec2 { x: 500., y: 300. };
+        sim.balls[0].vel = Vec2 { x: 2., y: 5. };
+
+        // Heading for the bottom paddle, right of the middle
+        let mut input = PaddleInput::default();
+        for _ in 0..=Difficulty::Hard.reaction_ticks() {
+            input = cpu.control(&sim, Side::Bottom);
+        }
+        assert!(input.axis > 0.);
+    }
+
+    #[test]
+    fn cpu_speed_is_limited_by_difficulty() {
+        let mut sim = sim();
+        let mut easy = AiController::new(Difficulty::Easy, 1);
+        sim.balls[0].vel = Vec2 { x: -5., y: 0. };
+        sim.balls[0].pos = Vec2 { x: 400., y: 100. };
+
+        // Far away target, the paddle would like to move at full speed
+        for _ in 0..=Difficulty::Easy.reaction_ticks() {
+            easy.control(&sim, Side::Left);
+        }
+        let input = easy.control(&sim, Side::Left);
+        assert_eq!(input.axis, -Difficulty::Easy.max_speed());
+    }
+}
@@ -0,0 +1,939 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use macroquad::prelude::*;
+
+use crate::ai::Difficulty;
+use crate::bindings::{self, Action, Bindings, DEFAULT_BINDINGS_PATH};
+use crate::cli::Options;
+use crate::config::{Config, ConfigWatcher, DEFAULT_CONFIG_PATH};
+use crate::editor::{Editor, EditorRequest, DEFAULT_LEVEL_PATH};
+use crate::effects::EffectSettings;
+use crate::gamepad::Gamepads;
+use crate::history::{History, DEFAULT_HISTORY_PATH};
+use crate::level::Level;
+use crate::menu::{self, Menu, MenuAction};
+use crate::net::{Connector, MatchSetup, NetShim, DEFAULT_PORT};
+use crate::replay::Replay;
+use crate::rules::{EdgeRule, MatchRules, ServeRule};
+use crate::sound::Sounds;
+use crate::{Arena, EventHandler, MyGame, Orientation, Side, SimEvent, COL_FOREGROUND, COL_LEFT, COL_RIGHT};
+
+/// Choices for the time limit setting in seconds, `None` plays without a clock
+const TIME_LIMITS: [Option<f32>; 5] = [None, Some(60.), Some(120.), Some(180.), Some(300.)];
+const BEST_OF: [u32; 4] = [1, 3, 5, 7];
+const DIFFICULTIES: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+const GAMEPADS: [Option<usize>; 5] = [None, Some(0), Some(1), Some(2), Some(3)];
+const LIVES: [u32; 5] = [1, 3, 5, 7, 9];
+const SERVE_RULES: [ServeRule; 3] = [ServeRule::Alternate, ServeRule::Loser, ServeRule::Winner];
+const EDGE_RULES: [EdgeRule; 2] = [EdgeRule::Bounce, EdgeRule::Miss];
+const VOLUMES: [f32; 11] = [0., 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.];
+const SHAKES: [f32; 5] = [0., 0.25, 0.5, 0.75, 1.];
+/// Bindings in the order they are listed on the controls screen
+const KEY_ACTIONS: [(Side, Action); 8] = [
+    (Side::Left, Action::Up),
+    (Side::Left, Action::Down),
+    (Side::Right, Action::Up),
+    (Side::Right, Action::Down),
+    (Side::Top, Action::Up),
+    (Side::Top, Action::Down),
+    (Side::Bottom, Action::Up),
+    (Side::Bottom, Action::Down),
+];
+
+/// Size of the touch area in the top center that pauses the game
+const PAUSE_BUTTON_SIZE: f32 = 60.;
+
+#[derive(Debug, Clone, PartialEq)]
+enum AppState {
+    Title,
+    Settings,
+    Controls,
+    /// Waiting for the key to bind to an action
+    Rebinding(Side, Action),
+    Lan,
+    /// Typing the address of the host to join
+    EnterAddress,
+    /// Waiting for the other player of a network game
+    Connecting,
+    /// Picking who sits at which paddle
+    Profiles,
+    /// Typing the name of a new profile
+    EnterName,
+    Stats,
+    Editor,
+    Playing,
+    /// A goal ended the round (`SimEvent::Goal`, `LifeLost` or `Eliminated`), waiting for the next serve
+    RoundOver(SimEvent),
+    /// The game is frozen, resuming continues in the stored state
+    Paused(Box<AppState>),
+    MatchOver(Side),
+    /// The other player of a network game is gone
+    ConnectionLost,
+}
+
+/// Top level of the program, decides which screen is shown and whether the game is running
+pub struct App {
+    state: AppState,
+    game: Option<MyGame>,
+    menu: Menu,
+    rules: MatchRules,
+    /// Layout of two player matches, `None` is the usual field
+    level: Option<Level>,
+    /// Kept while test playing so editing goes on where it stopped
+    editor: Option<Editor>,
+    /// Where the editor saves the level
+    level_path: PathBuf,
+    /// The running game plays the level of the editor, leaving it goes back there
+    testing: bool,
+    difficulty: Difficulty,
+    config: Config,
+    bindings: Bindings,
+    gamepads: Rc<RefCell<Gamepads>>,
+    sounds: Sounds,
+    /// Particles, ball trail and screen shake of every game
+    effects: EffectSettings,
+    /// Draws the ball as a circle, it bounces like one either way
+    round_ball: bool,
+    history: History,
+    /// Where the history is saved, `None` if the file couldn't be read and must not be overwritten
+    history_path: Option<PathBuf>,
+    /// Profile playing at each side, indexed by side. Without one the side is named after itself.
+    seats: [Option<usize>; 4],
+    new_profile: String,
+    profile_error: Option<String>,
+    /// Watches the config file when hot reloading is on
+    config_watcher: Option<ConfigWatcher>,
+    /// Every match gets recorded to this file
+    record_path: Option<PathBuf>,
+    /// Handshake of a network game in progress
+    connector: Option<Connector>,
+    port: u16,
+    join_address: String,
+    /// Simulated bad connection for testing network games
+    net_shim: Option<NetShim>,
+    /// Why the last attempt to host or join failed
+    net_error: Option<String>,
+    quit: bool,
+}
+
+impl App {
+    pub fn new(options: &Options, config: Config, replay: Option<Replay>, level: Option<Level>, sounds: Sounds) -> Self {
+        let mut menu = Menu::new();
+        // Preselect the CPU game if it was asked for on the command line
+        if options.cpu.is_some() {
+            menu.selected = 1;
+        }
+
+        // A replay skips the title and starts playing right away
+        let gamepads = Rc::new(RefCell::new(Gamepads::new()));
+        let (state, game) = match replay {
+            Some(replay) => (AppState::Playing, Some(MyGame::from_replay(replay, gamepads.clone()))),
+            None => (AppState::Title, None),
+        };
+
+        let config_watcher = match options.hot_reload {
+            true => Some(ConfigWatcher::new(options.config.clone().unwrap_or(DEFAULT_CONFIG_PATH.into()))),
+            false => None,
+        };
+
+        let (history, history_path) = match History::load_or_default(Path::new(DEFAULT_HISTORY_PATH)) {
+            Ok(history) => (history, Some(PathBuf::from(DEFAULT_HISTORY_PATH))),
+            Err(message) => {
+                eprintln!("{}, matches won't be saved", message);
+                (History::default(), None)
+            },
+        };
+
+        let mut app = App {
+            state: state,
+            game: game,
+            menu: menu,
+            rules: options.rules,
+            level: level,
+            editor: None,
+            level_path: options.level.clone().unwrap_or(DEFAULT_LEVEL_PATH.into()),
+            testing: false,
+            difficulty: options.cpu.unwrap_or(Difficulty::Normal),
+            config: config,
+            bindings: Bindings::load_or_default(Path::new(DEFAULT_BINDINGS_PATH)),
+            gamepads: gamepads,
+            sounds: sounds,
+            effects: match options.reduce_motion {
+                true  => EffectSettings::reduced_motion(),
+                false => EffectSettings::default(),
+            },
+            round_ball: false,
+            history: history,
+            history_path: history_path,
+            seats: [None; 4],
+            new_profile: String::new(),
+            profile_error: None,
+            config_watcher: config_watcher,
+            record_path: options.record.clone(),
+            connector: None,
+            port: options.port.unwrap_or(DEFAULT_PORT),
+            join_address: options.join.clone().unwrap_or("127.0.0.1".to_string()),
+            net_shim: match options.net_lag > 0. || options.net_loss > 0. {
+                true => Some(NetShim { latency: options.net_lag / 1000., loss: options.net_loss / 100. }),
+                false => None,
+            },
+            net_error: None,
+            quit: false,
+        };
+
+        // Network games asked for on the command line start connecting right away
+        if options.host {
+            app.host_game();
+        } else if options.join.is_some() {
+            app.join_game();
+        }
+        app
+    }
+
+    pub fn quit_requested(&self) -> bool {
+        self.quit
+    }
+
+    fn change_state(&mut self, state: AppState) -> () {
+        self.state = state;
+        self.menu = Menu::new();
+    }
+
+    fn start_game(&mut self, cpu: Option<Difficulty>) -> () {
+        self.game = Some(MyGame::new(
+            self.config.clone(),
+            self.rules,
+            self.level.clone(),
+            cpu,
+            self.bindings.clone(),
+            self.gamepads.clone(),
+            self.record_path.clone()
+        ));
+        self.testing = false;
+        self.change_state(AppState::Playing);
+    }
+
+    /// Opens the editor where it was left, on the level being played or on the saved one
+    fn open_editor(&mut self) -> () {
+        if self.editor.is_none() {
+            let level = match &self.level {
+                Some(level) => level.clone(),
+                None => Level::load(&self.level_path, &self.config).unwrap_or_else(|message| {
+                    if self.level_path.exists() {
+                        eprintln!("{}", message);
+                    }
+                    Level::classic(&self.config)
+                }),
+            };
+            self.editor = Some(Editor::new(level, self.config.clone(), self.level_path.clone()));
+        }
+        self.change_state(AppState::Editor);
+    }
+
+    fn update_editor(&mut self) -> () {
+        let request = match &mut self.editor {
+            Some(editor) => editor.update(),
+            None => return self.change_state(AppState::Title),
+        };
+        match request {
+            Some(EditorRequest::TestPlay(level)) => {
+                self.game = Some(MyGame::new(
+                    self.config.clone(),
+                    self.rules,
+                    Some(level),
+                    None,
+                    self.bindings.clone(),
+                    self.gamepads.clone(),
+                    None
+                ));
+                self.testing = true;
+                self.change_state(AppState::Playing);
+            },
+            Some(EditorRequest::Leave) => self.change_state(AppState::Title),
+            None => (),
+        }
+    }
+
+    /// Replaces the current game by a fresh one with the same players and rules
+    fn restart_game(&mut self) -> () {
+        self.leave_game();
+        let game = self.game.as_ref().map(|game| game.rematch());
+        self.game = game;
+        self.change_state(AppState::Playing);
+    }
+
+    /// Called whenever a game is over or abandoned
+    fn leave_game(&mut self) -> () {
+        if let Some(game) = &mut self.game {
+            game.save_recording();
+        }
+    }
+
+    /// Name of the profile at a side, or the side itself if nobody picked a profile
+    fn seat_name(&self, side: Side) -> String {
+        match self.seats[side as usize] {
+            Some(profile) => self.history.profiles[profile].clone(),
+            None => format!("{:?}", side),
+        }
+    }
+
+    /// Adds the finished match to the history file
+    fn record_match(&mut self) -> () {
+        let names = Side::ALL.map(|side| self.seat_name(side));
+        let record = match self.game.as_ref().and_then(|game| game.match_record(&names)) {
+            Some(record) => record,
+            None => return,
+        };
+        self.history.record(record);
+        self.save_history();
+    }
+
+    fn save_history(&self) -> () {
+        if let Some(path) = &self.history_path {
+            if let Err(message) = self.history.save(path) {
+                eprintln!("{}", message);
+            }
+        }
+    }
+
+    fn quit_to_title(&mut self) -> () {
+        self.leave_game();
+        if let Some(game) = &mut self.game {
+            game.leave_network();
+        }
+        match std::mem::replace(&mut self.testing, false) {
+            true  => self.change_state(AppState::Editor),
+            false => self.change_state(AppState::Title),
+        }
+    }
+
+    fn is_network_game(&self) -> bool {
+        self.game.as_ref().is_some_and(|game| game.is_network())
+    }
+
+    /// Offers a match with the current settings on the local network
+    fn host_game(&mut self) -> () {
+        let setup = MatchSetup {
+            seed: (miniquad::date::now() * 1000.) as u64,
+            arena: Arena::from_config(&self.config),
+            // Network games are always one against one
+            rules: MatchRules { four_players: false, ..self.rules },
+            config: self.config.clone(),
+        };
+        self.connect(Connector::host(self.port, setup));
+    }
+
+    fn join_game(&mut self) -> () {
+        self.connect(Connector::join(&self.join_address));
+    }
+
+    fn connect(&mut self, connector: Result<Connector, String>) -> () {
+        match connector {
+            Ok(connector) => {
+                self.connector = Some(connector);
+                self.net_error = None;
+                self.change_state(AppState::Connecting);
+            },
+            Err(message) => {
+                self.net_error = Some(message);
+                self.change_state(AppState::Lan);
+            },
+        }
+    }
+
+    /// Picks up changes to the config file, a broken file is reported and the old values stay
+    fn reload_config(&mut self) -> () {
+        let watcher = match &mut self.config_watcher {
+            Some(watcher) => watcher,
+            None => return,
+        };
+        match watcher.poll(get_time()) {
+            Some(Ok(config)) => {
+                println!("Config reloaded");
+                if let Some(game) = &mut self.game {
+                    game.reconfigure(config.clone());
+                }
+                if let Some(editor) = &mut self.editor {
+                    editor.config = config.clone();
+                }
+                self.config = config;
+            },
+            Some(Err(message)) => eprintln!("{}", message),
+            None => (),
+        }
+    }
+
+    /// Last item of the in-game menus, a test play goes back to the editor
+    fn quit_item(&self) -> String {
+        match self.testing {
+            true  => "Back to editor".to_string(),
+            false => "Quit to title".to_string(),
+        }
+    }
+
+    fn menu_items(&self) -> Vec<String> {
+        match &self.state {
+            AppState::Title => vec![
+                "2 Players".to_string(),
+                format!("Vs CPU ({:?})", self.difficulty),
+                "LAN game".to_string(),
+                "Profiles & stats".to_string(),
+                "Level editor".to_string(),
+                "Settings".to_string(),
+                "Quit".to_string(),
+            ],
+            AppState::Profiles => {
+                let mut items: Vec<String> = Side::ALL.iter()
+                    .map(|&side| format!("{:?} player: {}", side, self.seat_name(side)))
+                    .collect();
+                items.push("New profile".to_string());
+                items.push("Stats".to_string());
+                items.push("Back".to_string());
+                items
+            },
+            // The stats are shown as the items of the menu, choosing any of them goes back
+            AppState::Stats => {
+                let mut items: Vec<String> = self.history.profiles.iter()
+                    .map(|name| {
+                        let summary = self.history.summary(name);
+                        format!(
+                            "{}: {}/{} won, rally {}, {:.0} px/s, curve {:.0}%",
+                            name,
+                            summary.wins,
+                            summary.matches,
+                            summary.longest_rally,
+                            summary.fastest_ball,
+                            summary.curve_usage * 100.
+                        )
+                    })
+                    .collect();
+                if items.is_empty() {
+                    items.push("No profiles yet".to_string());
+                }
+                if let Some(last) = self.history.matches.last() {
+                    let players: Vec<String> = last.players.iter()
+                        .map(|player| format!("{} {}", player.name, player.points))
+                        .collect();
+                    items.push(format!("Last match: {}", players.join(" - ")));
+                }
+                items.push(format!("{} matches played", self.history.matches.len()));
+                items.push("Back".to_string());
+                items
+            },
+            AppState::Settings => vec![
+                format!("CPU: {:?}", self.difficulty),
+                format!("Points to win: {}", self.rules.points_to_win),
+                format!("Win by two: {}", if self.rules.win_by_two { "On" } else { "Off" }),
+                match self.rules.time_limit {
+                    Some(limit) => format!("Time limit: {}:{:02}", limit as u32 / 60, limit as u32 % 60),
+                    None => "Time limit: Off".to_string(),
+                },
+                format!("Best of: {}", self.rules.best_of),
+                format!("Players: {}", if self.rules.four_players { 4 } else { 2 }),
+                format!("Lives: {}", self.rules.lives),
+                format!("Serve: {:?}", self.rules.serve),
+                format!("Paddle edges: {:?}", self.rules.edge_hits),
+                format!("Power-ups: {}", if self.config.power_ups.enabled { "On" } else { "Off" }),
+                format!("Multi-ball: {}", if self.config.multi_ball.enabled { "On" } else { "Off" }),
+                format!("Volume: {:.0}%", self.sounds.volume * 100.),
+                format!("Sound: {}", if self.sounds.muted { "Off" } else { "On" }),
+                format!("Particles: {}", if self.effects.particles { "On" } else { "Off" }),
+                format!("Ball trail: {}", if self.effects.trail { "On" } else { "Off" }),
+                format!("Screen shake: {:.0}%", self.effects.shake * 100.),
+                format!("Ball shape: {}", if self.round_ball { "Round" } else { "Square" }),
+                "Controls".to_string(),
+                "Back".to_string(),
+            ],
+            AppState::Controls => {
+                let mut items: Vec<String> = KEY_ACTIONS.iter()
+                    .map(|&(side, action)| {
+                        let controls = self.bindings.controls(side);
+                        let horizontal = side.orientation() == Orientation::Horizontal;
+                        let (name, keys) = match action {
+                            Action::Up => (if horizontal { "left" } else { "up" }, &controls.up),
+                            Action::Down => (if horizontal { "right" } else { "down" }, &controls.down),
+                        };
+                        let keys: Vec<String> = keys.iter().map(|&key| bindings::key_name(key)).collect();
+                        let keys = if keys.is_empty() { "-".to_string() } else { keys.join(", ") };
+                        format!("{:?} {}: {}", side, name, keys)
+                    })
+                    .collect();
+                for side in Side::ALL {
+                    items.push(match self.bindings.controls(side).gamepad {
+                        Some(index) => format!("{:?} gamepad: {}", side, index + 1),
+                        None => format!("{:?} gamepad: Off", side),
+                    });
+                }
+                items.push("Reset to defaults".to_string());
+                items.push("Back".to_string());
+                items
+            },
+            AppState::Lan => vec![
+                format!("Host on port {}", self.port),
+                format!("Join {}", self.join_address),
+                "Back".to_string(),
+            ],
+            // A network game can't be restarted by just one of the players
+            AppState::Paused(_) if self.is_network_game() => vec![
+                "Resume".to_string(),
+                "Quit to title".to_string(),
+            ],
+            AppState::Paused(_) => vec![
+                "Resume".to_string(),
+                "Restart".to_string(),
+                self.quit_item(),
+            ],
+            AppState::MatchOver(_) if self.is_network_game() => vec![
+                "Quit to title".to_string(),
+            ],
+            AppState::MatchOver(_) => vec![
+                "Rematch".to_string(),
+                self.quit_item(),
+            ],
+            AppState::ConnectionLost => vec![
+                "Quit to title".to_string(),
+            ],
+            AppState::Rebinding(..) | AppState::EnterAddress | AppState::EnterName | AppState::Connecting
+                | AppState::Editor | AppState::Playing | AppState::RoundOver(_) => Vec::new(),
+        }
+    }
+
+    fn update_title(&mut self, action: MenuAction) -> () {
+        match action {
+            MenuAction::Choose(0) => self.start_game(None),
+            MenuAction::Choose(1) => self.start_game(Some(self.difficulty)),
+            MenuAction::Decrease(1) => self.difficulty = step(&DIFFICULTIES, self.difficulty, -1),
+            MenuAction::Increase(1) => self.difficulty = step(&DIFFICULTIES, self.difficulty, 1),
+            MenuAction::Choose(2) => self.change_state(AppState::Lan),
+            MenuAction::Choose(3) => self.change_state(AppState::Profiles),
+            MenuAction::Choose(4) => self.open_editor(),
+            MenuAction::Choose(5) => self.change_state(AppState::Settings),
+            MenuAction::Choose(6) | MenuAction::Back => self.quit = true,
+            _ => (),
+        }
+    }
+
+    fn update_settings(&mut self, action: MenuAction) -> () {
+        let (index, direction) = match action {
+            MenuAction::Choose(17) => {
+                self.change_state(AppState::Controls);
+                return;
+            },
+            MenuAction::Choose(18) | MenuAction::Back => {
+                self.change_state(AppState::Title);
+                return;
+            },
+            // Choosing a setting cycles through its values, which is all a touch screen can do
+            MenuAction::Choose(index) | MenuAction::Increase(index) => (index, 1),
+            MenuAction::Decrease(index) => (index, -1),
+        };
+
+        match index {
+            0 => self.difficulty = step(&DIFFICULTIES, self.difficulty, direction),
+            1 => {
+                let points = self.rules.points_to_win as i32 + direction;
+                self.rules.points_to_win = if points < 1 { 21 } else if points > 21 { 1 } else { points as u32 };
+            },
+            2 => self.rules.win_by_two = !self.rules.win_by_two,
+            3 => self.rules.time_limit = step(&TIME_LIMITS, self.rules.time_limit, direction),
+            4 => self.rules.best_of = step(&BEST_OF, self.rules.best_of, direction),
+            5 => self.rules.four_players = !self.rules.four_players,
+            6 => self.rules.lives = step(&LIVES, self.rules.lives, direction),
+            7 => self.rules.serve = step(&SERVE_RULES, self.rules.serve, direction),
+            8 => self.rules.edge_hits = step(&EDGE_RULES, self.rules.edge_hits, direction),
+            9 => self.config.power_ups.enabled = !self.config.power_ups.enabled,
+            10 => self.config.multi_ball.enabled = !self.config.multi_ball.enabled,
+            11 => self.sounds.volume = step(&VOLUMES, self.sounds.volume, direction),
+            12 => self.sounds.muted = !self.sounds.muted,
+            13 => self.effects.particles = !self.effects.particles,
+            14 => self.effects.trail = !self.effects.trail,
+            15 => self.effects.shake = step(&SHAKES, self.effects.shake, direction),
+            16 => self.round_ball = !self.round_ball,
+            _ => (),
+        }
+    }
+
+    fn update_controls(&mut self, action: MenuAction) -> () {
+        match action {
+            // Keep the menu as it is so the same item is selected again after the key was pressed
+            MenuAction::Choose(index) if index < KEY_ACTIONS.len() => {
+                let (side, action) = KEY_ACTIONS[index];
+                self.state = AppState::Rebinding(side, action);
+            },
+            // One gamepad item per side after the keys
+            MenuAction::Choose(index @ 8..=11) | MenuAction::Increase(index @ 8..=11) | MenuAction::Decrease(index @ 8..=11) => {
+                let direction = if matches!(action, MenuAction::Decrease(_)) { -1 } else { 1 };
+                let controls = self.bindings.controls_mut(Side::ALL[index - KEY_ACTIONS.len()]);
+                controls.gamepad = step(&GAMEPADS, controls.gamepad, direction);
+                self.save_bindings();
+            },
+            MenuAction::Choose(12) => {
+                self.bindings = Bindings::default();
+                self.save_bindings();
+            },
+            MenuAction::Choose(13) | MenuAction::Back => self.change_state(AppState::Settings),
+            _ => (),
+        }
+    }
+
+    fn update_profiles(&mut self, action: MenuAction) -> () {
+        match action {
+            // Cycle through the profiles and nobody
+            MenuAction::Choose(index @ 0..=3) | MenuAction::Increase(index @ 0..=3) | MenuAction::Decrease(index @ 0..=3) => {
+                let direction = if matches!(action, MenuAction::Decrease(_)) { -1 } else { 1 };
+                let choices: Vec<Option<usize>> = std::iter::once(None)
+                    .chain((0..self.history.profiles.len()).map(Some))
+                    .collect();
+                self.seats[index] = step(&choices, self.seats[index], direction);
+            },
+            MenuAction::Choose(4) => {
+                self.new_profile.clear();
+                self.profile_error = None;
+                self.state = AppState::EnterName;
+            },
+            MenuAction::Choose(5) => self.change_state(AppState::Stats),
+            MenuAction::Choose(6) | MenuAction::Back => self.change_state(AppState::Title),
+            _ => (),
+        }
+    }
+
+    fn update_enter_name(&mut self) -> () {
+        while let Some(character) = get_char_pressed() {
+            if !character.is_control() {
+                self.new_profile.push(character);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.new_profile.pop();
+        }
+        if is_key_pressed(KeyCode::Enter) {
+            match self.history.add_profile(&self.new_profile) {
+                Ok(()) => {
+                    self.save_history();
+                    self.state = AppState::Profiles;
+                },
+                Err(message) => self.profile_error = Some(message),
+            }
+        } else if is_key_pressed(KeyCode::Escape) {
+            self.state = AppState::Profiles;
+        }
+    }
+
+    fn update_rebinding(&mut self, side: Side, action: Action) -> () {
+        if is_key_pressed(KeyCode::Escape) {
+            self.state = AppState::Controls;
+        } else if is_key_pressed(KeyCode::Backspace) {
+            self.bindings.clear(side, action);
+            self.save_bindings();
+            self.state = AppState::Controls;
+        } else if let Some(key) = get_last_key_pressed() {
+            // Keys that can't be bound are ignored, the screen waits for another one
+            if self.bindings.toggle_key(side, action, key) {
+                self.save_bindings();
+                self.state = AppState::Controls;
+            }
+        }
+    }
+
+    fn save_bindings(&self) -> () {
+        if let Err(message) = self.bindings.save(Path::new(DEFAULT_BINDINGS_PATH)) {
+            eprintln!("{}", message);
+        }
+    }
+
+    fn update_lan(&mut self, action: MenuAction) -> () {
+        match action {
+            MenuAction::Choose(0) => self.host_game(),
+            MenuAction::Choose(1) => self.change_state(AppState::EnterAddress),
+            MenuAction::Choose(2) | MenuAction::Back => self.change_state(AppState::Title),
+            _ => (),
+        }
+    }
+
+    fn update_enter_address(&mut self) -> () {
+        while let Some(character) = get_char_pressed() {
+            if character.is_ascii_graphic() {
+                self.join_address.push(character);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.join_address.pop();
+        }
+        if is_key_pressed(KeyCode::Enter) {
+            self.join_game();
+        } else if is_key_pressed(KeyCode::Escape) {
+            self.change_state(AppState::Lan);
+        }
+    }
+
+    fn update_connecting(&mut self) -> () {
+        let connector = match &mut self.connector {
+            Some(connector) => connector,
+            None => return self.change_state(AppState::Lan),
+        };
+        if is_key_pressed(KeyCode::Escape) {
+            self.connector = None;
+            self.change_state(AppState::Lan);
+            return;
+        }
+
+        match connector.poll(get_time()) {
+            Ok(Some(connection)) => {
+                self.connector = None;
+                self.game = Some(MyGame::network(connection, self.bindings.clone(), self.gamepads.clone(), self.net_shim));
+                self.change_state(AppState::Playing);
+            },
+            Ok(None) => (),
+            Err(message) => {
+                self.connector = None;
+                self.net_error = Some(message);
+                self.change_state(AppState::Lan);
+            },
+        }
+    }
+
+    fn update_paused(&mut self, action: MenuAction, resume: AppState) -> () {
+        let network = self.is_network_game();
+        match action {
+            MenuAction::Choose(0) | MenuAction::Back => self.resume(resume),
+            MenuAction::Choose(1) if !network => self.restart_game(),
+            MenuAction::Choose(_) => self.quit_to_title(),
+            _ => (),
+        }
+    }
+
+    fn update_match_over(&mut self, action: MenuAction) -> () {
+        let network = self.is_network_game();
+        match action {
+            MenuAction::Choose(0) if !network => self.restart_game(),
+            MenuAction::Choose(_) | MenuAction::Back => self.quit_to_title(),
+            _ => (),
+        }
+    }
+
+    fn resume(&mut self, state: AppState) -> () {
+        // The time spent in the pause menu must not be simulated
+        if let Some(game) = &mut self.game {
+            game.skip_time();
+        }
+        self.change_state(state);
+    }
+
+    fn pause_requested() -> bool {
+        let pause_button = Rect::new(
+            (screen_width() - PAUSE_BUTTON_SIZE) / 2., 0., PAUSE_BUTTON_SIZE, PAUSE_BUTTON_SIZE
+        );
+        is_key_pressed(KeyCode::Escape) || is_key_pressed(KeyCode::P)
+            || touches().iter().any(|touch| touch.phase == TouchPhase::Started && pause_button.contains(touch.position))
+    }
+
+    /// Runs the game and moves between playing, round over and match over based on its events
+    fn update_game(&mut self) -> () {
+        if App::pause_requested() {
+            let resume = self.state.clone();
+            self.change_state(AppState::Paused(Box::new(resume)));
+            return;
+        }
+
+        let game = match &mut self.game {
+            Some(game) => game,
+            None => return,
+        };
+        game.effects.settings = self.effects;
+        game.round_ball = self.round_ball;
+        game.update();
+        self.sounds.play_events(&game.events, game.sim.config.ball.x_speed_limit);
+        if game.connection_lost() {
+            self.change_state(AppState::ConnectionLost);
+            return;
+        }
+
+        for event in game.events.clone() {
+            match event {
+                SimEvent::Goal(_) | SimEvent::LifeLost(_) | SimEvent::Eliminated(_) => {
+                    self.change_state(AppState::RoundOver(event))
+                },
+                SimEvent::Serve(_) => self.change_state(AppState::Playing),
+                SimEvent::MatchOver(side) => {
+                    // Test plays of a level don't count
+                    if !self.testing {
+                        self.record_match();
+                    }
+                    self.leave_game();
+                    self.change_state(AppState::MatchOver(side));
+                },
+                SimEvent::Point(_) | SimEvent::PowerUp(..) | SimEvent::Hit(..) | SimEvent::Bounce(_)
+                    | SimEvent::Countdown(_) => (),
+            }
+        }
+    }
+
+    fn draw_title() -> () {
+        // Half of the name in each player's color
+        let font_size = 120;
+        let text_left = "RUSTED ";
+        let text_right = "PONG";
+        let width = measure_text(text_left, None, font_size, 1.).width + measure_text(text_right, None, font_size, 1.).width;
+        let x = (screen_width() - width) / 2.;
+        let y = screen_height() * 0.2;
+        draw_text(text_left, x, y, font_size as f32, COL_LEFT);
+        draw_text(text_right, x + measure_text(text_left, None, font_size, 1.).width, y, font_size as f32, COL_RIGHT);
+    }
+
+    fn draw_pause_button() -> () {
+        let x = screen_width() / 2.;
+        let height = PAUSE_BUTTON_SIZE / 2.;
+        draw_rectangle(x - 12., (PAUSE_BUTTON_SIZE - height) / 2., 8., height, COL_FOREGROUND);
+        draw_rectangle(x + 4., (PAUSE_BUTTON_SIZE - height) / 2., 8., height, COL_FOREGROUND);
+    }
+
+    fn draw_round_over(event: SimEvent) -> () {
+        let (text, side) = match event {
+            SimEvent::LifeLost(side)   => (format!("{:?} loses a life", side), side),
+            SimEvent::Eliminated(side) => (format!("{:?} is out", side), side),
+            SimEvent::Goal(side)       => (format!("Point {:?}", side), side),
+            _ => return,
+        };
+        let text = text.to_uppercase();
+        let font_size = 60;
+        let text_center = get_text_center(&text, None, font_size, 1., 0.);
+        draw_text(
+            &text,
+            screen_width() / 2. - text_center.x,
+            screen_height() / 4. - text_center.y,
+            font_size as f32,
+            side.color()
+        );
+    }
+}
+
+impl EventHandler for App {
+    fn update(&mut self) -> () {
+        self.reload_config();
+
+        match self.state.clone() {
+            AppState::Playing | AppState::RoundOver(_) => self.update_game(),
+            AppState::Rebinding(side, action) => self.update_rebinding(side, action),
+            AppState::EnterAddress => self.update_enter_address(),
+            AppState::EnterName => self.update_enter_name(),
+            AppState::Connecting => self.update_connecting(),
+            AppState::Editor => self.update_editor(),
+            state => {
+                // The other player of a network game still needs to hear from this side
+                if let Some(game) = &mut self.game {
+                    if matches!(state, AppState::Paused(_) | AppState::MatchOver(_)) {
+                        game.keep_alive();
+                    }
+                }
+
+                let items = self.menu_items();
+                if let Some(action) = self.menu.update(&items) {
+                    match state {
+                        AppState::Title => self.update_title(action),
+                        AppState::Settings => self.update_settings(action),
+                        AppState::Controls => self.update_controls(action),
+                        AppState::Lan => self.update_lan(action),
+                        AppState::Profiles => self.update_profiles(action),
+                        AppState::Stats => if matches!(action, MenuAction::Choose(_) | MenuAction::Back) {
+                            self.change_state(AppState::Profiles);
+                        },
+                        AppState::Paused(resume) => self.update_paused(action, *resume),
+                        AppState::MatchOver(_) => self.update_match_over(action),
+                        AppState::ConnectionLost => self.quit_to_title(),
+                        AppState::Rebinding(..) | AppState::EnterAddress | AppState::EnterName
+                            | AppState::Connecting | AppState::Editor | AppState::Playing | AppState::RoundOver(_) => (),
+                    }
+                }
+            },
+        }
+    }
+
+    fn draw(&mut self) -> () {
+        clear_background(crate::COL_BACKGROUND);
+
+        // The game stays visible behind every in-game screen
+        let in_game = !matches!(
+            self.state,
+            AppState::Title | AppState::Settings | AppState::Controls | AppState::Rebinding(..)
+                | AppState::Lan | AppState::EnterAddress | AppState::Connecting
+                | AppState::Profiles | AppState::EnterName | AppState::Stats | AppState::Editor
+        );
+        if in_game {
+            if let Some(game) = &mut self.game {
+                game.draw();
+            }
+        }
+
+        match &self.state {
+            AppState::Title => App::draw_title(),
+            AppState::Settings => menu::draw_headline("SETTINGS", COL_FOREGROUND),
+            AppState::Controls => menu::draw_headline("CONTROLS", COL_FOREGROUND),
+            AppState::Rebinding(side, _) => {
+                menu::draw_headline("PRESS A KEY", side.color());
+                menu::draw_hint("A bound key gets unbound, Backspace clears, Escape cancels", 0.55, COL_FOREGROUND);
+            },
+            AppState::Lan => {
+                menu::draw_headline("LAN GAME", COL_FOREGROUND);
+                if let Some(message) = &self.net_error {
+                    menu::draw_hint(message, 0.35, RED);
+                }
+            },
+            AppState::EnterAddress => {
+                menu::draw_headline("JOIN", COL_FOREGROUND);
+                menu::draw_hint(&format!("Host address: {}_", self.join_address), 0.45, WHITE);
+                menu::draw_hint("Enter connects, Escape goes back", 0.55, COL_FOREGROUND);
+            },
+            AppState::Connecting => {
+                menu::draw_headline("WAITING", COL_FOREGROUND);
+                let text = match &self.connector {
+                    Some(connector) if connector.is_host() => format!("Hosting on port {}", connector.port()),
+                    _ => format!("Connecting to {}", self.join_address),
+                };
+                menu::draw_hint(&text, 0.45, WHITE);
+                menu::draw_hint("Escape cancels", 0.55, COL_FOREGROUND);
+            },
+            AppState::Profiles => menu::draw_headline("PROFILES", COL_FOREGROUND),
+            AppState::EnterName => {
+                menu::draw_headline("NEW PROFILE", COL_FOREGROUND);
+                menu::draw_hint(&format!("Name: {}_", self.new_profile), 0.45, WHITE);
+                match &self.profile_error {
+                    Some(message) => menu::draw_hint(message, 0.55, RED),
+                    None => menu::draw_hint("Enter saves, Escape goes back", 0.55, COL_FOREGROUND),
+                }
+            },
+            AppState::Stats => menu::draw_headline("STATS", COL_FOREGROUND),
+            AppState::Editor => if let Some(editor) = &self.editor {
+                editor.draw();
+            },
+            AppState::Playing => App::draw_pause_button(),
+            AppState::RoundOver(event) => {
+                App::draw_pause_button();
+                App::draw_round_over(*event);
+            },
+            AppState::Paused(_) => {
+                menu::draw_dim();
+                menu::draw_headline("PAUSED", COL_FOREGROUND);
+            },
+            AppState::MatchOver(winner) => {
+                menu::draw_dim();
+                let text = format!("{:?} wins", winner).to_uppercase();
+                menu::draw_headline(&text, winner.color());
+            },
+            AppState::ConnectionLost => {
+                menu::draw_dim();
+                menu::draw_headline("CONNECTION LOST", COL_FOREGROUND);
+            },
+        }
+
+        self.menu.draw(&self.menu_items());
+    }
+}
+
+/// Picks the neighbour of `current` in a list of choices, wrapping around at both ends
+fn step<T: Copy + PartialEq>(choices: &[T], current: T, direction: i32) -> T {
+    let index = choices.iter().position(|&choice| choice == current).unwrap_or(0) as i32;
+    let len = choices.len() as i32;
+    choices[((index + direction).rem_euclid(len)) as usize]
+}
@@ -0,0 +1,237 @@
+use std::fs;
+use std::path::Path;
+
+use macroquad::prelude::KeyCode;
+use serde::{Deserialize, Serialize};
+
+use crate::controller::Controls;
+use crate::Side;
+
+/// File the bindings are saved to whenever they are changed in the game
+pub const DEFAULT_BINDINGS_PATH: &str = "rusted_pong_bindings.toml";
+
+/// Keys a paddle can be bound to. Escape and P are missing on purpose, they pause the game,
+/// and so is Backspace which clears a binding.
+const BINDABLE_KEYS: [KeyCode; 105] = [
+    KeyCode::Space, KeyCode::Apostrophe, KeyCode::Comma, KeyCode::Minus, KeyCode::Period, KeyCode::Slash,
+    KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+    KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    KeyCode::Semicolon, KeyCode::Equal,
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+    KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N,
+    KeyCode::O, KeyCode::Q, KeyCode::R, KeyCode::S, KeyCode::T, KeyCode::U,
+    KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    KeyCode::LeftBracket, KeyCode::Backslash, KeyCode::RightBracket, KeyCode::GraveAccent,
+    KeyCode::Enter, KeyCode::Tab, KeyCode::Insert, KeyCode::Delete,
+    KeyCode::Right, KeyCode::Left, KeyCode::Down, KeyCode::Up,
+    KeyCode::PageUp, KeyCode::PageDown, KeyCode::Home, KeyCode::End,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::Kp0, KeyCode::Kp1, KeyCode::Kp2, KeyCode::Kp3, KeyCode::Kp4,
+    KeyCode::Kp5, KeyCode::Kp6, KeyCode::Kp7, KeyCode::Kp8, KeyCode::Kp9,
+    KeyCode::KpDecimal, KeyCode::KpDivide, KeyCode::KpMultiply, KeyCode::KpSubtract,
+    KeyCode::KpAdd, KeyCode::KpEnter, KeyCode::KpEqual,
+    KeyCode::LeftShift, KeyCode::LeftControl, KeyCode::LeftAlt, KeyCode::LeftSuper,
+    KeyCode::RightShift, KeyCode::RightControl, KeyCode::RightAlt, KeyCode::RightSuper,
+    KeyCode::Menu, KeyCode::CapsLock, KeyCode::ScrollLock, KeyCode::NumLock,
+    KeyCode::PrintScreen, KeyCode::Pause, KeyCode::World1, KeyCode::World2, KeyCode::Back,
+];
+
+/// Direction a paddle is told to move by a binding
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+    Up,
+    Down,
+}
+
+/// Which keys and gamepads steer which paddle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bindings {
+    pub left: Controls,
+    pub right: Controls,
+    /// Only used in four player matches, `up` moves left and `down` moves right
+    pub top: Controls,
+    pub bottom: Controls,
+    /// Stick movement below this fraction of the full range is ignored
+    pub dead_zone: f32,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            left: Controls {
+                up: vec![KeyCode::W],
+                down: vec![KeyCode::S],
+                gamepad: Some(0),
+            },
+            right: Controls {
+                up: vec![KeyCode::Up],
+                down: vec![KeyCode::Down],
+                gamepad: Some(1),
+            },
+            top: Controls {
+                up: vec![KeyCode::J],
+                down: vec![KeyCode::L],
+                gamepad: Some(2),
+            },
+            bottom: Controls {
+                up: vec![KeyCode::Kp4],
+                down: vec![KeyCode::Kp6],
+                gamepad: Some(3),
+            },
+            dead_zone: 0.15,
+        }
+    }
+}
+
+impl Bindings {
+    pub fn load(path: &Path) -> Result<Bindings, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read bindings {}: {}", path.display(), err))?;
+        Bindings::parse(&text).map_err(|err| format!("Invalid bindings {}: {}", path.display(), err))
+    }
+
+    /// Loads the bindings saved last time, falling back to the defaults if that fails
+    pub fn load_or_default(path: &Path) -> Bindings {
+        if !path.exists() {
+            return Bindings::default();
+        }
+        Bindings::load(path).unwrap_or_else(|message| {
+            eprintln!("{}, using the default controls", message);
+            Bindings::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_text())
+            .map_err(|err| format!("Could not write bindings {}: {}", path.display(), err))
+    }
+
+    pub fn parse(text: &str) -> Result<Bindings, String> {
+        let bindings: Bindings = toml::from_str(text).map_err(|err| err.to_string())?;
+        if !(0. ..1.).contains(&bindings.dead_zone) {
+            return Err(format!("dead_zone must be at least 0 and below 1, got {}", bindings.dead_zone));
+        }
+        Ok(bindings)
+    }
+
+    pub fn to_text(&self) -> String {
+        toml::to_string(self).expect("bindings can always be written as TOML")
+    }
+
+    pub fn controls(&self, side: Side) -> &Controls {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+            Side::Top => &self.top,
+            Side::Bottom => &self.bottom,
+        }
+    }
+
+    pub fn controls_mut(&mut self, side: Side) -> &mut Controls {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+            Side::Top => &mut self.top,
+            Side::Bottom => &mut self.bottom,
+        }
+    }
+
+    fn keys_mut(&mut self, side: Side, action: Action) -> &mut Vec<KeyCode> {
+        let controls = self.controls_mut(side);
+        match action {
+            Action::Up => &mut controls.up,
+            Action::Down => &mut controls.down,
+        }
+    }
+
+    /// Adds a key to an action or removes it if it was bound already.
+    /// A key only ever does one thing, so it is taken away from every other action.
+    /// Returns false for keys that can't be bound.
+    pub fn toggle_key(&mut self, side: Side, action: Action, key: KeyCode) -> bool {
+        if !BINDABLE_KEYS.contains(&key) {
+            return false;
+        }
+
+        let was_bound = self.keys_mut(side, action).contains(&key);
+        for other_side in Side::ALL {
+            for other_action in [Action::Up, Action::Down] {
+                self.keys_mut(other_side, other_action).retain(|&bound| bound != key);
+            }
+        }
+        if !was_bound {
+            self.keys_mut(side, action).push(key);
+        }
+        true
+    }
+
+    pub fn clear(&mut self, side: Side, action: Action) -> () {
+        self.keys_mut(side, action).clear();
+    }
+}
+
+pub fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    BINDABLE_KEYS.into_iter().find(|&key| key_name(key) == name)
+}
+
+/// Stores keys by their name so the file stays readable, used with `#[serde(with)]`
+pub mod key_list {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(keys: &[KeyCode], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(keys.iter().map(|&key| key_name(key)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<KeyCode>, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        names.iter()
+            .map(|name| key_from_name(name).ok_or_else(|| serde::de::Error::custom(format!("unknown key '{}'", name))))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_survive_a_round_trip() {
+        let bindings = Bindings::default();
+        assert_eq!(Bindings::parse(&bindings.to_text()).unwrap(), bindings);
+    }
+
+    #[test]
+    fn several_keys_per_action_are_read() {
+        let text = "[left]\nup = [\"W\", \"Key1\"]\ndown = [\"S\"]\n";
+        let bindings = Bindings::parse(text).unwrap();
+        assert_eq!(bindings.left.up, vec![KeyCode::W, KeyCode::Key1]);
+        assert_eq!(bindings.left.gamepad, None);
+        assert_eq!(bindings.right, Bindings::default().right);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let err = Bindings::parse("[left]\nup = [\"Escape\"]\ndown = []\n").unwrap_err();
+        assert!(err.contains("unknown key 'Escape'"), "{}", err);
+    }
+
+    #[test]
+    fn binding_a_key_takes_it_from_the_other_action() {
+        let mut bindings = Bindings::default();
+        assert!(bindings.toggle_key(Side::Right, Action::Down, KeyCode::W));
+        assert_eq!(bindings.left.up, Vec::<KeyCode>::new());
+        assert_eq!(bindings.right.down, vec![KeyCode::Down, KeyCode::W]);
+
+        // Pressing it again unbinds it
+        bindings.toggle_key(Side::Right, Action::Down, KeyCode::W);
+        assert_eq!(bindings.right.down, vec![KeyCode::Down]);
+
+        assert!(!bindings.toggle_key(Side::Left, Action::Up, KeyCode::Escape));
+    }
+}
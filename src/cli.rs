@@ -0,0 +1,138 @@
+use std::path::PathBuf;
+
+use crate::ai::Difficulty;
+use crate::rules::{EdgeRule, MatchRules, ServeRule};
+
+const USAGE: &str = "\
+Usage: rusty_ball [OPTIONS]
+
+Options:
+  --cpu <easy|normal|hard>  Play alone against the CPU, it takes the right paddle or all three others
+  --first-to <points>       Points needed to win a set (default 11)
+  --no-win-by-two           End a set as soon as somebody reaches the points
+  --time-limit <seconds>    Give the match to the leader once the time is up
+  --best-of <sets>          Play a match over several sets (odd number, default 1)
+  --four-players            Put a paddle on every side of the arena
+  --lives <n>               Goals a player can concede with four players (default 5)
+  --serve <rule>            Who gets the ball after a goal: alternate, loser (default) or winner
+  --edge-hits <rule>        Ball hitting the end of a paddle: bounce (default) or miss
+  --level <file>            Play two player matches on the layout of a level file
+  --record <file>           Record the inputs of every match into a replay file
+  --replay <file>           Play a recorded replay file
+  --verify-replay <file>    Play a replay without a window and check it ends as recorded
+  --config <file>           Load gameplay values from this file (default rusted_pong.toml)
+  --hot-reload              Apply changes to the config file while playing
+  --volume <percent>        Volume of the sound effects (default 80)
+  --mute                    Start without sound
+  --reduce-motion           Start without particles, ball trail and screen shake
+  --export-history <file>   Write the match history to a .csv or .json file and quit
+  --host                    Wait for a player on the local network
+  --join <address>          Play with the host at this address (port is optional)
+  --port <port>             Port to host on (default 7878)
+  --net-lag <ms>            Delay sent packets on purpose to test network games
+  --net-loss <percent>      Drop sent packets on purpose to test network games
+  -h, --help                Print this help";
+
+/// Settings given on the command line
+#[derive(Debug, Default)]
+pub struct Options {
+    pub cpu: Option<Difficulty>,
+    pub rules: MatchRules,
+    pub level: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    /// Only check the replay instead of showing it
+    pub verify_replay: bool,
+    /// Config file given on the command line, otherwise the default one is used if it exists
+    pub config: Option<PathBuf>,
+    pub hot_reload: bool,
+    /// Percent
+    pub volume: Option<f32>,
+    pub mute: bool,
+    pub reduce_motion: bool,
+    /// Only write the match history to this file instead of playing
+    pub export_history: Option<PathBuf>,
+    pub host: bool,
+    pub join: Option<String>,
+    pub port: Option<u16>,
+    /// Bad connection to simulate, milliseconds of lag and percent of lost packets
+    pub net_lag: f64,
+    pub net_loss: f32,
+}
+
+/// Parses the command line arguments (without the program name).
+/// On error or when asking for help the text to print is returned.
+pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cpu" => {
+                let value = args.next().ok_or("--cpu needs a difficulty")?;
+                let difficulty = Difficulty::from_name(&value)
+                    .ok_or(format!("Unknown difficulty '{}', expected easy, normal or hard", value))?;
+                options.cpu = Some(difficulty);
+            },
+            "--first-to" => options.rules.points_to_win = parse_value(&arg, args.next())?,
+            "--no-win-by-two" => options.rules.win_by_two = false,
+            "--time-limit" => options.rules.time_limit = Some(parse_value(&arg, args.next())?),
+            "--best-of" => options.rules.best_of = parse_value(&arg, args.next())?,
+            "--four-players" => options.rules.four_players = true,
+            "--lives" => options.rules.lives = parse_value(&arg, args.next())?,
+            "--serve" => {
+                let value = args.next().ok_or("--serve needs a rule")?;
+                options.rules.serve = ServeRule::from_name(&value)
+                    .ok_or(format!("Unknown serve rule '{}', expected alternate, loser or winner", value))?;
+            },
+            "--edge-hits" => {
+                let value = args.next().ok_or("--edge-hits needs a rule")?;
+                options.rules.edge_hits = EdgeRule::from_name(&value)
+                    .ok_or(format!("Unknown edge rule '{}', expected bounce or miss", value))?;
+            },
+            "--level" => options.level = Some(parse_value(&arg, args.next())?),
+            "--record" => options.record = Some(parse_value(&arg, args.next())?),
+            "--replay" => options.replay = Some(parse_value(&arg, args.next())?),
+            "--verify-replay" => {
+                options.replay = Some(parse_value(&arg, args.next())?);
+                options.verify_replay = true;
+            },
+            "--config" => options.config = Some(parse_value(&arg, args.next())?),
+            "--hot-reload" => options.hot_reload = true,
+            "--volume" => options.volume = Some(parse_value(&arg, args.next())?),
+            "--mute" => options.mute = true,
+            "--reduce-motion" => options.reduce_motion = true,
+            "--export-history" => options.export_history = Some(parse_value(&arg, args.next())?),
+            "--host" => options.host = true,
+            "--join" => options.join = Some(parse_value(&arg, args.next())?),
+            "--port" => options.port = Some(parse_value(&arg, args.next())?),
+            "--net-lag" => options.net_lag = parse_value(&arg, args.next())?,
+            "--net-loss" => options.net_loss = parse_value(&arg, args.next())?,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ => return Err(format!("Unknown argument '{}'\n\n{}", arg, USAGE)),
+        }
+    }
+
+    if !(options.net_lag >= 0. && (0. ..=100.).contains(&options.net_loss)) {
+        return Err("--net-lag must not be negative and --net-loss must be between 0 and 100".to_string());
+    }
+    if options.volume.is_some_and(|volume| !(0. ..=100.).contains(&volume)) {
+        return Err("--volume must be between 0 and 100".to_string());
+    }
+    if options.host && options.join.is_some() {
+        return Err("Use either --host or --join, not both".to_string());
+    }
+    if options.rules.four_players && (options.host || options.join.is_some()) {
+        return Err("Network games are for two players only".to_string());
+    }
+    if options.level.is_some() && (options.rules.four_players || options.host || options.join.is_some()) {
+        return Err("Levels are for local two player matches only".to_string());
+    }
+    options.rules.validate()?;
+    Ok(options)
+}
+
+/// Parses the value following an option
+fn parse_value<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or(format!("{} needs a value", option))?;
+    value.parse().map_err(|_| format!("Invalid value '{}' for {}", value, option))
+}
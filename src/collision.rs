@@ -0,0 +1,356 @@
+use macroquad::math::Vec2;
+
+use crate::trig;
+
+/// First point of contact found by a swept collision test
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Contact {
+    /// Fraction of the movement (0..=1) after which the contact happens
+    pub time: f32,
+    /// Normal of the surface that was hit, pointing towards the moving rectangle
+    pub normal: Vec2,
+    /// How far the shapes already overlap along the normal, 0 unless they touch at time 0
+    pub depth: f32,
+}
+
+/// Swept AABB test of a rectangle moving by `delta` against a static rectangle.
+/// The static rectangle is grown by the size of the moving one, so the problem
+/// becomes a ray (the moving center) against a box.
+/// Rectangles that already overlap report a contact at time 0 on the axis of least penetration.
+pub fn sweep_aabb(pos: Vec2, size: Vec2, delta: Vec2, other_pos: Vec2, other_size: Vec2) -> Option<Contact> {
+    let half = (size + other_size) / 2.;
+    let min = other_pos - half;
+    let max = other_pos + half;
+
+    // Already overlapping, push out along the shallowest axis
+    let penetration = half - (pos - other_pos).abs();
+    if penetration.x > 0. && penetration.y > 0. {
+        let (normal, depth) = if penetration.x < penetration.y {
+            (Vec2 { x: if pos.x < other_pos.x { -1. } else { 1. }, y: 0. }, penetration.x)
+        } else {
+            (Vec2 { x: 0., y: if pos.y < other_pos.y { -1. } else { 1. } }, penetration.y)
+        };
+        return Some(Contact { time: 0., normal: normal, depth: depth });
+    }
+
+    // Entry and exit times of the ray along one axis
+    let slab = |origin: f32, delta: f32, min: f32, max: f32| -> Option<(f32, f32)> {
+        if delta == 0. {
+            // Not moving along this axis, either always inside the slab or never
+            if origin < min || origin > max {
+                None
+            } else {
+                Some((f32::NEG_INFINITY, f32::INFINITY))
+            }
+        } else {
+            let t1 = (min - origin) / delta;
+            let t2 = (max - origin) / delta;
+            Some((t1.min(t2), t1.max(t2)))
+        }
+    };
+    let (entry_x, exit_x) = slab(pos.x, delta.x, min.x, max.x)?;
+    let (entry_y, exit_y) = slab(pos.y, delta.y, min.y, max.y)?;
+
+    let entry = entry_x.max(entry_y);
+    let exit = exit_x.min(exit_y);
+    if entry > exit || !(0. ..=1.).contains(&entry) {
+        return None;
+    }
+
+    // The axis that was entered last is the one whose face got hit
+    let normal = if entry_x > entry_y {
+        Vec2 { x: -delta.x.signum(), y: 0. }
+    } else {
+        Vec2 { x: 0., y: -delta.y.signum() }
+    };
+    Some(Contact { time: entry, normal: normal, depth: 0. })
+}
+
+/// Shape an entity collides as
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Collider {
+    Aabb { center: Vec2, half: Vec2 },
+    Circle { center: Vec2, radius: f32 },
+    /// Box turned clockwise by `angle` radians around its center
+    Obb { center: Vec2, half: Vec2, angle: f32 },
+}
+
+impl Collider {
+    /// The same shape moved by `offset`
+    pub fn moved(&self, offset: Vec2) -> Collider {
+        match *self {
+            Collider::Aabb { center, half } => Collider::Aabb { center: center + offset, half: half },
+            Collider::Circle { center, radius } => Collider::Circle { center: center + offset, radius: radius },
+            Collider::Obb { center, half, angle } => Collider::Obb { center: center + offset, half: half, angle: angle },
+        }
+    }
+
+    /// Smallest box around the shape
+    fn bounds(&self) -> (Vec2, Vec2) {
+        match *self {
+            Collider::Aabb { center, half } => (center, half),
+            Collider::Circle { center, radius } => (center, Vec2::splat(radius)),
+            Collider::Obb { center, half, angle } => {
+                let (sin, cos) = trig::sin_cos(angle);
+                let (sin, cos) = (sin.abs(), cos.abs());
+                (center, Vec2 { x: half.x * cos + half.y * sin, y: half.x * sin + half.y * cos })
+            },
+        }
+    }
+
+    /// Moves this shape along `delta` and returns the first contact with `other` on the way.
+    /// A circle is exact against every shape, boxes against turned boxes use the box around the turned one.
+    pub fn sweep(&self, delta: Vec2, other: &Collider) -> Option<Contact> {
+        match (*self, *other) {
+            (Collider::Circle { center, radius }, Collider::Circle { center: other_center, radius: other_radius }) => {
+                sweep_circle_circle(center, radius, delta, other_center, other_radius)
+            },
+            (Collider::Circle { center, radius }, Collider::Aabb { center: other_center, half }) => {
+                sweep_circle_box(center, radius, delta, other_center, half, 0.)
+            },
+            (Collider::Circle { center, radius }, Collider::Obb { center: other_center, half, angle }) => {
+                sweep_circle_box(center, radius, delta, other_center, half, angle)
+            },
+            // A box running into a circle is the circle running into the box the other way round
+            (_, Collider::Circle { .. }) => other.sweep(-delta, self)
+                .map(|contact| Contact { normal: -contact.normal, ..contact }),
+            _ => {
+                let ((center, half), (other_center, other_half)) = (self.bounds(), other.bounds());
+                sweep_aabb(center, half * 2., delta, other_center, other_half * 2.)
+            },
+        }
+    }
+
+    /// Whether the shapes overlap, boxes already do when they touch
+    pub fn overlaps(&self, other: &Collider) -> bool {
+        match (*self, *other) {
+            (Collider::Aabb { center, half }, Collider::Aabb { center: other_center, half: other_half }) => {
+                // Easy to calculate the case of NOT colliding in case of rectangles
+                let (a_top_l, a_bot_r) = (center - half, center + half);
+                let (b_top_l, b_bot_r) = (other_center - other_half, other_center + other_half);
+                !(a_top_l.x > b_bot_r.x || a_top_l.y > b_bot_r.y ||
+                    b_top_l.x > a_bot_r.x || b_top_l.y > a_bot_r.y)
+            },
+            _ => self.sweep(Vec2::ZERO, other).is_some(),
+        }
+    }
+}
+
+/// Swept test of a circle moving by `delta` against a resting circle.
+/// Growing the resting circle by the moving radius turns it into a ray against a circle.
+/// Circles that already overlap report a contact at time 0, pushing apart along the line between the centers.
+pub fn sweep_circle_circle(pos: Vec2, radius: f32, delta: Vec2, center: Vec2, other_radius: f32) -> Option<Contact> {
+    let reach = radius + other_radius;
+    let offset = pos - center;
+
+    // Already overlapping, straight on top of each other pushes up
+    if offset.length_squared() < reach * reach {
+        let normal = offset.try_normalize().unwrap_or(Vec2 { x: 0., y: -1. });
+        return Some(Contact { time: 0., normal: normal, depth: reach - offset.length() });
+    }
+
+    // |offset + delta * t| = reach
+    let a = delta.dot(delta);
+    if a == 0. {
+        return None;
+    }
+    let b = 2. * offset.dot(delta);
+    let c = offset.dot(offset) - reach * reach;
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    let time = (-b - discriminant.sqrt()) / (2. * a);
+    if !(0. ..=1.).contains(&time) {
+        return None;
+    }
+    Some(Contact { time: time, normal: (offset + delta * time) / reach, depth: 0. })
+}
+
+/// Swept test of a circle moving by `delta` against a resting box turned by `angle` radians around its center.
+/// In the box's own frame this is a ray against the box grown by the radius, which has round corners.
+pub fn sweep_circle_box(pos: Vec2, radius: f32, delta: Vec2, center: Vec2, half: Vec2, angle: f32) -> Option<Contact> {
+    let turn = trig::from_angle(angle);
+    let to_local = |v: Vec2| Vec2 { x: turn.x, y: -turn.y }.rotate(v);
+    let from_local = |contact: Contact| Contact { normal: turn.rotate(contact.normal), ..contact };
+    let pos = to_local(pos - center);
+    let delta = to_local(delta);
+
+    // Already overlapping, push out from the closest point of the box
+    let closest = pos.clamp(-half, half);
+    let offset = pos - closest;
+    if offset.length_squared() < radius * radius {
+        let (normal, depth) = if offset == Vec2::ZERO {
+            // The center is inside the box, out along the shallowest axis
+            let penetration = half - pos.abs();
+            if penetration.x < penetration.y {
+                (Vec2 { x: pos.x.signum(), y: 0. }, penetration.x + radius)
+            } else {
+                (Vec2 { x: 0., y: pos.y.signum() }, penetration.y + radius)
+            }
+        } else {
+            (offset.normalize(), radius - offset.length())
+        };
+        return Some(from_local(Contact { time: 0., normal: normal, depth: depth }));
+    }
+
+    // Beyond both edges the grown box is rounded around the corner
+    let grown = half + Vec2::splat(radius);
+    let beyond_corner = |point: Vec2| point.x.abs() > half.x && point.y.abs() > half.y;
+    let corner_of = |point: Vec2| Vec2 { x: half.x.copysign(point.x), y: half.y.copysign(point.y) };
+    // Starting next to a corner, inside the grown box but outside its rounding, the corner is all there is to hit
+    if beyond_corner(pos) && pos.x.abs() <= grown.x && pos.y.abs() <= grown.y {
+        return sweep_circle_circle(pos, radius, delta, corner_of(pos), 0.).map(from_local);
+    }
+    let (time, normal) = ray_box(pos, delta, grown)?;
+    let hit = pos + delta * time;
+    if beyond_corner(hit) {
+        return sweep_circle_circle(pos, radius, delta, corner_of(hit), 0.).map(from_local);
+    }
+    Some(from_local(Contact { time: time, normal: normal, depth: 0. }))
+}
+
+/// Entry time (0..=1) of a ray into a box centered on the origin and the normal of the face it enters through
+fn ray_box(origin: Vec2, delta: Vec2, half: Vec2) -> Option<(f32, Vec2)> {
+    let slab = |origin: f32, delta: f32, half: f32| -> Option<(f32, f32)> {
+        if delta == 0. {
+            // Not moving along this axis, either always inside the slab or never
+            match origin.abs() <= half {
+                true  => Some((f32::NEG_INFINITY, f32::INFINITY)),
+                false => None,
+            }
+        } else {
+            let t1 = (-half - origin) / delta;
+            let t2 = (half - origin) / delta;
+            Some((t1.min(t2), t1.max(t2)))
+        }
+    };
+    let (entry_x, exit_x) = slab(origin.x, delta.x, half.x)?;
+    let (entry_y, exit_y) = slab(origin.y, delta.y, half.y)?;
+
+    let entry = entry_x.max(entry_y);
+    if entry > exit_x.min(exit_y) || !(0. ..=1.).contains(&entry) {
+        return None;
+    }
+    let normal = if entry_x > entry_y {
+        Vec2 { x: -delta.x.signum(), y: 0. }
+    } else {
+        Vec2 { x: 0., y: -delta.y.signum() }
+    };
+    Some((entry, normal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec2, b: Vec2) -> () {
+        assert!((a - b).length() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn sweep_reports_time_and_normal() {
+        let size = Vec2 { x: 10., y: 10. };
+        let wall_pos = Vec2 { x: 100., y: 0. };
+        let wall_size = Vec2 { x: 20., y: 200. };
+
+        // Moving right into the left face of the wall
+        let contact = sweep_aabb(Vec2::ZERO, size, Vec2 { x: 200., y: 0. }, wall_pos, wall_size).unwrap();
+        assert_eq!(contact.time, 85. / 200.);
+        assert_eq!(contact.normal, Vec2 { x: -1., y: 0. });
+
+        // Falling short of the wall or moving away from it
+        assert!(sweep_aabb(Vec2::ZERO, size, Vec2 { x: 50., y: 0. }, wall_pos, wall_size).is_none());
+        assert!(sweep_aabb(Vec2::ZERO, size, Vec2 { x: -200., y: 0. }, wall_pos, wall_size).is_none());
+
+        // Dropping onto the top face
+        let contact = sweep_aabb(Vec2 { x: 100., y: -200. }, size, Vec2 { x: 0., y: 200. }, wall_pos, wall_size).unwrap();
+        assert_eq!(contact.time, 95. / 200.);
+        assert_eq!(contact.normal, Vec2 { x: 0., y: -1. });
+    }
+
+    #[test]
+    fn circles_meet_where_their_edges_touch() {
+        let contact = sweep_circle_circle(Vec2::ZERO, 5., Vec2::new(100., 0.), Vec2::new(60., 0.), 15.).unwrap();
+        assert!((contact.time - 0.4).abs() < 1e-5);
+        assert_close(contact.normal, Vec2::new(-1., 0.));
+
+        // Glancing off the top of the circle
+        let contact = sweep_circle_circle(Vec2::new(0., -15.), 5., Vec2::new(100., 0.), Vec2::new(60., 0.), 15.).unwrap();
+        assert!(contact.normal.y < -0.7 && contact.normal.x < 0.);
+        assert!(sweep_circle_circle(Vec2::new(0., -21.), 5., Vec2::new(100., 0.), Vec2::new(60., 0.), 15.).is_none());
+        assert!(sweep_circle_circle(Vec2::ZERO, 5., Vec2::new(30., 0.), Vec2::new(60., 0.), 15.).is_none());
+
+        let overlap = sweep_circle_circle(Vec2::new(50., 0.), 5., Vec2::ZERO, Vec2::new(60., 0.), 15.).unwrap();
+        assert_eq!((overlap.time, overlap.depth), (0., 10.));
+        assert_close(overlap.normal, Vec2::new(-1., 0.));
+    }
+
+    #[test]
+    fn boxes_are_hit_on_faces_and_round_corners() {
+        let half = Vec2::new(20., 10.);
+        let center = Vec2::new(100., 0.);
+        let contact = sweep_circle_box(Vec2::ZERO, 5., Vec2::new(100., 0.), center, half, 0.).unwrap();
+        assert!((contact.time - 0.75).abs() < 1e-5);
+        assert_close(contact.normal, Vec2::new(-1., 0.));
+
+        // Just past the corner the normal points diagonally out of it
+        let contact = sweep_circle_box(Vec2::new(0., -13.), 5., Vec2::new(100., 0.), center, half, 0.).unwrap();
+        assert!(contact.normal.x < 0. && contact.normal.y < 0.);
+        assert!(sweep_circle_box(Vec2::new(0., -16.), 5., Vec2::new(100., 0.), center, half, 0.).is_none());
+    }
+
+    #[test]
+    fn circles_starting_next_to_a_corner_still_hit_it() {
+        // Inside the grown box but outside its round corner, skimming along the end of a paddle
+        let (pos, half) = (Vec2::new(-14., -54.), Vec2::new(10., 50.));
+        for delta in [Vec2::new(20., 0.), Vec2::new(2., 0.)] {
+            let contact = sweep_circle_box(pos, 5., delta, Vec2::ZERO, half, 0.).unwrap();
+            assert!((contact.time * delta.x - 1.).abs() < 1e-4);
+            assert!(contact.normal.x < 0. && contact.normal.y < 0.);
+        }
+        assert!(sweep_circle_box(pos, 5., Vec2::new(0.5, 0.), Vec2::ZERO, half, 0.).is_none());
+        assert!(sweep_circle_box(pos, 5., Vec2::new(-20., 0.), Vec2::ZERO, half, 0.).is_none());
+    }
+
+    #[test]
+    fn turned_boxes_turn_their_normals() {
+        // A square turned by 45 degrees shows a corner to the left, hitting it head on comes straight back
+        let half = Vec2::splat(10.);
+        let angle = std::f32::consts::FRAC_PI_4;
+        let contact = sweep_circle_box(Vec2::ZERO, 5., Vec2::new(100., 0.), Vec2::new(100., 0.), half, angle).unwrap();
+        assert!((contact.time - (100. - 5. - 10. * 2f32.sqrt()) / 100.).abs() < 1e-4);
+        assert_close(contact.normal, Vec2::new(-1., 0.));
+
+        // Its upper left face faces up and to the left
+        let contact = sweep_circle_box(Vec2::new(95., -40.), 2., Vec2::new(0., 40.), Vec2::new(100., 0.), half, angle).unwrap();
+        assert_close(contact.normal, Vec2::new(-1., -1.).normalize());
+
+        let inside = sweep_circle_box(Vec2::new(100., 9.), 5., Vec2::ZERO, Vec2::new(100., 0.), Vec2::new(20., 10.), 0.).unwrap();
+        assert_eq!(inside.time, 0.);
+        assert_close(inside.normal, Vec2::new(0., 1.));
+        assert!((inside.depth - 6.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn colliders_pick_the_test_for_their_shapes() {
+        let ball = Collider::Circle { center: Vec2::ZERO, radius: 5. };
+        let paddle = Collider::Aabb { center: Vec2::new(100., 0.), half: Vec2::new(10., 50.) };
+
+        // A box moving into a circle sees the normal from the circle's side
+        let contact = ball.sweep(Vec2::new(100., 0.), &paddle).unwrap();
+        let mirrored = paddle.sweep(Vec2::new(-100., 0.), &ball).unwrap();
+        assert_eq!(mirrored.time, contact.time);
+        assert_eq!(mirrored.normal, -contact.normal);
+
+        // Clipping the corner of the paddle deflects diagonally
+        let corner = ball.moved(Vec2::new(0., -53.)).sweep(Vec2::new(100., 0.), &paddle).unwrap();
+        assert!(corner.normal.x < 0. && corner.normal.y < 0.);
+
+        assert!(paddle.overlaps(&Collider::Aabb { center: Vec2::new(120., 0.), half: Vec2::splat(10.) }));
+        assert!(!ball.overlaps(&paddle));
+        assert!(ball.moved(Vec2::new(88., 0.)).overlaps(&paddle));
+        let turned = Collider::Obb { center: Vec2::new(100., 0.), half: Vec2::new(10., 50.), angle: std::f32::consts::FRAC_PI_2 };
+        assert!(turned.overlaps(&Collider::Aabb { center: Vec2::new(150., 0.), half: Vec2::splat(5.) }));
+    }
+}
@@ -0,0 +1,318 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::TICK_RATE;
+
+/// File that is loaded when no other config file is given
+pub const DEFAULT_CONFIG_PATH: &str = "rusted_pong.toml";
+
+/// Seconds between two checks of the config file for changes
+const WATCH_INTERVAL: f64 = 0.5;
+
+/// Gameplay tuning values, every value that is missing in the file keeps its default.
+/// Lengths are in arena units, speeds in arena units per tick. The window scales the arena to fit.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub player: PlayerConfig,
+    pub ball: BallConfig,
+    pub arena: ArenaConfig,
+    pub timer: TimerConfig,
+    pub power_ups: PowerUpConfig,
+    pub multi_ball: MultiBallConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlayerConfig {
+    pub speed: f32,
+    /// How much hitting the ball off center makes it curve
+    pub curve_strength: f32,
+    /// Extra speed when hitting the ball with the center of the paddle
+    pub straight_strength: f32,
+    pub width: f32,
+    pub height: f32,
+    /// Distance between the paddle and the edge of the screen
+    pub edge_distance: f32,
+    /// How much moving the paddle while hitting the ball makes it spin
+    pub spin_strength: f32,
+}
+
+impl Default for PlayerConfig {
+    fn default() -> Self {
+        PlayerConfig {
+            speed: 7.0,
+            curve_strength: 1.7,
+            straight_strength: 1.05,
+            width: 20.,
+            height: 150.,
+            edge_distance: 100.,
+            spin_strength: 0.0015,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BallConfig {
+    pub size: f32,
+    /// Fraction of the speed kept when bouncing
+    pub bounciness: f32,
+    pub x_speed_limit: f32,
+    pub serve_speed: f32,
+    /// Fraction of the spin lost per second
+    pub spin_decay: f32,
+    /// How much spin pushes the ball sideways when it bounces off a wall
+    pub spin_bounce: f32,
+}
+
+impl Default for BallConfig {
+    fn default() -> Self {
+        BallConfig {
+            size: 10.,
+            bounciness: 0.9,
+            x_speed_limit: 8.,
+            serve_speed: 3.,
+            spin_decay: 1.5,
+            spin_bounce: 20.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArenaConfig {
+    /// Size of the playing field. The window shows all of it scaled to fit,
+    /// so resizing the window never changes the game.
+    pub width: f32,
+    pub height: f32,
+    pub goal_width: f32,
+    pub wall_height: f32,
+}
+
+impl Default for ArenaConfig {
+    fn default() -> Self {
+        ArenaConfig {
+            width: 1280.,
+            height: 720.,
+            goal_width: 130.,
+            wall_height: 80.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimerConfig {
+    /// Seconds to wait after a goal and again before the serve, the serve counts down 3-2-1
+    pub duration: f32,
+}
+
+impl Default for TimerConfig {
+    fn default() -> Self {
+        TimerConfig {
+            // 50 ticks, as long as the wait has always been
+            duration: 50. / TICK_RATE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerUpConfig {
+    pub enabled: bool,
+    /// Seconds until the next pickup appears, the actual wait is up to twice as long
+    pub spawn_interval: f32,
+    /// Seconds an effect lasts
+    pub duration: f32,
+}
+
+impl Default for PowerUpConfig {
+    fn default() -> Self {
+        PowerUpConfig {
+            enabled: false,
+            spawn_interval: 6.0,
+            duration: 8.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MultiBallConfig {
+    /// Serves an extra ball every `spawn_interval` seconds
+    pub enabled: bool,
+    /// Most balls in play at the same time, also limits balls from power-ups
+    pub max_balls: u32,
+    pub spawn_interval: f32,
+}
+
+impl Default for MultiBallConfig {
+    fn default() -> Self {
+        MultiBallConfig {
+            enabled: false,
+            max_balls: 3,
+            spawn_interval: 10.0,
+        }
+    }
+}
+
+impl Config {
+    /// Loads and validates a config file
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read config {}: {}", path.display(), err))?;
+        Config::parse(&text).map_err(|err| format!("Invalid config {}: {}", path.display(), err))
+    }
+
+    /// Loads the default config file if there is one, otherwise uses the built in values
+    pub fn load_default() -> Result<Config, String> {
+        let path = Path::new(DEFAULT_CONFIG_PATH);
+        if path.exists() {
+            Config::load(path)
+        } else {
+            Ok(Config::default())
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(text).map_err(|err| err.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn to_text(&self) -> String {
+        toml::to_string(self).expect("config can always be written as TOML")
+    }
+
+    /// Rejects values that would break the game instead of just making it weird
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = [
+            ("player.speed", self.player.speed),
+            ("player.width", self.player.width),
+            ("player.height", self.player.height),
+            ("ball.size", self.ball.size),
+            ("ball.x_speed_limit", self.ball.x_speed_limit),
+            ("ball.serve_speed", self.ball.serve_speed),
+            ("arena.width", self.arena.width),
+            ("arena.height", self.arena.height),
+            ("arena.goal_width", self.arena.goal_width),
+            ("arena.wall_height", self.arena.wall_height),
+            ("power_ups.spawn_interval", self.power_ups.spawn_interval),
+            ("power_ups.duration", self.power_ups.duration),
+            ("multi_ball.spawn_interval", self.multi_ball.spawn_interval),
+        ];
+        let not_negative = [
+            ("player.curve_strength", self.player.curve_strength),
+            ("player.straight_strength", self.player.straight_strength),
+            ("player.edge_distance", self.player.edge_distance),
+            ("player.spin_strength", self.player.spin_strength),
+            ("ball.spin_decay", self.ball.spin_decay),
+            ("ball.spin_bounce", self.ball.spin_bounce),
+            ("timer.duration", self.timer.duration),
+        ];
+
+        for (name, value) in positive {
+            if !(value.is_finite() && value > 0.) {
+                return Err(format!("{} must be greater than 0, got {}", name, value));
+            }
+        }
+        for (name, value) in not_negative {
+            if !(value.is_finite() && value >= 0.) {
+                return Err(format!("{} must not be negative, got {}", name, value));
+            }
+        }
+        if !(self.ball.bounciness > 0. && self.ball.bounciness <= 1.) {
+            return Err(format!("ball.bounciness must be between 0 (exclusive) and 1, got {}", self.ball.bounciness));
+        }
+        if self.multi_ball.max_balls == 0 {
+            return Err("multi_ball.max_balls must be at least 1".to_string());
+        }
+        if self.ball.serve_speed > self.ball.x_speed_limit {
+            return Err(format!(
+                "ball.serve_speed ({}) must not be above ball.x_speed_limit ({})",
+                self.ball.serve_speed, self.ball.x_speed_limit
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Keeps an eye on a config file and hands out the new config whenever it was saved
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_check: f64,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        ConfigWatcher {
+            modified: ConfigWatcher::modified(&path),
+            path: path,
+            last_check: 0.,
+        }
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|meta| meta.modified()).ok()
+    }
+
+    /// Returns the reloaded config (or why it couldn't be loaded) if the file changed
+    pub fn poll(&mut self, now: f64) -> Option<Result<Config, String>> {
+        if now - self.last_check < WATCH_INTERVAL {
+            return None;
+        }
+        self.last_check = now;
+
+        let modified = ConfigWatcher::modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(Config::load(&self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_file_gives_defaults() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn partial_file_keeps_other_defaults() {
+        let config = Config::parse("[ball]\nbounciness = 0.5\n").unwrap();
+        assert_eq!(config.ball.bounciness, 0.5);
+        assert_eq!(config.ball.size, BallConfig::default().size);
+        assert_eq!(config.player, PlayerConfig::default());
+    }
+
+    #[test]
+    fn round_trip_is_exact() {
+        let mut config = Config::default();
+        config.player.curve_strength = 1.234_567_8;
+        assert_eq!(Config::parse(&config.to_text()).unwrap(), config);
+    }
+
+    #[test]
+    fn bad_values_are_explained() {
+        let err = Config::parse("[player]\nspeed = -3.0\n").unwrap_err();
+        assert_eq!(err, "player.speed must be greater than 0, got -3");
+
+        let err = Config::parse("[ball]\nbounciness = 1.5\n").unwrap_err();
+        assert!(err.contains("ball.bounciness"));
+    }
+
+    #[test]
+    fn typos_are_rejected() {
+        let err = Config::parse("[player]\nsped = 3.0\n").unwrap_err();
+        assert!(err.contains("unknown field `sped`"), "{}", err);
+    }
+}
@@ -0,0 +1,190 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::gamepad::{self, Gamepads};
+use crate::{Orientation, PaddleInput, Side, Simulation};
+
+/// Decides how a paddle moves each tick, no matter if a human or the computer is playing
+pub trait PaddleController {
+    fn control(&mut self, sim: &Simulation, side: Side) -> PaddleInput;
+}
+
+// ===================== KEYBOARD =====================
+/// Everything one player can steer a paddle with, any of the keys of an action will do.
+/// Paddles at the top and bottom move left on `up` and right on `down`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Controls {
+    #[serde(with = "crate::bindings::key_list")]
+    pub up: Vec<KeyCode>,
+    #[serde(with = "crate::bindings::key_list")]
+    pub down: Vec<KeyCode>,
+    /// Gamepad in the order they were connected, 0 is the first one
+    pub gamepad: Option<usize>,
+}
+
+pub struct KeyboardController {
+    controls: Controls,
+}
+
+impl KeyboardController {
+    pub fn new(controls: Controls) -> Self {
+        KeyboardController {
+            controls: controls,
+        }
+    }
+}
+
+impl PaddleController for KeyboardController {
+    fn control(&mut self, _sim: &Simulation, _side: Side) -> PaddleInput {
+        PaddleInput::from_buttons(
+            self.controls.up.iter().any(|&key| is_key_down(key)),
+            self.controls.down.iter().any(|&key| is_key_down(key))
+        )
+    }
+}
+// --------------------- KEYBOARD ---------------------
+
+// ===================== GAMEPAD =====================
+/// Left stick moves the paddle as fast as it is pushed, the D-pad moves it at full speed.
+/// Paddles at the top and bottom listen to the horizontal directions.
+pub struct GamepadController {
+    gamepads: Rc<RefCell<Gamepads>>,
+    /// Gamepad to listen to, `None` listens to all of them
+    index: Option<usize>,
+    dead_zone: f32,
+}
+
+impl GamepadController {
+    pub fn new(gamepads: Rc<RefCell<Gamepads>>, index: Option<usize>, dead_zone: f32) -> Self {
+        GamepadController {
+            gamepads: gamepads,
+            index: index,
+            dead_zone: dead_zone,
+        }
+    }
+}
+
+impl PaddleController for GamepadController {
+    fn control(&mut self, _sim: &Simulation, side: Side) -> PaddleInput {
+        let states = self.gamepads.borrow_mut().states();
+        let axis: f32 = states.iter().enumerate()
+            .filter(|(i, _)| self.index.is_none_or(|index| index == *i))
+            .map(|(_, pad)| match side.orientation() {
+                Orientation::Vertical => {
+                    gamepad::stick_to_axis(pad.stick_y, self.dead_zone)
+                        + PaddleInput::from_buttons(pad.dpad_up, pad.dpad_down).axis
+                },
+                // Right is positive on both the stick and the paddle
+                Orientation::Horizontal => {
+                    -gamepad::stick_to_axis(pad.stick_x, self.dead_zone)
+                        + PaddleInput::from_buttons(pad.dpad_left, pad.dpad_right).axis
+                },
+            })
+            .sum();
+        PaddleInput {
+            axis: axis.clamp(-1., 1.),
+        }
+    }
+}
+// --------------------- GAMEPAD ---------------------
+
+// ===================== TOUCH =====================
+/// Touching the upper half of the screen moves up, the lower half moves down.
+/// Paddles at the top and bottom move towards the left or right half instead.
+pub struct TouchController {
+    /// Half of the screen that belongs to this player, `None` uses the whole screen.
+    /// With four players each one gets the quarter next to their edge.
+    area: Option<Side>,
+}
+
+impl TouchController {
+    pub fn new(area: Option<Side>) -> Self {
+        TouchController {
+            area: area,
+        }
+    }
+}
+
+impl PaddleController for TouchController {
+    fn control(&mut self, sim: &Simulation, side: Side) -> PaddleInput {
+        let mut move_up = false;
+        let mut move_down = false;
+
+        for touch in touches_local() {
+            // Check if the touch is on the current player's side
+            let pos = touch.position;
+            let in_area = match self.area {
+                Some(area) if sim.rules.four_players => nearest_edge(pos) == area,
+                Some(Side::Left)  => pos.x < 0.,
+                Some(Side::Right) => pos.x > 0.,
+                Some(_) => false,
+                None => true,
+            };
+            let towards = match side.orientation() {
+                Orientation::Vertical   => pos.y,
+                Orientation::Horizontal => pos.x,
+            };
+            if in_area {
+                if towards < 0. {
+                    move_up = true;
+                } else {
+                    move_down = true;
+                }
+            }
+        }
+
+        PaddleInput::from_buttons(move_up, move_down)
+    }
+}
+
+/// Edge of the screen closest to a position in local touch coordinates
+fn nearest_edge(pos: Vec2) -> Side {
+    match (pos.x.abs() > pos.y.abs(), pos.x < 0., pos.y < 0.) {
+        (true, true, _)   => Side::Left,
+        (true, false, _)  => Side::Right,
+        (false, _, true)  => Side::Top,
+        (false, _, false) => Side::Bottom,
+    }
+}
+// --------------------- TOUCH ---------------------
+
+// ===================== REMOTE =====================
+/// Paddle steered somewhere else, like by the other player of a network game
+pub struct RemoteController;
+
+impl PaddleController for RemoteController {
+    fn control(&mut self, _sim: &Simulation, _side: Side) -> PaddleInput {
+        PaddleInput::default()
+    }
+}
+// --------------------- REMOTE ---------------------
+
+// ===================== COMBINED =====================
+/// Lets several input devices steer the same paddle, opposing inputs cancel each other out
+pub struct CombinedController {
+    controllers: Vec<Box<dyn PaddleController>>,
+}
+
+impl CombinedController {
+    pub fn new(controllers: Vec<Box<dyn PaddleController>>) -> Self {
+        CombinedController {
+            controllers: controllers,
+        }
+    }
+}
+
+impl PaddleController for CombinedController {
+    fn control(&mut self, sim: &Simulation, side: Side) -> PaddleInput {
+        let axis: f32 = self.controllers.iter_mut()
+            .map(|controller| controller.control(sim, side).axis)
+            .sum();
+        PaddleInput {
+            axis: axis.clamp(-1., 1.),
+        }
+    }
+}
+// --------------------- COMBINED ---------------------
@@ -0,0 +1,421 @@
+use std::fs;
+use std::path::PathBuf;
+
+use macroquad::prelude::*;
+
+use crate::config::Config;
+use crate::level::{Block, GoalArea, Level};
+use crate::menu;
+use crate::viewport::Viewport;
+use crate::{Entity, Side, COL_FOREGROUND, COL_LETTERBOX};
+
+/// Where the editor saves unless a level file was given on the command line
+pub const DEFAULT_LEVEL_PATH: &str = "rusted_pong_level.toml";
+/// Arena units between the lines of the snap grid
+const GRID_SIZE: f32 = 20.;
+/// Edge length of the square on the bottom right corner of the selection that resizes it
+const HANDLE_SIZE: f32 = 20.;
+
+/// Kind of piece a click on empty space places, picked with the number keys
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Tool {
+    Wall,
+    Obstacle,
+    Goal(Side),
+}
+
+const TOOLS: [(KeyCode, Tool); 4] = [
+    (KeyCode::Key1, Tool::Wall),
+    (KeyCode::Key2, Tool::Obstacle),
+    (KeyCode::Key3, Tool::Goal(Side::Left)),
+    (KeyCode::Key4, Tool::Goal(Side::Right)),
+];
+
+impl Tool {
+    /// Size of a freshly placed piece
+    fn size(&self) -> Vec2 {
+        match self {
+            Tool::Wall     => Vec2 { x: 200., y: 40. },
+            Tool::Obstacle => Vec2 { x: 40., y: 120. },
+            Tool::Goal(_)  => Vec2 { x: 60., y: 200. },
+        }
+    }
+}
+
+/// Piece of the level by its index in the list it belongs to
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Piece {
+    Wall(usize),
+    Obstacle(usize),
+    Goal(usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Drag {
+    /// `grab` is where the piece is held, relative to its center
+    Move { grab: Vec2 },
+    /// Pulls the bottom right corner, the top left one stays where it is
+    Resize,
+}
+
+/// What the editor wants the app to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditorRequest {
+    /// Play a match on the level, it was checked already
+    TestPlay(Level),
+    Leave,
+}
+
+/// Places, moves, resizes and deletes the walls, obstacles and goals of a level with the mouse.
+/// The paddle tracks are shown but stay where they are.
+pub struct Editor {
+    pub level: Level,
+    pub config: Config,
+    path: PathBuf,
+    tool: Tool,
+    selected: Option<Piece>,
+    drag: Option<Drag>,
+    snap: bool,
+    /// Outcome of the last save or test play, errors explain why the level can't be played
+    status: Option<Result<String, String>>,
+}
+
+impl Editor {
+    pub fn new(level: Level, config: Config, path: PathBuf) -> Self {
+        Editor {
+            level: level,
+            config: config,
+            path: path,
+            tool: Tool::Wall,
+            selected: None,
+            drag: None,
+            snap: true,
+            status: None,
+        }
+    }
+
+    fn viewport(&self) -> Viewport {
+        Viewport::fit(&self.level.arena(), Vec2 { x: screen_width(), y: screen_height() })
+    }
+
+    fn snap(&self, pos: Vec2) -> Vec2 {
+        match self.snap {
+            true  => (pos / GRID_SIZE).round() * GRID_SIZE,
+            false => pos,
+        }
+    }
+
+    // --------------------- PIECES ---------------------
+    fn block(&self, piece: Piece) -> Block {
+        match piece {
+            Piece::Wall(i)     => self.level.walls[i],
+            Piece::Obstacle(i) => self.level.obstacles[i],
+            Piece::Goal(i)     => self.level.goals[i].block(),
+        }
+    }
+
+    fn set_block(&mut self, piece: Piece, block: Block) -> () {
+        match piece {
+            Piece::Wall(i)     => self.level.walls[i] = block,
+            Piece::Obstacle(i) => self.level.obstacles[i] = block,
+            Piece::Goal(i)     => {
+                let goal = &mut self.level.goals[i];
+                *goal = GoalArea { side: goal.side, x: block.x, y: block.y, width: block.width, height: block.height };
+            },
+        }
+    }
+
+    /// Every piece with the entity the game would make of it, in drawing order
+    fn entities(&self) -> Vec<(Piece, Box<dyn Entity>)> {
+        let pieces = (0..self.level.walls.len()).map(Piece::Wall)
+            .chain((0..self.level.obstacles.len()).map(Piece::Obstacle))
+            .chain((0..self.level.goals.len()).map(Piece::Goal));
+        let entities = self.level.walls().into_iter().map(|wall| Box::new(wall) as Box<dyn Entity>)
+            .chain(self.level.goals().into_iter().map(|goal| Box::new(goal) as Box<dyn Entity>));
+        pieces.zip(entities).collect()
+    }
+
+    /// Topmost piece at a point of the arena
+    fn piece_at(&self, pos: Vec2) -> Option<Piece> {
+        self.entities().into_iter().rev()
+            .find(|(_, entity)| {
+                let diff = (pos - entity.get_pos()).abs();
+                diff.x <= entity.get_size().x / 2. && diff.y <= entity.get_size().y / 2.
+            })
+            .map(|(piece, _)| piece)
+    }
+
+    /// Whether a point is on the resize handle of the selected piece
+    fn on_handle(&self, pos: Vec2) -> bool {
+        match self.selected {
+            Some(piece) => {
+                let block = self.block(piece);
+                let corner = block.center() + block.size() / 2.;
+                (pos - corner).abs().max_element() <= HANDLE_SIZE / 2.
+            },
+            None => false,
+        }
+    }
+
+    // --------------------- EDITING ---------------------
+    /// Mouse button went down at `pos`: grabs the handle or a piece, or places a new one
+    pub fn press(&mut self, pos: Vec2) -> () {
+        self.status = None;
+        if self.on_handle(pos) {
+            self.drag = Some(Drag::Resize);
+            return;
+        }
+        let piece = match self.piece_at(pos) {
+            Some(piece) => piece,
+            None => self.place(self.snap(pos)),
+        };
+        self.selected = Some(piece);
+        self.drag = Some(Drag::Move { grab: pos - self.block(piece).center() });
+    }
+
+    /// Mouse moved to `pos` while the button is held
+    pub fn drag_to(&mut self, pos: Vec2) -> () {
+        let (piece, drag) = match (self.selected, self.drag) {
+            (Some(piece), Some(drag)) => (piece, drag),
+            _ => return,
+        };
+        let block = self.block(piece);
+        let (center, size) = match drag {
+            Drag::Move { grab } => (self.snap(pos - grab), block.size()),
+            Drag::Resize => {
+                let top_left = block.center() - block.size() / 2.;
+                let size = (self.snap(pos) - top_left).max(Vec2::splat(GRID_SIZE));
+                (top_left + size / 2., size)
+            },
+        };
+        self.set_block(piece, Block { x: center.x, y: center.y, width: size.x, height: size.y });
+    }
+
+    pub fn release(&mut self) -> () {
+        self.drag = None;
+    }
+
+    /// Adds a piece of the current tool centered on `pos`
+    fn place(&mut self, pos: Vec2) -> Piece {
+        let size = self.tool.size();
+        let block = Block { x: pos.x, y: pos.y, width: size.x, height: size.y };
+        match self.tool {
+            Tool::Wall => {
+                self.level.walls.push(block);
+                Piece::Wall(self.level.walls.len() - 1)
+            },
+            Tool::Obstacle => {
+                self.level.obstacles.push(block);
+                Piece::Obstacle(self.level.obstacles.len() - 1)
+            },
+            Tool::Goal(side) => {
+                self.level.goals.push(GoalArea { side: side, x: pos.x, y: pos.y, width: size.x, height: size.y });
+                Piece::Goal(self.level.goals.len() - 1)
+            },
+        }
+    }
+
+    pub fn delete(&mut self, piece: Piece) -> () {
+        match piece {
+            Piece::Wall(i)     => { self.level.walls.remove(i); },
+            Piece::Obstacle(i) => { self.level.obstacles.remove(i); },
+            Piece::Goal(i)     => { self.level.goals.remove(i); },
+        }
+        self.selected = None;
+        self.drag = None;
+        self.status = None;
+    }
+
+    /// Writes the level to its file, only if it can be played
+    pub fn save(&mut self) -> () {
+        let result = self.level.validate(&self.config)
+            .and_then(|()| fs::write(&self.path, self.level.to_text())
+                .map_err(|err| format!("Could not save {}: {}", self.path.display(), err)));
+        self.status = Some(result.map(|()| format!("Saved to {}", self.path.display())));
+    }
+
+    /// The level to play, if it can be played
+    fn test_play(&mut self) -> Option<EditorRequest> {
+        match self.level.validate(&self.config) {
+            Ok(()) => Some(EditorRequest::TestPlay(self.level.clone())),
+            Err(message) => {
+                self.status = Some(Err(message));
+                None
+            },
+        }
+    }
+
+    // --------------------- INPUT ---------------------
+    pub fn update(&mut self) -> Option<EditorRequest> {
+        if is_key_pressed(KeyCode::Escape) {
+            self.release();
+            return Some(EditorRequest::Leave);
+        }
+        if is_key_pressed(KeyCode::T) || is_key_pressed(KeyCode::Enter) {
+            self.release();
+            return self.test_play();
+        }
+        if is_key_pressed(KeyCode::S) {
+            self.save();
+        }
+        if is_key_pressed(KeyCode::G) {
+            self.snap = !self.snap;
+        }
+        if let Some(&(_, tool)) = TOOLS.iter().find(|(key, _)| is_key_pressed(*key)) {
+            self.tool = tool;
+        }
+        if is_key_pressed(KeyCode::Delete) || is_key_pressed(KeyCode::Backspace) {
+            if let Some(piece) = self.selected {
+                self.delete(piece);
+            }
+        }
+
+        let pos = self.viewport().arena_pos(Vec2::from(mouse_position()));
+        if is_mouse_button_pressed(MouseButton::Left) {
+            self.press(pos);
+        } else if is_mouse_button_down(MouseButton::Left) {
+            self.drag_to(pos);
+        } else {
+            self.release();
+        }
+        if is_mouse_button_pressed(MouseButton::Right) {
+            if let Some(piece) = self.piece_at(pos) {
+                self.delete(piece);
+            }
+        }
+        None
+    }
+
+    // --------------------- DRAWING ---------------------
+    pub fn draw(&self) -> () {
+        let arena = self.level.arena();
+        let viewport = self.viewport();
+        set_camera(&viewport.camera(&arena, Vec2::ZERO));
+
+        if self.snap {
+            let color = Color { a: 0.08, ..COL_FOREGROUND };
+            for column in 0..=(arena.width / GRID_SIZE) as i32 {
+                let x = column as f32 * GRID_SIZE;
+                draw_line(x, 0., x, arena.height, 1., color);
+            }
+            for row in 0..=(arena.height / GRID_SIZE) as i32 {
+                let y = row as f32 * GRID_SIZE;
+                draw_line(0., y, arena.width, y, 1., color);
+            }
+        }
+
+        // Paddles show where their tracks are, at both ends
+        for player in self.level.players(&self.config) {
+            player.draw(0.);
+            for track in self.level.tracks.iter().filter(|track| track.side == player.side) {
+                let reach = (track.length - player.get_size().y) / 2.;
+                draw_line(track.x, track.y - reach, track.x, track.y + reach, 2., player.get_stroke_color());
+            }
+        }
+        for (_, entity) in self.entities() {
+            entity.draw(0.);
+        }
+        for mover in self.level.movers() {
+            mover.draw(0.);
+        }
+
+        if let Some(piece) = self.selected {
+            let block = self.block(piece);
+            let top_left = block.center() - block.size() / 2.;
+            draw_rectangle_lines(top_left.x, top_left.y, block.width, block.height, 4., WHITE);
+            let corner = top_left + block.size();
+            draw_rectangle(corner.x - HANDLE_SIZE / 2., corner.y - HANDLE_SIZE / 2., HANDLE_SIZE, HANDLE_SIZE, WHITE);
+        }
+
+        set_default_camera();
+        viewport.draw_bars(COL_LETTERBOX);
+
+        let tool = match self.tool {
+            Tool::Wall => "wall".to_string(),
+            Tool::Obstacle => "obstacle".to_string(),
+            Tool::Goal(side) => format!("{:?} goal", side),
+        };
+        let grid = if self.snap { "on" } else { "off" };
+        menu::draw_hint(&format!("Placing {}, grid {}", tool, grid), 0.06, WHITE);
+        match &self.status {
+            Some(Ok(message)) => menu::draw_hint(message, 0.95, GREEN),
+            Some(Err(message)) => menu::draw_hint(message, 0.95, RED),
+            None => menu::draw_hint("1-4 pick, G grid, Del delete, S save, T test, Esc back", 0.95, COL_FOREGROUND),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor() -> Editor {
+        let config = Config::default();
+        Editor::new(Level::classic(&config), config, PathBuf::from(DEFAULT_LEVEL_PATH))
+    }
+
+    #[test]
+    fn clicking_empty_space_places_a_piece_on_the_grid() {
+        let mut editor = editor();
+        editor.tool = Tool::Obstacle;
+        editor.press(Vec2::new(647., 352.));
+        editor.release();
+        assert_eq!(editor.selected, Some(Piece::Obstacle(0)));
+        assert_eq!(editor.level.obstacles, vec![Block { x: 640., y: 360., width: 40., height: 120. }]);
+
+        // Without the grid it goes exactly where the click was
+        editor.snap = false;
+        editor.tool = Tool::Goal(Side::Right);
+        editor.press(Vec2::new(901., 303.));
+        assert_eq!(editor.level.goals[2].side, Side::Right);
+        assert_eq!(editor.level.goals[2].block().center(), Vec2::new(901., 303.));
+    }
+
+    #[test]
+    fn pieces_are_dragged_resized_and_deleted() {
+        let mut editor = editor();
+        editor.tool = Tool::Obstacle;
+        editor.press(Vec2::new(640., 360.));
+        editor.release();
+
+        // Held off center, the piece keeps that offset and snaps
+        editor.press(Vec2::new(650., 400.));
+        editor.drag_to(Vec2::new(755., 205.));
+        editor.release();
+        assert_eq!(editor.level.obstacles[0].center(), Vec2::new(740., 160.));
+
+        // The top left corner stays, sizes never get smaller than a grid cell
+        editor.press(Vec2::new(760., 220.));
+        editor.drag_to(Vec2::new(841., 278.));
+        assert_eq!(editor.level.obstacles[0], Block { x: 780., y: 190., width: 120., height: 180. });
+        editor.drag_to(Vec2::new(0., 0.));
+        assert_eq!(editor.level.obstacles[0].size(), Vec2::splat(GRID_SIZE));
+        editor.release();
+
+        editor.delete(Piece::Obstacle(0));
+        assert!(editor.level.obstacles.is_empty());
+        assert_eq!(editor.selected, None);
+    }
+
+    #[test]
+    fn only_playable_levels_are_saved_or_tested() {
+        let dir = std::env::temp_dir().join(format!("rusted_pong_editor_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut editor = editor();
+        editor.path = dir.join("level.toml");
+
+        let goal = editor.level.goals[0];
+        editor.delete(Piece::Goal(0));
+        editor.save();
+        assert!(matches!(&editor.status, Some(Err(message)) if message.contains("Left has no goal")));
+        assert_eq!(editor.test_play(), None);
+        assert!(!editor.path.exists());
+
+        editor.level.goals.push(goal);
+        editor.save();
+        assert!(matches!(editor.status, Some(Ok(_))));
+        assert_eq!(Level::load(&editor.path, &editor.config), Ok(editor.level.clone()));
+        assert_eq!(editor.test_play(), Some(EditorRequest::TestPlay(editor.level.clone())));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+use crate::rng::Rng;
+use crate::{lerp_color, Arena, Ball, Side, SimEvent, COL_FOREGROUND};
+
+/// Positions kept for the trail of each ball, one per tick
+const TRAIL_LENGTH: usize = 10;
+/// Oldest particles are dropped beyond this many
+const MAX_PARTICLES: usize = 600;
+/// Arena units the screen moves at most when shaking at full strength
+const MAX_SHAKE: f32 = 14.;
+/// Trauma the shake loses per second, a full shake calms down within 1 / SHAKE_DECAY seconds
+const SHAKE_DECAY: f32 = 1.6;
+const SPARKS_PER_HIT: usize = 12;
+const SPARKS_PER_BOUNCE: usize = 6;
+const CONFETTI_PER_GOAL: usize = 60;
+/// Arena units per second squared pulling confetti down
+const CONFETTI_GRAVITY: f32 = 500.;
+
+/// Which effects are shown, players sensitive to motion can turn each of them off
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EffectSettings {
+    pub particles: bool,
+    pub trail: bool,
+    /// 0 turns screen shake off, 1 is full strength
+    pub shake: f32,
+}
+
+impl Default for EffectSettings {
+    fn default() -> Self {
+        EffectSettings {
+            particles: true,
+            trail: true,
+            shake: 0.5,
+        }
+    }
+}
+
+impl EffectSettings {
+    /// Nothing moves unless the game needs it to
+    pub fn reduced_motion() -> Self {
+        EffectSettings {
+            particles: false,
+            trail: false,
+            shake: 0.,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Particle {
+    pos: Vec2,
+    /// Arena units per second
+    vel: Vec2,
+    size: f32,
+    color: Color,
+    /// Seconds left until it's gone
+    life: f32,
+    lifetime: f32,
+    gravity: f32,
+    /// Fraction of the speed lost per second
+    drag: f32,
+}
+
+/// Sparks, confetti, ball trails and screen shake. They only watch the simulation and
+/// use their own random numbers, so matches play out the same with or without them.
+pub struct Effects {
+    pub settings: EffectSettings,
+    particles: Vec<Particle>,
+    /// Last positions of every ball, oldest first
+    trails: Vec<VecDeque<Vec2>>,
+    /// How much the screen shakes, from 0 to 1
+    trauma: f32,
+    shake_offset: Vec2,
+    rng: Rng,
+}
+
+impl Effects {
+    pub fn new(settings: EffectSettings) -> Self {
+        Effects {
+            settings: settings,
+            particles: Vec::new(),
+            trails: Vec::new(),
+            trauma: 0.,
+            shake_offset: Vec2::ZERO,
+            rng: Rng::new((miniquad::date::now() * 1000.) as u64),
+        }
+    }
+
+    /// Spawns whatever the events of a frame call for
+    pub fn react(&mut self, events: &[SimEvent], arena: &Arena) -> () {
+        for event in events {
+            match *event {
+                SimEvent::Hit(side, pos, ..) => self.burst_sparks(pos, side.color(), SPARKS_PER_HIT),
+                SimEvent::Bounce(pos) => self.burst_sparks(pos, COL_FOREGROUND, SPARKS_PER_BOUNCE),
+                SimEvent::Goal(scorer) => {
+                    self.burst_confetti(scorer, arena);
+                    self.shake(1.);
+                },
+                SimEvent::Point(scorer) => {
+                    self.burst_confetti(scorer, arena);
+                    self.shake(0.5);
+                },
+                SimEvent::LifeLost(_) | SimEvent::Eliminated(_) => self.shake(1.),
+                SimEvent::Serve(_) | SimEvent::MatchOver(_) | SimEvent::PowerUp(..) | SimEvent::Countdown(_) => (),
+            }
+        }
+    }
+
+    /// Remembers where the balls are after a tick. A ball that jumped, e.g. back to the
+    /// center, starts a new trail instead of drawing a line across the field.
+    pub fn record_tick(&mut self, balls: &[Ball]) -> () {
+        self.trails.resize_with(balls.len(), VecDeque::new);
+        for (trail, ball) in self.trails.iter_mut().zip(balls) {
+            if trail.back() != Some(&ball.prev_pos) || ball.vel == Vec2::ZERO {
+                trail.clear();
+            }
+            trail.push_back(ball.pos);
+            if trail.len() > TRAIL_LENGTH {
+                trail.pop_front();
+            }
+        }
+    }
+
+    /// Moves the particles and calms the shake down, `dt` is in seconds
+    pub fn update(&mut self, dt: f32) -> () {
+        for particle in &mut self.particles {
+            particle.vel.y += particle.gravity * dt;
+            particle.vel *= (1. - particle.drag * dt).max(0.);
+            particle.pos += particle.vel * dt;
+            particle.life -= dt;
+        }
+        self.particles.retain(|particle| particle.life > 0.);
+
+        self.trauma = (self.trauma - SHAKE_DECAY * dt).max(0.);
+        // Squaring makes small shakes gentle and big ones punchy
+        let amount = MAX_SHAKE * self.settings.shake * self.trauma * self.trauma;
+        self.shake_offset = Vec2 {
+            x: self.rng.range(-amount, amount),
+            y: self.rng.range(-amount, amount),
+        };
+    }
+
+    /// Where the whole field is moved to this frame
+    pub fn shake_offset(&self) -> Vec2 {
+        self.shake_offset
+    }
+
+    /// Fading copies of every ball where it just was, ghost balls leave none. `round` draws them as circles
+    pub fn draw_trails(&self, balls: &[Ball], round: bool) -> () {
+        if !self.settings.trail {
+            return;
+        }
+        for (trail, ball) in self.trails.iter().zip(balls).filter(|(_, ball)| !ball.ghost) {
+            for (i, pos) in trail.iter().enumerate() {
+                let progress = (i + 1) as f32 / (TRAIL_LENGTH + 1) as f32;
+                let size = ball.size * (0.4 + 0.6 * progress);
+                let mut color = ball.color;
+                color.a *= 0.35 * progress;
+                match round {
+                    true  => draw_circle(pos.x, pos.y, size.x / 2., color),
+                    false => draw_rectangle(pos.x - size.x / 2., pos.y - size.y / 2., size.x, size.y, color),
+                }
+            }
+        }
+    }
+
+    pub fn draw_particles(&self) -> () {
+        for particle in &self.particles {
+            let mut color = particle.color;
+            color.a *= particle.life / particle.lifetime;
+            let size = particle.size;
+            draw_rectangle(particle.pos.x - size / 2., particle.pos.y - size / 2., size, size, color);
+        }
+    }
+
+    fn shake(&mut self, amount: f32) -> () {
+        if self.settings.shake > 0. {
+            self.trauma = self.trauma.max(amount);
+        }
+    }
+
+    /// Short lived sparks flying off in every direction
+    fn burst_sparks(&mut self, pos: Vec2, color: Color, count: usize) -> () {
+        for _ in 0..count {
+            let angle = self.rng.range(0., std::f32::consts::TAU);
+            let speed = self.rng.range(80., 320.);
+            let lifetime = self.rng.range(0.15, 0.4);
+            let particle = Particle {
+                pos: pos,
+                vel: Vec2::from_angle(angle) * speed,
+                size: self.rng.range(2., 5.),
+                color: lerp_color(&color, &WHITE, self.rng.range(0., 0.6)),
+                life: lifetime,
+                lifetime: lifetime,
+                gravity: 0.,
+                drag: 4.,
+            };
+            self.spawn(particle);
+        }
+    }
+
+    /// Confetti in the scorer's color, thrown up from their half of the field
+    fn burst_confetti(&mut self, scorer: Side, arena: &Arena) -> () {
+        let offset = match scorer {
+            Side::Left   => Vec2 { x: -0.2, y: 0. },
+            Side::Right  => Vec2 { x: 0.2, y: 0. },
+            Side::Top    => Vec2 { x: 0., y: -0.2 },
+            Side::Bottom => Vec2 { x: 0., y: 0.2 },
+        };
+        let origin = arena.center() + offset * Vec2 { x: arena.width, y: arena.height };
+        for _ in 0..CONFETTI_PER_GOAL {
+            let angle = self.rng.range(-0.8, 0.8) - std::f32::consts::FRAC_PI_2;
+            let speed = self.rng.range(200., 550.);
+            let lifetime = self.rng.range(1., 1.8);
+            let particle = Particle {
+                pos: origin,
+                vel: Vec2::from_angle(angle) * speed,
+                size: self.rng.range(4., 9.),
+                color: lerp_color(&scorer.color(), &WHITE, self.rng.range(0., 0.3)),
+                life: lifetime,
+                lifetime: lifetime,
+                gravity: CONFETTI_GRAVITY,
+                drag: 1.,
+            };
+            self.spawn(particle);
+        }
+    }
+
+    fn spawn(&mut self, particle: Particle) -> () {
+        if !self.settings.particles {
+            return;
+        }
+        if self.particles.len() >= MAX_PARTICLES {
+            self.particles.remove(0);
+        }
+        self.particles.push(particle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn arena() -> Arena {
+        Arena::new(800., 600.)
+    }
+
+    #[test]
+    fn particles_fade_away() {
+        let mut effects = Effects::new(EffectSettings::default());
+        effects.react(&[SimEvent::Hit(Side::Left, Vec2::new(100., 100.), 5., 0.), SimEvent::Bounce(Vec2::ZERO)], &arena());
+        assert_eq!(effects.particles.len(), SPARKS_PER_HIT + SPARKS_PER_BOUNCE);
+        effects.update(0.2);
+        assert!(effects.particles.iter().all(|particle| particle.pos != Vec2::new(100., 100.) && particle.pos != Vec2::ZERO));
+        effects.update(0.3);
+        assert!(effects.particles.is_empty());
+    }
+
+    #[test]
+    fn confetti_has_the_scorers_color() {
+        let mut effects = Effects::new(EffectSettings::default());
+        effects.react(&[SimEvent::Goal(Side::Right)], &arena());
+        assert_eq!(effects.particles.len(), CONFETTI_PER_GOAL);
+        for particle in &effects.particles {
+            // Lightened towards white, but never away from the side's color
+            assert!(particle.color.r >= Side::Right.color().r && particle.color.g >= Side::Right.color().g);
+            assert!(particle.pos.x > arena().center().x);
+        }
+    }
+
+    #[test]
+    fn shake_calms_down_and_can_be_turned_off() {
+        let mut effects = Effects::new(EffectSettings::default());
+        effects.react(&[SimEvent::Goal(Side::Left)], &arena());
+        effects.update(0.01);
+        assert!(effects.shake_offset().length() <= MAX_SHAKE * effects.settings.shake * 2f32.sqrt());
+        effects.update(1.);
+        assert_eq!(effects.shake_offset(), Vec2::ZERO);
+
+        let mut calm = Effects::new(EffectSettings::reduced_motion());
+        calm.react(&[SimEvent::Goal(Side::Left), SimEvent::Bounce(Vec2::ZERO)], &arena());
+        calm.update(0.01);
+        assert_eq!(calm.shake_offset(), Vec2::ZERO);
+        assert!(calm.particles.is_empty());
+    }
+
+    #[test]
+    fn trail_restarts_when_the_ball_jumps() {
+        let mut effects = Effects::new(EffectSettings::default());
+        let mut ball = Ball::new(&arena(), &Config::default());
+        ball.vel = Vec2::new(5., 0.);
+        for _ in 0..TRAIL_LENGTH + 5 {
+            ball.prev_pos = ball.pos;
+            ball.pos += ball.vel;
+            effects.record_tick(std::slice::from_ref(&ball));
+        }
+        assert_eq!(effects.trails[0].len(), TRAIL_LENGTH);
+        assert_eq!(effects.trails[0].back(), Some(&ball.pos));
+
+        ball.reset(&arena());
+        ball.vel = Vec2::new(5., 0.);
+        ball.pos += ball.vel;
+        effects.record_tick(std::slice::from_ref(&ball));
+        assert_eq!(effects.trails[0].len(), 1);
+    }
+}
@@ -0,0 +1,94 @@
+/// All connected gamepads. Without the `gamepad` feature there never are any.
+pub struct Gamepads {
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+/// State of one gamepad that matters for steering a paddle
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct PadState {
+    /// Vertical position of the left stick, up is positive
+    pub stick_y: f32,
+    /// Horizontal position of the left stick, right is positive
+    pub stick_x: f32,
+    pub dpad_up: bool,
+    pub dpad_down: bool,
+    pub dpad_left: bool,
+    pub dpad_right: bool,
+}
+
+impl Gamepads {
+    #[cfg(feature = "gamepad")]
+    pub fn new() -> Self {
+        let gilrs = match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(err) => {
+                eprintln!("Gamepads are not available: {}", err);
+                None
+            },
+        };
+        Gamepads {
+            gilrs: gilrs,
+        }
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    pub fn new() -> Self {
+        Gamepads {}
+    }
+
+    /// Current state of every connected gamepad, in the order they were connected
+    #[cfg(feature = "gamepad")]
+    pub fn states(&mut self) -> Vec<PadState> {
+        use gilrs::{Axis, Button};
+
+        let gilrs = match &mut self.gilrs {
+            Some(gilrs) => gilrs,
+            None => return Vec::new(),
+        };
+        // Handling the events is what updates the cached state of the pads
+        while gilrs.next_event().is_some() {}
+
+        gilrs.gamepads()
+            .map(|(_, pad)| PadState {
+                stick_y: pad.value(Axis::LeftStickY),
+                stick_x: pad.value(Axis::LeftStickX),
+                dpad_up: pad.is_pressed(Button::DPadUp),
+                dpad_down: pad.is_pressed(Button::DPadDown),
+                dpad_left: pad.is_pressed(Button::DPadLeft),
+                dpad_right: pad.is_pressed(Button::DPadRight),
+            })
+            .collect()
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    pub fn states(&mut self) -> Vec<PadState> {
+        Vec::new()
+    }
+}
+
+/// Maps a stick position to a paddle axis: nothing inside the dead zone, then growing
+/// linearly to full speed so small movements give slow and precise paddles
+pub fn stick_to_axis(stick_y: f32, dead_zone: f32) -> f32 {
+    let amount = stick_y.abs();
+    if amount <= dead_zone {
+        return 0.;
+    }
+    // Sticks point up with positive values, paddles move up with negative ones
+    let scaled = ((amount - dead_zone) / (1. - dead_zone)).min(1.);
+    -scaled * stick_y.signum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stick_is_proportional_outside_the_dead_zone() {
+        assert_eq!(stick_to_axis(0.25, 0.5), 0.);
+        assert_eq!(stick_to_axis(-0.5, 0.5), 0.);
+        assert_eq!(stick_to_axis(0.75, 0.5), -0.5);
+        assert_eq!(stick_to_axis(-1., 0.5), 1.);
+        assert_eq!(stick_to_axis(1.3, 0.5), -1.);
+    }
+}
@@ -0,0 +1,283 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Side, Simulation};
+
+/// File every finished match is added to
+pub const DEFAULT_HISTORY_PATH: &str = "rusted_pong_history.toml";
+
+const MAX_NAME_LENGTH: usize = 16;
+
+/// One paddle of a recorded match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerRecord {
+    pub name: String,
+    /// `Left`, `Right`, `Top` or `Bottom`
+    pub side: String,
+    pub points: u32,
+    pub sets: u32,
+    pub won: bool,
+    pub hits: u32,
+    /// How far off center the ball was hit on average, 0 is always straight and 1 always the edge
+    pub curve_usage: f32,
+}
+
+/// Summary of one finished match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MatchRecord {
+    /// Seconds since 1970 at the end of the match
+    pub played_at: u64,
+    /// Seconds
+    pub duration: f32,
+    pub longest_rally: u32,
+    pub hits: u32,
+    /// Arena units per second
+    pub fastest_ball: f32,
+    pub players: Vec<PlayerRecord>,
+}
+
+impl MatchRecord {
+    /// Sums up a finished match, `names` are indexed by side
+    pub fn new(sim: &Simulation, names: &[String; 4], played_at: u64) -> Self {
+        let sides = match sim.rules.four_players {
+            true  => &Side::ALL[..],
+            false => &Side::ALL[..2],
+        };
+        MatchRecord {
+            played_at: played_at,
+            duration: sim.elapsed,
+            longest_rally: sim.stats.longest_rally,
+            hits: sim.stats.hits,
+            fastest_ball: sim.stats.fastest_ball,
+            players: sides.iter()
+                .map(|&side| PlayerRecord {
+                    name: names[side as usize].clone(),
+                    side: format!("{:?}", side),
+                    points: sim.score.points[side as usize],
+                    sets: sim.score.sets[side as usize],
+                    won: sim.winner == Some(side),
+                    hits: sim.stats.side_hits[side as usize],
+                    curve_usage: sim.stats.curve_usage(side),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Everything a profile did over all recorded matches
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProfileSummary {
+    pub matches: u32,
+    pub wins: u32,
+    pub hits: u32,
+    pub longest_rally: u32,
+    pub fastest_ball: f32,
+    /// Average over all hits
+    pub curve_usage: f32,
+}
+
+/// Player profiles and every match played with this installation
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct History {
+    pub profiles: Vec<String>,
+    pub matches: Vec<MatchRecord>,
+}
+
+impl History {
+    pub fn load(path: &Path) -> Result<History, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read history {}: {}", path.display(), err))?;
+        History::parse(&text).map_err(|err| format!("Invalid history {}: {}", path.display(), err))
+    }
+
+    /// Starts an empty history if there is none yet. A broken file is not replaced,
+    /// so matches are only kept in memory until it is fixed.
+    pub fn load_or_default(path: &Path) -> Result<History, String> {
+        if !path.exists() {
+            return Ok(History::default());
+        }
+        History::load(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_text())
+            .map_err(|err| format!("Could not write history {}: {}", path.display(), err))
+    }
+
+    pub fn parse(text: &str) -> Result<History, String> {
+        toml::from_str(text).map_err(|err| err.to_string())
+    }
+
+    pub fn to_text(&self) -> String {
+        toml::to_string(self).expect("history can always be written as TOML")
+    }
+
+    pub fn add_profile(&mut self, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Profile name is empty".to_string());
+        }
+        if name.chars().count() > MAX_NAME_LENGTH {
+            return Err(format!("Profile names have at most {} characters", MAX_NAME_LENGTH));
+        }
+        if self.profiles.iter().any(|profile| profile.eq_ignore_ascii_case(name)) {
+            return Err(format!("There already is a profile called {}", name));
+        }
+        self.profiles.push(name.to_string());
+        Ok(())
+    }
+
+    pub fn record(&mut self, record: MatchRecord) -> () {
+        self.matches.push(record);
+    }
+
+    pub fn summary(&self, name: &str) -> ProfileSummary {
+        let mut summary = ProfileSummary::default();
+        let mut curve_sum = 0.;
+        for record in &self.matches {
+            for player in record.players.iter().filter(|player| player.name == name) {
+                summary.matches += 1;
+                summary.wins += player.won as u32;
+                summary.hits += player.hits;
+                summary.longest_rally = summary.longest_rally.max(record.longest_rally);
+                summary.fastest_ball = summary.fastest_ball.max(record.fastest_ball);
+                curve_sum += player.curve_usage * player.hits as f32;
+            }
+        }
+        if summary.hits > 0 {
+            summary.curve_usage = curve_sum / summary.hits as f32;
+        }
+        summary
+    }
+
+    /// One line per player and match, the columns of the match repeat on each of its lines
+    pub fn to_csv(&self) -> String {
+        let mut lines = vec![
+            "match,played_at,duration,longest_rally,hits,fastest_ball,side,name,points,sets,won,player_hits,curve_usage".to_string(),
+        ];
+        for (i, record) in self.matches.iter().enumerate() {
+            for player in &record.players {
+                lines.push(format!(
+                    "{},{},{:.1},{},{},{:.0},{},{},{},{},{},{},{:.3}",
+                    i + 1,
+                    record.played_at,
+                    record.duration,
+                    record.longest_rally,
+                    record.hits,
+                    record.fastest_ball,
+                    player.side,
+                    csv_field(&player.name),
+                    player.points,
+                    player.sets,
+                    player.won,
+                    player.hits,
+                    player.curve_usage
+                ));
+            }
+        }
+        lines.join("\n") + "\n"
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("history can always be written as JSON")
+    }
+
+    /// Writes the history as CSV or JSON, depending on the extension of `path`
+    pub fn export(&self, path: &Path) -> Result<(), String> {
+        let text = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => self.to_csv(),
+            Some("json") => self.to_json(),
+            _ => return Err(format!("Can't export to {}, use a .csv or .json file", path.display())),
+        };
+        fs::write(path, text).map_err(|err| format!("Could not write {}: {}", path.display(), err))
+    }
+}
+
+/// Quotes a CSV value if it would break the line apart
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::rules::MatchRules;
+    use crate::{Arena, PaddleInput, TickInput};
+
+    fn names() -> [String; 4] {
+        ["Ada".to_string(), "Bob, Jr.".to_string(), String::new(), String::new()]
+    }
+
+    /// A short match that the right player wins without the left one touching the ball
+    fn finished_match() -> MatchRecord {
+        let rules = MatchRules { points_to_win: 2, win_by_two: false, ..MatchRules::default() };
+        let mut sim = Simulation::new(Arena::new(800., 600.), Config::default(), rules, 0);
+        let input = TickInput { left: PaddleInput::from_buttons(true, false), ..TickInput::default() };
+        while sim.winner.is_none() {
+            sim.update(&input);
+        }
+        MatchRecord::new(&sim, &names(), 1_700_000_000)
+    }
+
+    #[test]
+    fn finished_match_is_summed_up() {
+        let record = finished_match();
+        assert_eq!(record.players.len(), 2);
+        assert_eq!(record.players[1].name, "Bob, Jr.");
+        assert_eq!(record.players[1].points, 2);
+        assert!(record.players[1].won && !record.players[0].won);
+        assert_eq!(record.players[0].hits, 0);
+        assert!(record.duration > 0. && record.fastest_ball > 0.);
+    }
+
+    #[test]
+    fn history_survives_a_round_trip() {
+        let mut history = History::default();
+        history.add_profile("Ada").unwrap();
+        history.record(finished_match());
+        assert_eq!(History::parse(&history.to_text()).unwrap(), history);
+    }
+
+    #[test]
+    fn profile_names_must_be_unique() {
+        let mut history = History::default();
+        assert!(history.add_profile(" Ada ").is_ok());
+        assert!(history.add_profile("ada").is_err());
+        assert!(history.add_profile("   ").is_err());
+        assert!(history.add_profile("A name that is far too long").is_err());
+        assert_eq!(history.profiles, vec!["Ada".to_string()]);
+    }
+
+    #[test]
+    fn summary_counts_wins_per_profile() {
+        let mut history = History::default();
+        history.record(finished_match());
+        history.record(finished_match());
+        let summary = history.summary("Bob, Jr.");
+        assert_eq!((summary.matches, summary.wins), (2, 2));
+        assert_eq!(history.summary("Ada").wins, 0);
+        assert_eq!(history.summary("Nobody"), ProfileSummary::default());
+    }
+
+    #[test]
+    fn exports_quote_names_and_list_every_player() {
+        let mut history = History::default();
+        history.record(finished_match());
+        let csv = history.to_csv();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.contains(",Right,\"Bob, Jr.\",2,0,true,"), "{}", csv);
+
+        let json: serde_json::Value = serde_json::from_str(&history.to_json()).unwrap();
+        assert_eq!(json["matches"][0]["players"][1]["points"], 2);
+    }
+}
@@ -0,0 +1,580 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+
+use macroquad::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::mover::Mover;
+use crate::trig;
+use crate::{lerp_color, Arena, Goal, Player, Side, Wall, WallSide, COL_FOREGROUND};
+
+/// Sides that play on a level, levels are made for two players
+const LEVEL_SIDES: [Side; 2] = [Side::Left, Side::Right];
+/// Longest side an arena may have, checking a level walks through all of it
+const MAX_ARENA_SIZE: f32 = 10_000.;
+/// Grid cells the reachability check visits at most, smaller balls need more of them
+const MAX_GRID_CELLS: usize = 4_000_000;
+
+/// Layout of the field for a two player match: solid walls and obstacles, the goals
+/// and the tracks the paddles move along. Positions are the centers of the rectangles,
+/// in arena units from the top left corner.
+///
+/// ```toml
+/// name = "Pillars"
+/// width = 1280.0
+/// height = 720.0
+///
+/// [[walls]]
+/// x = 640.0
+/// y = 0.0
+/// width = 1920.0
+/// height = 80.0
+///
+/// [[obstacles]]
+/// x = 640.0
+/// y = 200.0
+/// width = 40.0
+/// height = 120.0
+///
+/// [[moving_obstacles]]
+/// shape = "Circle"
+/// x = 440.0
+/// y = 360.0
+/// width = 60.0
+/// height = 60.0
+/// motion = { path = "Sine", dx = 0.0, dy = 200.0, period = 4.0 }
+///
+/// [[goals]]
+/// side = "Left"
+/// x = 0.0
+/// y = 360.0
+/// width = 130.0
+/// height = 640.0
+///
+/// [[tracks]]
+/// side = "Left"
+/// x = 100.0
+/// y = 360.0
+/// length = 640.0
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    pub name: String,
+    pub width: f32,
+    pub height: f32,
+    /// Edges of the field
+    #[serde(default)]
+    pub walls: Vec<Block>,
+    /// Solid blocks inside the field
+    #[serde(default)]
+    pub obstacles: Vec<Block>,
+    #[serde(default)]
+    pub moving_obstacles: Vec<MovingObstacle>,
+    pub goals: Vec<GoalArea>,
+    pub tracks: Vec<Track>,
+}
+
+/// Solid rectangle
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Block {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Rectangle the ball scores in, against `side`
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GoalArea {
+    pub side: Side,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Obstacle that moves along a path around its position and can turn while doing so
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MovingObstacle {
+    pub shape: Shape,
+    pub x: f32,
+    pub y: f32,
+    /// Circles use the width as their diameter
+    pub width: f32,
+    pub height: f32,
+    /// Radians the obstacle starts turned by, clockwise
+    #[serde(default)]
+    pub angle: f32,
+    /// Radians per second the obstacle turns, clockwise
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default)]
+    pub motion: Motion,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Box,
+    Circle,
+}
+
+/// Path of a moving obstacle, offsets from its position that repeat every `period` seconds
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "path", deny_unknown_fields)]
+pub enum Motion {
+    #[default]
+    Still,
+    /// Back and forth at a steady speed between `dx`/`dy` to either side
+    Line { dx: f32, dy: f32, period: f32 },
+    /// Back and forth between `dx`/`dy` to either side, slowing down at the ends
+    Sine { dx: f32, dy: f32, period: f32 },
+    /// Around the position, clockwise
+    Circle { radius: f32, period: f32 },
+}
+
+/// Line a paddle moves along, `length` is how far its whole body can go
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Track {
+    pub side: Side,
+    pub x: f32,
+    pub y: f32,
+    pub length: f32,
+}
+
+impl Block {
+    pub fn center(&self) -> Vec2 {
+        Vec2 { x: self.x, y: self.y }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        Vec2 { x: self.width, y: self.height }
+    }
+
+    /// Rectangles only touching each other don't overlap
+    fn overlaps(&self, other: &Block) -> bool {
+        let diff = (self.center() - other.center()).abs();
+        let reach = (self.size() + other.size()) / 2.;
+        diff.x < reach.x && diff.y < reach.y
+    }
+}
+
+impl GoalArea {
+    pub fn block(&self) -> Block {
+        Block { x: self.x, y: self.y, width: self.width, height: self.height }
+    }
+}
+
+impl MovingObstacle {
+    /// Area the obstacle covers somewhere along its path, however it's turned
+    fn reach(&self) -> Block {
+        let half = self.size() / 2.;
+        let half = match self.shape {
+            Shape::Circle => Vec2::splat(half.x),
+            Shape::Box if self.rotation != 0. => Vec2::splat(half.length()),
+            // Corners of a box turned by a fixed angle
+            Shape::Box => {
+                let (sin, cos) = trig::sin_cos(self.angle);
+                Vec2 {
+                    x: half.x * cos.abs() + half.y * sin.abs(),
+                    y: half.x * sin.abs() + half.y * cos.abs(),
+                }
+            },
+        };
+        let size = (half + self.motion.reach()) * 2.;
+        Block { x: self.x, y: self.y, width: size.x, height: size.y }
+    }
+
+    pub fn center(&self) -> Vec2 {
+        Vec2 { x: self.x, y: self.y }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        match self.shape {
+            Shape::Box    => Vec2 { x: self.width, y: self.height },
+            Shape::Circle => Vec2::splat(self.width),
+        }
+    }
+}
+
+impl Motion {
+    /// Offset from the position after `time` seconds
+    pub fn offset(&self, time: f32) -> Vec2 {
+        let turn = |period: f32| std::f32::consts::TAU * (time / period).fract();
+        match *self {
+            Motion::Still => Vec2::ZERO,
+            Motion::Line { dx, dy, period } => {
+                // Zigzag from 0 up to 1, down to -1 and back to 0
+                let phase = (time / period + 0.25).fract();
+                Vec2 { x: dx, y: dy } * (1. - 4. * (phase - 0.5).abs())
+            },
+            Motion::Sine { dx, dy, period } => Vec2 { x: dx, y: dy } * trig::sin_cos(turn(period)).0,
+            Motion::Circle { radius, period } => trig::from_angle(turn(period)) * radius,
+        }
+    }
+
+    /// Furthest the path goes from the position along each axis
+    fn reach(&self) -> Vec2 {
+        match *self {
+            Motion::Still => Vec2::ZERO,
+            Motion::Line { dx, dy, .. } | Motion::Sine { dx, dy, .. } => Vec2 { x: dx, y: dy }.abs(),
+            Motion::Circle { radius, .. } => Vec2::splat(radius.abs()),
+        }
+    }
+
+    fn period(&self) -> Option<f32> {
+        match *self {
+            Motion::Still => None,
+            Motion::Line { period, .. } | Motion::Sine { period, .. } | Motion::Circle { period, .. } => Some(period),
+        }
+    }
+}
+
+impl Track {
+    pub fn center(&self) -> Vec2 {
+        Vec2 { x: self.x, y: self.y }
+    }
+
+    /// Area the paddle can cover along the track
+    fn block(&self, config: &Config) -> Block {
+        Block { x: self.x, y: self.y, width: config.player.width, height: self.length }
+    }
+}
+
+impl Level {
+    /// The usual two player field for the given config, a starting point for new levels
+    pub fn classic(config: &Config) -> Level {
+        let arena = Arena::from_config(config);
+        let block = |pos: Vec2, size: Vec2| Block { x: pos.x, y: pos.y, width: size.x, height: size.y };
+        Level {
+            name: "Classic".to_string(),
+            width: arena.width,
+            height: arena.height,
+            walls: [WallSide::Top, WallSide::Bottom].iter()
+                .map(|&side| block(Wall::calc_pos(side, &arena), Wall::calc_size(side, &arena, config)))
+                .collect(),
+            obstacles: Vec::new(),
+            moving_obstacles: Vec::new(),
+            goals: LEVEL_SIDES.iter()
+                .map(|&side| {
+                    let area = block(Goal::calc_pos(side, &arena), Goal::calc_size(side, &arena, config, false));
+                    GoalArea { side: side, x: area.x, y: area.y, width: area.width, height: area.height }
+                })
+                .collect(),
+            tracks: LEVEL_SIDES.iter()
+                .map(|&side| {
+                    let pos = Player::calc_pos(side, &arena, config);
+                    Track { side: side, x: pos.x, y: pos.y, length: arena.height - config.arena.wall_height }
+                })
+                .collect(),
+        }
+    }
+
+    /// Loads a level and checks that it can be played with the given config
+    pub fn load(path: &Path, config: &Config) -> Result<Level, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read level {}: {}", path.display(), err))?;
+        Level::parse(&text)
+            .and_then(|level| level.validate(config).map(|()| level))
+            .map_err(|err| format!("Invalid level {}: {}", path.display(), err))
+    }
+
+    pub fn parse(text: &str) -> Result<Level, String> {
+        toml::from_str(text).map_err(|err| err.to_string())
+    }
+
+    pub fn to_text(&self) -> String {
+        toml::to_string(self).expect("level can always be written as TOML")
+    }
+
+    pub fn arena(&self) -> Arena {
+        Arena::new(self.width, self.height)
+    }
+
+    // --------------------- ENTITIES ---------------------
+    pub fn players(&self, config: &Config) -> Vec<Player> {
+        LEVEL_SIDES.iter()
+            .filter_map(|&side| self.tracks.iter().find(|track| track.side == side))
+            .map(|&track| Player::on_track(track, config))
+            .collect()
+    }
+
+    pub fn walls(&self) -> Vec<Wall> {
+        let obstacle_color = lerp_color(&COL_FOREGROUND, &macroquad::color::WHITE, 0.2);
+        self.walls.iter()
+            .map(|&block| Wall::fixed(block, COL_FOREGROUND))
+            .chain(self.obstacles.iter().map(|&block| Wall::fixed(block, obstacle_color)))
+            .collect()
+    }
+
+    pub fn movers(&self) -> Vec<Mover> {
+        self.moving_obstacles.iter().map(Mover::new).collect()
+    }
+
+    pub fn goals(&self) -> Vec<Goal> {
+        self.goals.iter().map(|goal| Goal::fixed(goal.side, goal.block())).collect()
+    }
+
+    // --------------------- VALIDATION ---------------------
+    /// Rejects layouts that can't be played: missing or overlapping paddles,
+    /// goals the ball can never reach and holes the ball could fly out of
+    pub fn validate(&self, config: &Config) -> Result<(), String> {
+        if !(self.width.is_finite() && self.width > 0. && self.height.is_finite() && self.height > 0.) {
+            return Err(format!("Arena size must be greater than 0, got {} x {}", self.width, self.height));
+        }
+        if self.width > MAX_ARENA_SIZE || self.height > MAX_ARENA_SIZE {
+            return Err(format!("Arena can be at most {} x {}, got {} x {}", MAX_ARENA_SIZE, MAX_ARENA_SIZE, self.width, self.height));
+        }
+        let blocks = self.walls.iter().chain(&self.obstacles).copied()
+            .chain(self.goals.iter().map(GoalArea::block))
+            .chain(self.tracks.iter().map(|track| track.block(config)));
+        for block in blocks {
+            let values = [block.x, block.y, block.width, block.height];
+            if values.iter().any(|value| !value.is_finite()) || block.width <= 0. || block.height <= 0. {
+                return Err(format!("Rectangle at {} {} has a broken position or size", block.x, block.y));
+            }
+        }
+
+        // Both players need a paddle and something to defend
+        for side in LEVEL_SIDES {
+            if self.tracks.iter().filter(|track| track.side == side).count() != 1 {
+                return Err(format!("{:?} needs exactly one paddle track", side));
+            }
+            if !self.goals.iter().any(|goal| goal.side == side) {
+                return Err(format!("{:?} has no goal", side));
+            }
+        }
+        if let Some(other) = self.tracks.iter().map(|track| track.side).chain(self.goals.iter().map(|goal| goal.side))
+            .find(|side| !LEVEL_SIDES.contains(side))
+        {
+            return Err(format!("Levels are for two players, {:?} can't have a track or goal", other));
+        }
+
+        self.validate_tracks(config)?;
+        self.validate_moving(config)?;
+        self.validate_reachable(config)
+    }
+
+    /// Paddles fit on their track, stay inside the arena and never run into each other or a wall
+    fn validate_tracks(&self, config: &Config) -> Result<(), String> {
+        for (i, track) in self.tracks.iter().enumerate() {
+            if track.length < config.player.height {
+                return Err(format!("Track of {:?} is shorter than the paddle", track.side));
+            }
+            let area = track.block(config);
+            let (near, far) = (area.center() - area.size() / 2., area.center() + area.size() / 2.);
+            if near.min_element() < 0. || far.x > self.width || far.y > self.height {
+                return Err(format!("Track of {:?} leaves the arena", track.side));
+            }
+            if self.walls.iter().chain(&self.obstacles).any(|block| block.overlaps(&area)) {
+                return Err(format!("Track of {:?} runs into a wall", track.side));
+            }
+            if let Some(other) = self.tracks[i + 1..].iter().find(|other| other.block(config).overlaps(&area)) {
+                return Err(format!("Paddles of {:?} and {:?} overlap", track.side, other.side));
+            }
+        }
+        Ok(())
+    }
+
+    /// Moving obstacles never get in the way of the paddles or the serve, they are left out of the
+    /// flood fill as they only block the ball for a moment
+    fn validate_moving(&self, config: &Config) -> Result<(), String> {
+        for obstacle in &self.moving_obstacles {
+            let values = [obstacle.x, obstacle.y, obstacle.width, obstacle.height, obstacle.angle, obstacle.rotation];
+            let motion_values = obstacle.motion.reach().to_array();
+            if values.iter().chain(&motion_values).any(|value| !value.is_finite())
+                || obstacle.width <= 0. || obstacle.height <= 0.
+                || obstacle.motion.period().is_some_and(|period| !(period.is_finite() && period > 0.))
+            {
+                return Err(format!("Moving obstacle at {} {} has a broken position, size or path", obstacle.x, obstacle.y));
+            }
+            let reach = obstacle.reach();
+            if let Some(track) = self.tracks.iter().find(|track| track.block(config).overlaps(&reach)) {
+                return Err(format!("Moving obstacle at {} {} can run into the paddle of {:?}", obstacle.x, obstacle.y, track.side));
+            }
+            let start = self.arena().center();
+            let ball = Block { x: start.x, y: start.y, width: config.ball.size, height: config.ball.size };
+            if reach.overlaps(&ball) {
+                return Err(format!("Moving obstacle at {} {} can run over the serve spot", obstacle.x, obstacle.y));
+            }
+        }
+        Ok(())
+    }
+
+    /// Flood fills the field from the serve spot on a grid as fine as the ball.
+    /// Every goal has to be reached and the ball may only leave the arena through a goal.
+    fn validate_reachable(&self, config: &Config) -> Result<(), String> {
+        let cell = config.ball.size;
+        // One extra ring of cells around the arena catches balls flying out of it
+        let columns = ((self.width / cell).ceil() as usize).saturating_add(2);
+        let rows = ((self.height / cell).ceil() as usize).saturating_add(2);
+        if columns.checked_mul(rows).is_none_or(|cells| cells > MAX_GRID_CELLS) {
+            return Err(format!("Arena is too large to check with a ball of size {}", cell));
+        }
+        let (columns, rows) = (columns as i32, rows as i32);
+        let center_of = |column: i32, row: i32| Vec2 {
+            x: (column as f32 - 0.5) * cell,
+            y: (row as f32 - 0.5) * cell,
+        };
+        // Where the ball would be touching a rectangle
+        let touches = |block: &Block, pos: Vec2| block.overlaps(&Block { x: pos.x, y: pos.y, width: cell, height: cell });
+        let solid = |pos: Vec2| self.walls.iter().chain(&self.obstacles).any(|block| touches(block, pos));
+        let goal = |pos: Vec2| self.goals.iter().position(|goal| touches(&goal.block(), pos));
+
+        let start = self.arena().center();
+        if solid(start) {
+            return Err("The ball starts inside a wall".to_string());
+        }
+        if goal(start).is_some() {
+            return Err("The ball starts inside a goal".to_string());
+        }
+
+        let start_cell = ((start.x / cell) as i32 + 1, (start.y / cell) as i32 + 1);
+        let mut visited = vec![false; (columns * rows) as usize];
+        let mut reached = vec![false; self.goals.len()];
+        let mut queue = VecDeque::from([start_cell]);
+        visited[(start_cell.1 * columns + start_cell.0) as usize] = true;
+        while let Some((column, row)) = queue.pop_front() {
+            let pos = center_of(column, row);
+            // The ball stops in a goal
+            if let Some(i) = goal(pos) {
+                reached[i] = true;
+                continue;
+            }
+            if column == 0 || row == 0 || column == columns - 1 || row == rows - 1 {
+                return Err(format!("The ball can leave the arena near {:.0} {:.0}", pos.x, pos.y));
+            }
+            for (next_column, next_row) in [(column - 1, row), (column + 1, row), (column, row - 1), (column, row + 1)] {
+                let index = (next_row * columns + next_column) as usize;
+                if !visited[index] && !solid(center_of(next_column, next_row)) {
+                    visited[index] = true;
+                    queue.push_back((next_column, next_row));
+                }
+            }
+        }
+
+        match reached.iter().position(|&reached| !reached) {
+            Some(i) => Err(format!("Goal of {:?} at {} {} can't be reached", self.goals[i].side, self.goals[i].x, self.goals[i].y)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pillars() -> Level {
+        Level::parse(include_str!("../levels/pillars.toml")).unwrap()
+    }
+
+    #[test]
+    fn classic_level_is_playable() {
+        let config = Config::default();
+        let level = Level::classic(&config);
+        assert_eq!(level.validate(&config), Ok(()));
+        assert_eq!(level.goals[0].block(), pillars().goals[0].block());
+    }
+
+    #[test]
+    fn oversized_levels_are_refused() {
+        let mut config = Config::default();
+        let mut level = Level::classic(&config);
+        level.width = 1e6;
+        level.height = 1e6;
+        assert!(level.validate(&config).unwrap_err().contains("at most"));
+
+        // Fine for a normal ball, but far too many cells for a tiny one
+        let level = Level::classic(&config);
+        config.ball.size = 0.01;
+        assert!(level.validate(&config).unwrap_err().contains("too large"));
+    }
+
+    #[test]
+    fn levels_in_the_repo_are_playable() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("levels");
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "toml") {
+                assert!(Level::load(&path, &Config::default()).is_ok(), "{} can't be played", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn level_survives_a_round_trip_and_builds_its_entities() {
+        let level = pillars();
+        assert_eq!(level.validate(&Config::default()), Ok(()));
+        assert_eq!(Level::parse(&level.to_text()).unwrap(), level);
+
+        let config = Config::default();
+        let players = level.players(&config);
+        assert_eq!(players.iter().map(|player| (player.side, player.pos)).collect::<Vec<_>>(),
+            vec![(Side::Left, Vec2::new(100., 360.)), (Side::Right, Vec2::new(1180., 360.))]);
+        assert_eq!(level.walls().len(), 4);
+        assert_eq!(level.goals()[1].pos, Vec2::new(1280., 360.));
+    }
+
+    #[test]
+    fn unplayable_layouts_are_rejected() {
+        let config = Config::default();
+        let broken = |change: fn(&mut Level)| {
+            let mut level = pillars();
+            change(&mut level);
+            level.validate(&config).unwrap_err()
+        };
+
+        assert!(broken(|level| { level.tracks.pop(); }).contains("Right needs exactly one paddle track"));
+        assert!(broken(|level| level.tracks[1].x = 110.).contains("overlap"));
+        assert!(broken(|level| level.tracks[0].length = 50.).contains("shorter than the paddle"));
+        assert!(broken(|level| level.tracks[0].length = 700.).contains("runs into a wall"));
+        assert!(broken(|level| level.goals[0].side = Side::Top).contains("Left has no goal"));
+        // A wall right in front of the left goal
+        assert!(broken(|level| level.walls.push(Block { x: 75., y: 360., width: 10., height: 720. }))
+            .contains("can't be reached"));
+        // No wall at the bottom
+        assert!(broken(|level| { level.walls.pop(); }).contains("leave the arena"));
+        assert!(broken(|level| level.obstacles[0].y = 360.).contains("starts inside a wall"));
+    }
+
+    #[test]
+    fn moving_obstacles_follow_their_path_and_stay_clear_of_paddles() {
+        let line = Motion::Line { dx: 100., dy: 0., period: 4. };
+        assert_eq!(line.offset(0.), Vec2::ZERO);
+        assert_eq!(line.offset(1.), Vec2::new(100., 0.));
+        assert_eq!(line.offset(3.), Vec2::new(-100., 0.));
+        let sine = Motion::Sine { dx: 0., dy: 50., period: 2. };
+        assert!((sine.offset(0.5) - Vec2::new(0., 50.)).length() < 1e-4);
+        let circle = Motion::Circle { radius: 30., period: 4. };
+        assert!((circle.offset(1.) - Vec2::new(0., 30.)).length() < 1e-4);
+
+        let config = Config::default();
+        let mut level = pillars();
+        let spinner = MovingObstacle {
+            shape: Shape::Box,
+            x: 400.,
+            y: 360.,
+            width: 100.,
+            height: 20.,
+            angle: 0.,
+            rotation: 2.,
+            motion: Motion::Still,
+        };
+        level.moving_obstacles.push(spinner);
+        assert_eq!(level.validate(&config), Ok(()));
+        assert_eq!(Level::parse(&level.to_text()).unwrap(), level);
+
+        level.moving_obstacles[0].motion = Motion::Line { dx: 250., dy: 0., period: 3. };
+        assert!(level.validate(&config).unwrap_err().contains("paddle of Left"));
+        level.moving_obstacles[0].motion = Motion::Circle { radius: 200., period: 3. };
+        assert!(level.validate(&config).unwrap_err().contains("serve spot"));
+        level.moving_obstacles[0].motion = Motion::Sine { dx: 0., dy: 10., period: 0. };
+        assert!(level.validate(&config).unwrap_err().contains("broken"));
+    }
+}
@@ -1,7 +1,58 @@
+// The code base spells out `-> ()` and `field: field` on purpose
+#![allow(clippy::unused_unit, clippy::redundant_field_names)]
+
 use std::fmt::Debug;
 
 use::macroquad::prelude::*;
 use miniquad::window::screen_size;
+use serde::{Deserialize, Serialize};
+
+mod ai;
+mod app;
+mod bindings;
+mod cli;
+mod collision;
+mod config;
+mod controller;
+mod editor;
+mod effects;
+mod gamepad;
+mod history;
+mod level;
+mod menu;
+mod mover;
+mod net;
+mod powerup;
+mod replay;
+mod rng;
+mod rules;
+mod scheduler;
+mod sound;
+mod stats;
+mod trig;
+mod viewport;
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use ai::{AiController, Difficulty};
+use bindings::Bindings;
+use collision::{Collider, Contact};
+use config::Config;
+use controller::{CombinedController, GamepadController, KeyboardController, PaddleController, RemoteController, TouchController};
+use effects::{EffectSettings, Effects};
+use gamepad::Gamepads;
+use history::{History, MatchRecord, DEFAULT_HISTORY_PATH};
+use level::{Block, Level, Track};
+use mover::Mover;
+use net::{Connection, NetSession, NetShim};
+use powerup::{PowerUpKind, PowerUps};
+use replay::{Playback, Replay};
+use rules::{EdgeRule, MatchRules, Outcome};
+use scheduler::Scheduler;
+use stats::MatchStats;
+use viewport::Viewport;
 
 // ===================== COLORS =====================
 pub const COL_BACKGROUND: Color = Color {
@@ -11,6 +62,14 @@ pub const COL_BACKGROUND: Color = Color {
     a: 1.0,
 };
 
+/// Bars around the arena when the window has a different shape
+pub const COL_LETTERBOX: Color = Color {
+    r: 0.,
+    g: 0.,
+    b: 0.,
+    a: 1.0,
+};
+
 pub const COL_FOREGROUND: Color = Color {
     r: 0.3,
     g: 0.3,
@@ -32,6 +91,20 @@ pub const COL_RIGHT: Color = Color {
     a: 1.0,
 };
 
+pub const COL_TOP: Color = Color {
+    r: 0.9,
+    g: 0.3,
+    b: 1.0,
+    a: 1.0,
+};
+
+pub const COL_BOTTOM: Color = Color {
+    r: 0.4,
+    g: 1.0,
+    b: 0.4,
+    a: 1.0,
+};
+
 fn lerp_color(a: &Color, b: &Color, s: f32) -> Color {
     Color {
         r: a.r * ( 1. - s ) + b.r * s,
@@ -43,11 +116,93 @@ fn lerp_color(a: &Color, b: &Color, s: f32) -> Color {
 // --------------------- COLORS ---------------------
 
 // ===================== MAIN =====================
-#[macroquad::main("RustedPong")]
-async fn main() {
-    let mut rusted_pong = MyGame::new();
+fn main() {
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            println!("{}", message);
+            return;
+        }
+    };
+
+    let config = match &options.config {
+        Some(path) => Config::load(path),
+        None => Config::load_default(),
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(message) => {
+            println!("{}", message);
+            std::process::exit(1);
+        }
+    };
+
+    // Exporting the history doesn't need a window either
+    if let Some(path) = &options.export_history {
+        let exported = History::load_or_default(Path::new(DEFAULT_HISTORY_PATH))
+            .and_then(|history| history.export(path).map(|()| history.matches.len()));
+        match exported {
+            Ok(count) => println!("Exported {} matches to {}", count, path.display()),
+            Err(message) => {
+                println!("{}", message);
+                std::process::exit(1);
+            },
+        }
+        return;
+    }
+
+    let replay = match &options.replay {
+        Some(path) => match Replay::load(path) {
+            Ok(replay) => Some(replay),
+            Err(message) => {
+                println!("{}", message);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let level = match &options.level {
+        Some(path) => match Level::load(path, &config) {
+            Ok(level) => Some(level),
+            Err(message) => {
+                println!("{}", message);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // Checking a replay doesn't need a window, so it also works on machines without a GPU
+    if let (Some(replay), true) = (&replay, options.verify_replay) {
+        let sim = replay.run();
+        match replay.checksum {
+            Some(checksum) if checksum == sim.checksum() => println!("Replay plays out exactly as recorded"),
+            Some(_) => {
+                println!("Replay does NOT play out as recorded");
+                std::process::exit(1);
+            },
+            None => println!("Replay has no checksum to verify against"),
+        }
+        return;
+    }
+
+    // The window starts at the size of the arena, it can be resized freely afterwards
+    let window = Conf {
+        window_title: "RustedPong".to_string(),
+        window_width: config.arena.width as i32,
+        window_height: config.arena.height as i32,
+        window_resizable: true,
+        ..Conf::default()
+    };
+    macroquad::Window::from_config(window, run(options, config, replay, level));
+}
+
+async fn run(options: cli::Options, config: Config, replay: Option<Replay>, level: Option<Level>) {
+    let sounds = sound::Sounds::load(options.volume.unwrap_or(80.) / 100., options.mute).await;
+    let mut rusted_pong = app::App::new(&options, config, replay, level, sounds);
 
-    loop {
+    while !rusted_pong.quit_requested() {
         rusted_pong.update();
         rusted_pong.draw();
 
@@ -57,12 +212,62 @@ async fn main() {
 // --------------------- MAIN ---------------------
 
 // ===================== PLAYER =====================
-#[derive(Debug, Copy, Clone, PartialEq)]
+/// Edge of the arena a player defends, top and bottom only have players in four player matches
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 enum Side {
     Left,
     Right,
+    Top,
+    Bottom,
+}
+
+impl Side {
+    const ALL: [Side; 4] = [Side::Left, Side::Right, Side::Top, Side::Bottom];
+
+    /// The side across the arena
+    fn opponent(&self) -> Side {
+        match self {
+            Side::Left   => Side::Right,
+            Side::Right  => Side::Left,
+            Side::Top    => Side::Bottom,
+            Side::Bottom => Side::Top,
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            Side::Left   => COL_LEFT,
+            Side::Right  => COL_RIGHT,
+            Side::Top    => COL_TOP,
+            Side::Bottom => COL_BOTTOM,
+        }
+    }
+
+    /// Orientation of the paddle, left and right paddles stand upright and move up and down
+    fn orientation(&self) -> Orientation {
+        match self {
+            Side::Left | Side::Right => Orientation::Vertical,
+            Side::Top | Side::Bottom => Orientation::Horizontal,
+        }
+    }
+
+    /// Direction from the side's edge into the field
+    fn normal(&self) -> Vec2 {
+        match self {
+            Side::Left   => Vec2 { x: 1., y: 0. },
+            Side::Right  => Vec2 { x: -1., y: 0. },
+            Side::Top    => Vec2 { x: 0., y: 1. },
+            Side::Bottom => Vec2 { x: 0., y: -1. },
+        }
+    }
+
+    /// Whether this side sits at the far end of its axis, right or bottom
+    fn is_far(&self) -> bool {
+        matches!(self, Side::Right | Side::Bottom)
+    }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 enum Orientation {
     Vertical,
     Horizontal,
@@ -99,116 +304,240 @@ trait ExcitedThing {
 
 trait Entity {
     /// Generic draw function for rectangle shaped entities
-    fn draw(&self) -> () {
+    /// `alpha` is how far the renderer is between the previous and the current tick
+    fn draw(&self, alpha: f32) -> () {
         // Create rectangle
-        let pos = self.get_pos();
+        let pos = self.get_draw_pos(alpha);
         let size = self.get_size();
 
         draw_rectangle(pos.x - size.x / 2., pos.y - size.y / 2., size.x, size.y, self.get_fill_color());
         draw_rectangle_lines(pos.x - size.x / 2., pos.y - size.y / 2., size.x, size.y, 4., self.get_stroke_color());
     }
 
+    /// Position to render at, entities that don't need interpolation simply use their position
+    fn get_draw_pos(&self, _alpha: f32) -> Vec2 {
+        self.get_pos()
+    }
+
     fn get_pos(&self) -> Vec2;
     fn get_size(&self) -> Vec2;
+
+    /// Shape the entity collides as, most of them are boxes
+    fn get_collider(&self) -> Collider {
+        Collider::Aabb { center: self.get_pos(), half: self.get_size() / 2. }
+    }
+
     fn get_stroke_color(&self) -> Color;
     fn get_fill_color(&self) -> Color;
-    fn resize(&mut self) -> ();
+    fn resize(&mut self, arena: &Arena, config: &Config) -> ();
     fn update(&mut self) -> ();
 }
 
 impl dyn Entity {
     fn check_collision(&self, other: &dyn Entity) -> bool {
-        // Get the top left and bottom right corners of the rectangles
-        let a_top_l = self.get_pos()  - self.get_size()  / 2.0;
-        let a_bot_r = self.get_pos()  + self.get_size()  / 2.0;
-        let b_top_l = other.get_pos() - other.get_size() / 2.0;
-        let b_bot_r = other.get_pos() + other.get_size() / 2.0;
+        self.get_collider().overlaps(&other.get_collider())
+    }
 
-        // Easy to calculate tha case of NOT colliding in case of rectangles
-        if a_top_l.x > b_bot_r.x || a_top_l.y > b_bot_r.y ||
-            b_top_l.x > a_bot_r.x || b_top_l.y > a_bot_r.y {
-            false
-        }
-        else {
-            true
-        }
+    /// Moves this entity along `delta` and returns the first contact with `other` on the way
+    fn sweep_collision(&self, delta: Vec2, other: &dyn Entity) -> Option<Contact> {
+        self.get_collider().sweep(delta, &other.get_collider())
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-struct Controls {
-    up: KeyCode,
-    down: KeyCode,
+/// Contacts whose normal is at least this close to a paddle's own are hits on its face
+const FACE_HIT: f32 = 0.99;
+
+/// Part of a paddle the ball ran into
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ContactFace {
+    /// The side facing the field, the only one that aims
+    Front,
+    /// The side facing the goal
+    Back,
+    /// One of the two ends
+    Edge,
+    Corner,
 }
 
+#[derive(Clone)]
 struct Player {
     side: Side,
     pos: Vec2,
+    /// Position at the start of the tick, the difference is how fast the paddle moves
+    prev_pos: Vec2,
     size: Vec2,
-    controls: Controls,
+    input: PaddleInput,
     speed: f32,
     curve_strength: f32,
     straight_strength: f32,
+    spin_strength: f32,
+    /// Line the paddle moves along on a level, otherwise it runs between the walls
+    track: Option<Track>,
     color: Color,
     excitement: f32,
 }
 
 impl Player {
-    fn new(side: Side, controls: &Controls) -> Self {
+    fn new(side: Side, arena: &Arena, config: &Config) -> Self {
+        let pos = Player::calc_pos(side, arena, config);
         Player {
             side: side,
-            pos: Player::calc_pos(side),
-            size: Player::calc_size(),
-            controls: *controls,
-            speed: 7.0,
-            curve_strength: 1.7,
-            straight_strength: 1.05,
-            color: match side {
-                Side::Left  => COL_LEFT,
-                Side::Right => COL_RIGHT,
-            },
+            pos: pos,
+            prev_pos: pos,
+            size: Player::calc_size(side, config),
+            input: PaddleInput::default(),
+            speed: config.player.speed,
+            curve_strength: config.player.curve_strength,
+            straight_strength: config.player.straight_strength,
+            spin_strength: config.player.spin_strength,
+            track: None,
+            color: side.color(),
             excitement: 0.,
         }
     }
 
+    /// Paddle of a level, starting in the middle of its track
+    fn on_track(track: Track, config: &Config) -> Self {
+        let mut player = Player::new(track.side, &Arena::from_config(config), config);
+        player.track = Some(track);
+        player.pos = track.center();
+        player.prev_pos = player.pos;
+        player
+    }
+
     fn check_collision(&self, other: &dyn Entity) -> bool {
         (self as &dyn Entity).check_collision(other)
     }
 
-    fn hit(&mut self, ball: &mut Ball) -> () {
-        // Ball bounce (overwrite x position to avoid getting stuck)
-        ball.bounce(&Orientation::Vertical);
-        ball.pos.x = self.pos.x + match self.side {
-            Side::Left  =>  (self.size.x + ball.size.x) / 2.,
-            Side::Right => -(self.size.x + ball.size.x) / 2.,
+    /// Which part of the paddle a contact with surface `normal` is on
+    fn contact_face(&self, normal: Vec2) -> ContactFace {
+        let facing = normal.dot(self.side.normal());
+        if facing > FACE_HIT {
+            ContactFace::Front
+        } else if facing < -FACE_HIT {
+            ContactFace::Back
+        } else if facing.abs() < 1. - FACE_HIT {
+            ContactFace::Edge
+        } else {
+            ContactFace::Corner
+        }
+    }
+
+    /// Sends the ball back, returns how far off center it was hit (0 is the center, ±1 the edges)
+    fn hit(&mut self, ball: &mut Ball) -> f32 {
+        let motion = self.motion();
+        let rel_diff = match self.side.orientation() {
+            Orientation::Vertical => {
+                // Ball bounce (overwrite x position to avoid getting stuck)
+                ball.bounce(self.side.normal());
+                ball.pos.x = self.pos.x + match self.side.is_far() {
+                    false =>  (self.size.x + ball.size.x) / 2.,
+                    true  => -(self.size.x + ball.size.x) / 2.,
+                };
+
+                // Add curve based on relative position
+                let rel_diff = (ball.pos.y - self.pos.y) / (self.size.y / 2. + ball.size.y / 2.);
+                ball.vel.y = ball.vel.x.abs() * rel_diff * self.curve_strength;
+
+                // Add extra strength near center hit
+                ball.vel.x *= 1. + (1. - rel_diff.abs()) * self.straight_strength;
+                rel_diff
+            },
+            Orientation::Horizontal => {
+                // Same as above with the axes swapped
+                ball.bounce(self.side.normal());
+                ball.vel.y = ball.vel.y.clamp(-ball.x_speed_limit, ball.x_speed_limit);
+                ball.pos.y = self.pos.y + match self.side.is_far() {
+                    false =>  (self.size.y + ball.size.y) / 2.,
+                    true  => -(self.size.y + ball.size.y) / 2.,
+                };
+
+                let rel_diff = (ball.pos.x - self.pos.x) / (self.size.x / 2. + ball.size.x / 2.);
+                ball.vel.x = ball.vel.y.abs() * rel_diff * self.curve_strength;
+
+                ball.vel.y *= 1. + (1. - rel_diff.abs()) * self.straight_strength;
+                rel_diff
+            },
         };
-        
-        // Add curve based on relative position
-        let rel_diff = (ball.pos.y - self.pos.y) / (self.size.y / 2. + ball.size.y / 2.);
-        ball.vel.y = ball.vel.x.abs() * rel_diff * self.curve_strength;
-        
-        // Add extra strength near center hit
-        ball.vel.x *= 1. + (1. - rel_diff.abs()) * self.straight_strength;
+        ball.last_hit = Some(self.side);
+
+        // A moving paddle makes the ball spin, curving it towards where the paddle was going
+        ball.spin = ball.vel.normalize_or_zero().perp_dot(motion) * self.spin_strength;
 
         // Get excited
         self.trig_excited();
+        rel_diff
     }
 
-    fn calc_pos(side: Side) -> Vec2 {
-        Vec2 {
-            // X position is based on side
-            x: match side {
-                Side::Left => 100.,
-                Side::Right => screen_width() - 100.,
-            },
-            y: screen_height() / 2.
+    /// Knocks the ball away off a corner or the back of the paddle, where there is nothing to aim with.
+    /// The paddle's movement carries over, returns how far off center it was hit like `hit`.
+    fn deflect(&mut self, ball: &mut Ball, normal: Vec2) -> f32 {
+        ball.bounce_off(normal, self.motion());
+        ball.last_hit = Some(self.side);
+        self.trig_excited();
+        let rel_diff = match self.side.orientation() {
+            Orientation::Vertical   => (ball.pos.y - self.pos.y) / (self.size.y / 2. + ball.size.y / 2.),
+            Orientation::Horizontal => (ball.pos.x - self.pos.x) / (self.size.x / 2. + ball.size.x / 2.),
+        };
+        rel_diff.clamp(-1., 1.)
+    }
+
+    /// Stops the paddle at the ends of its track, if it has one
+    fn keep_on_track(&mut self) -> () {
+        if let Some(track) = self.track {
+            let reach = ((track.length - self.size.y) / 2.).max(0.);
+            self.pos.y = self.pos.y.clamp(track.y - reach, track.y + reach);
         }
     }
 
-    fn calc_size() -> Vec2 {
-        Vec2 {
-            x: 20.0,
-            y: 150.0
+    /// Distance the paddle moved along its track during this tick, walls included
+    fn motion(&self) -> Vec2 {
+        let motion = self.pos - self.prev_pos;
+        match self.side.orientation() {
+            Orientation::Vertical   => Vec2 { x: 0., y: motion.y },
+            Orientation::Horizontal => Vec2 { x: motion.x, y: 0. },
+        }
+    }
+
+    /// Changes the length of the paddle, the direction it moves in
+    fn set_length(&mut self, length: f32) -> () {
+        match self.side.orientation() {
+            Orientation::Vertical   => self.size.y = length,
+            Orientation::Horizontal => self.size.x = length,
+        }
+    }
+
+    /// Applies new tuning values without moving the paddle up or down
+    fn configure(&mut self, arena: &Arena, config: &Config) -> () {
+        self.speed = config.player.speed;
+        self.curve_strength = config.player.curve_strength;
+        self.straight_strength = config.player.straight_strength;
+        self.spin_strength = config.player.spin_strength;
+        self.size = Player::calc_size(self.side, config);
+        let pos = match self.track {
+            Some(track) => track.center(),
+            None => Player::calc_pos(self.side, arena, config),
+        };
+        match self.side.orientation() {
+            Orientation::Vertical   => self.pos.x = pos.x,
+            Orientation::Horizontal => self.pos.y = pos.y,
+        }
+    }
+
+    fn calc_pos(side: Side, arena: &Arena, config: &Config) -> Vec2 {
+        // Distance to the edge is based on side, along the edge the paddle starts in the middle
+        match side {
+            Side::Left   => Vec2 { x: config.player.edge_distance, y: arena.height / 2. },
+            Side::Right  => Vec2 { x: arena.width - config.player.edge_distance, y: arena.height / 2. },
+            Side::Top    => Vec2 { x: arena.width / 2., y: config.player.edge_distance },
+            Side::Bottom => Vec2 { x: arena.width / 2., y: arena.height - config.player.edge_distance },
+        }
+    }
+
+    fn calc_size(side: Side, config: &Config) -> Vec2 {
+        match side.orientation() {
+            Orientation::Vertical   => Vec2 { x: config.player.width, y: config.player.height },
+            Orientation::Horizontal => Vec2 { x: config.player.height, y: config.player.width },
         }
     }
 }
@@ -244,44 +573,22 @@ impl Entity for Player {
         self.calc_fill_color()
     }
 
-    fn resize(&mut self) -> () {
-        self.size = Player::calc_size();
-        self.pos  = Player::calc_pos(self.side);
+    fn resize(&mut self, arena: &Arena, config: &Config) -> () {
+        self.size = Player::calc_size(self.side, config);
+        self.pos = match self.track {
+            Some(track) => track.center(),
+            None => Player::calc_pos(self.side, arena, config),
+        };
+        self.prev_pos = self.pos;
     }
 
     fn update(&mut self) -> () {
-        // Flags that keep track of the intended movement of the player
-        // Move up and move down CAN be true at the same time, in that case the player remains still
-        let mut move_up = false;
-        let mut move_down = false;
-
-        // Handle touch screen input
-        for touch in touches_local() {
-            // Check if the touch is on the current player's side
-            if (self.side == Side::Left && touch.position.x < 0.) || 
-                (self.side == Side::Right && touch.position.x > 0.) {
-                if touch.position.y < 0. {
-                    move_up = true;
-                } else {
-                    move_down = true;
-                }
-            } 
-        }
-
-        // Handle keyboard input
-        if is_key_down(self.controls.up) {
-            move_up = true;
-        }
-        if is_key_down(self.controls.down) {
-            move_down = true;
-        }
-        
-        // Update position
-        if move_up {
-            self.pos.y -= self.speed;
-        }
-        if move_down {
-            self.pos.y += self.speed;
+        // Update position based on the input of this tick
+        self.prev_pos = self.pos;
+        let step = self.speed * self.input.axis.clamp(-1., 1.);
+        match self.side.orientation() {
+            Orientation::Vertical   => self.pos.y += step,
+            Orientation::Horizontal => self.pos.x += step,
         }
 
         // Lower excitement (aka entity glow)
@@ -291,12 +598,19 @@ impl Entity for Player {
 // --------------------- PLAYER ---------------------
 
 // ===================== WALL =====================
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 enum WallSide {
     Top,
     Bottom,
+    /// Block in a corner of a four player arena, given by the vertical and the horizontal side it touches
+    Corner(Side, Side),
+    /// Goal of a player that is out of a four player match
+    Closed(Side),
+    /// Placed by a level, stays where it is
+    Fixed(Block),
 }
 
+#[derive(Clone)]
 struct Wall {
     pos: Vec2,
     size: Vec2,
@@ -306,30 +620,51 @@ struct Wall {
 }
 
 impl Wall {
-    fn new(side: WallSide) -> Self {
+    fn fixed(block: Block, color: Color) -> Self {
+        Wall {
+            pos: block.center(),
+            size: block.size(),
+            color: color,
+            excitement: 0.,
+            side: WallSide::Fixed(block),
+        }
+    }
+
+    fn new(side: WallSide, arena: &Arena, config: &Config) -> Self {
         Wall {
-            pos: Wall::calc_pos(side),
-            size: Wall::calc_size(),
-            color: COL_FOREGROUND,
+            pos: Wall::calc_pos(side, arena),
+            size: Wall::calc_size(side, arena, config),
+            color: match side {
+                WallSide::Closed(player) => lerp_color(&player.color(), &COL_FOREGROUND, 0.6),
+                _ => COL_FOREGROUND,
+            },
             excitement: 0.,
             side: side,
         }
     }
 
-    fn calc_pos(side: WallSide) -> Vec2 {
-        Vec2 {
-            x: screen_width() / 2.,
-            y: match side {
-                WallSide::Top    => 0.,
-                WallSide::Bottom => screen_height(),
-            }
+    fn calc_pos(side: WallSide, arena: &Arena) -> Vec2 {
+        match side {
+            WallSide::Top    => Vec2 { x: arena.width / 2., y: 0. },
+            WallSide::Bottom => Vec2 { x: arena.width / 2., y: arena.height },
+            WallSide::Corner(vertical, horizontal) => Vec2 {
+                x: if vertical.is_far() { arena.width } else { 0. },
+                y: if horizontal.is_far() { arena.height } else { 0. },
+            },
+            WallSide::Closed(player) => Goal::calc_pos(player, arena),
+            WallSide::Fixed(block) => block.center(),
         }
     }
 
-    fn calc_size() -> Vec2 {
-        Vec2 {
-            x: screen_width() * 1.5,
-            y: 80.0
+    fn calc_size(side: WallSide, arena: &Arena, config: &Config) -> Vec2 {
+        match side {
+            WallSide::Top | WallSide::Bottom => Vec2 {
+                x: arena.width * 1.5,
+                y: config.arena.wall_height
+            },
+            WallSide::Corner(..) => Vec2::splat(corner_size(config) * 2.),
+            WallSide::Closed(player) => Goal::calc_size(player, arena, config, true),
+            WallSide::Fixed(block) => block.size(),
         }
     }
 }
@@ -365,9 +700,9 @@ impl Entity for Wall {
         self.calc_fill_color()
     }
 
-    fn resize(&mut self) -> () {
-        self.size = Wall::calc_size();
-        self.pos  = Wall::calc_pos(self.side);
+    fn resize(&mut self, arena: &Arena, config: &Config) -> () {
+        self.size = Wall::calc_size(self.side, arena, config);
+        self.pos  = Wall::calc_pos(self.side, arena);
     }
 
     fn update(&mut self) -> () {
@@ -377,46 +712,66 @@ impl Entity for Wall {
 // --------------------- WALL ---------------------
 
 // ===================== GOAL =====================
+#[derive(Clone)]
 struct Goal {
     pos: Vec2,
     size: Vec2,
     side: Side,
+    /// Four player arenas have corners instead of walls next to the goals
+    four_players: bool,
+    /// Placed by a level instead of filling the edge of the arena
+    area: Option<Block>,
     color: Color,
     excitement: f32,
 }
 
 impl Goal {
-    fn new(side: Side) -> Self {
+    fn new(side: Side, arena: &Arena, config: &Config, four_players: bool) -> Self {
         Goal {
-            pos: Goal::calc_pos(side),
-            size: Goal::calc_size(),
+            pos: Goal::calc_pos(side, arena),
+            size: Goal::calc_size(side, arena, config, four_players),
             side: side,
-            color: lerp_color(
-                &match side {
-                    Side::Left => COL_LEFT, 
-                    Side::Right => COL_RIGHT,
-                }, 
-                &COL_BACKGROUND, 
-                0.5
-            ),
+            four_players: four_players,
+            area: None,
+            color: lerp_color(&side.color(), &COL_BACKGROUND, 0.5),
             excitement: 0.,
         }
     }
 
-    fn calc_pos(side: Side) -> Vec2 {
-        Vec2 {
-            x: match side {
-                Side::Left  => 0.,
-                Side::Right => screen_width(),
-            },
-            y: screen_height() / 2.
+    fn fixed(side: Side, block: Block) -> Self {
+        Goal {
+            pos: block.center(),
+            size: block.size(),
+            side: side,
+            four_players: false,
+            area: Some(block),
+            color: lerp_color(&side.color(), &COL_BACKGROUND, 0.5),
+            excitement: 0.,
         }
     }
 
-    fn calc_size() -> Vec2 {
-        Vec2 {
-            x: 130.0,
-            y: screen_height() - 80.
+    fn calc_pos(side: Side, arena: &Arena) -> Vec2 {
+        match side {
+            Side::Left   => Vec2 { x: 0., y: arena.height / 2. },
+            Side::Right  => Vec2 { x: arena.width, y: arena.height / 2. },
+            Side::Top    => Vec2 { x: arena.width / 2., y: 0. },
+            Side::Bottom => Vec2 { x: arena.width / 2., y: arena.height },
+        }
+    }
+
+    fn calc_size(side: Side, arena: &Arena, config: &Config, four_players: bool) -> Vec2 {
+        let edge = match side.orientation() {
+            Orientation::Vertical   => arena.height,
+            Orientation::Horizontal => arena.width,
+        };
+        // The goal fills its edge between the walls or corners
+        let span = match four_players {
+            true  => edge - corner_size(config) * 2.,
+            false => edge - config.arena.wall_height,
+        };
+        match side.orientation() {
+            Orientation::Vertical   => Vec2 { x: config.arena.goal_width, y: span },
+            Orientation::Horizontal => Vec2 { x: span, y: config.arena.goal_width },
         }
     }
 }
@@ -452,55 +807,100 @@ impl Entity for Goal {
         self.calc_fill_color()
     }
 
-    fn resize(&mut self) -> () {
-        self.size = Goal::calc_size();
-        self.pos  = Goal::calc_pos(self.side);
+    fn resize(&mut self, arena: &Arena, config: &Config) -> () {
+        if self.area.is_none() {
+            self.size = Goal::calc_size(self.side, arena, config, self.four_players);
+            self.pos  = Goal::calc_pos(self.side, arena);
+        }
     }
 
     fn update(&mut self) -> () {
         self.lower_excitement();
     }
 }
+
+/// Half the edge length of the blocks in the corners of a four player arena,
+/// big enough that the paddles of neighbouring sides never meet
+fn corner_size(config: &Config) -> f32 {
+    config.player.edge_distance + config.player.width
+}
 // --------------------- GOAL ---------------------
 
 // ===================== BALL =====================
+/// How much faster the ball is drawn turning than its flight curves, so small spins still show
+const SPIN_ROTATION: f32 = 40.;
+
+#[derive(Clone)]
 struct Ball {
     pos: Vec2,
     prev_pos: Vec2,
     vel: Vec2,
+    /// Radians the velocity turns per tick, positive turns clockwise on screen
+    spin: f32,
+    /// Only for drawing, turns with the spin
+    angle: f32,
     size: Vec2,
     bounciness: f32,
     x_speed_limit: f32,
+    serve_speed: f32,
+    /// Fraction of the spin lost per second
+    spin_decay: f32,
+    /// How much spin pushes the ball along a wall it bounces off
+    spin_bounce: f32,
+    /// Barely visible while a ghost power-up is running
+    ghost: bool,
+    /// Player who touched the ball last, `None` right after the serve
+    last_hit: Option<Side>,
     color: Color,
     excitement: f32,
 }
 
 impl Ball {
-    fn new() -> Self {
+    fn new(arena: &Arena, config: &Config) -> Self {
         Ball {
-            pos: Vec2{ x: screen_width() / 2., y: screen_height() / 2. },
-            prev_pos: Vec2::ZERO,
+            pos: arena.center(),
+            prev_pos: arena.center(),
             vel: Vec2::ZERO,
-            size: Vec2{ x: 10.0, y: 10.0 },
-            bounciness: 0.9,
-            x_speed_limit: 8.,
+            spin: 0.,
+            angle: 0.,
+            size: Vec2{ x: config.ball.size, y: config.ball.size },
+            bounciness: config.ball.bounciness,
+            x_speed_limit: config.ball.x_speed_limit,
+            serve_speed: config.ball.serve_speed,
+            spin_decay: config.ball.spin_decay,
+            spin_bounce: config.ball.spin_bounce,
+            ghost: false,
+            last_hit: None,
             color: WHITE,
             excitement: 0.,
         }
     }
 
-    fn reset(&mut self) -> () {
-        self.pos = Vec2{ x: screen_width() / 2., y: screen_height() / 2. };
+    /// Applies new tuning values, the ball keeps flying
+    fn configure(&mut self, config: &Config) -> () {
+        self.size = Vec2{ x: config.ball.size, y: config.ball.size };
+        self.bounciness = config.ball.bounciness;
+        self.x_speed_limit = config.ball.x_speed_limit;
+        self.serve_speed = config.ball.serve_speed;
+        self.spin_decay = config.ball.spin_decay;
+        self.spin_bounce = config.ball.spin_bounce;
+    }
+
+    fn reset(&mut self, arena: &Arena) -> () {
+        self.pos = arena.center();
+        self.prev_pos = self.pos;
         self.vel = Vec2::ZERO;
+        self.spin = 0.;
+        self.last_hit = None;
     }
 
+    /// Serves the ball straight towards the given side
     fn start(&mut self, side: Side) -> () {
-        self.vel = Vec2 {
-            x: match side {
-                Side::Left => -3.,
-                Side::Right => 3.,
-            },
-            y: 0.
+        self.vel = match side {
+            Side::Left   => Vec2 { x: -self.serve_speed, y: 0. },
+            Side::Right  => Vec2 { x: self.serve_speed, y: 0. },
+            Side::Top    => Vec2 { x: 0., y: -self.serve_speed },
+            Side::Bottom => Vec2 { x: 0., y: self.serve_speed },
         };
     }
 
@@ -508,29 +908,75 @@ impl Ball {
         (self as &dyn Entity).check_collision(other)
     }
 
-    fn bounce(&mut self, surface_orientation: &Orientation) -> () {
-        // Bounce depending on surface orientation
-        match surface_orientation {
-            Orientation::Horizontal => {
-                self.pos.y = self.prev_pos.y;
-                self.vel.y *= -self.bounciness;
-            },
-            Orientation::Vertical   => {
-                self.pos.x = self.prev_pos.x;
-                self.vel.x *= -self.bounciness;
-
-                // Limit X speed because players could hit ball too fast
-                if self.vel.x > self.x_speed_limit {
-                    self.vel.x = self.x_speed_limit;
-                } else if self.vel.x < -self.x_speed_limit {
-                    self.vel.x = -self.x_speed_limit;
-                }
-            }
+    fn sweep_collision(&self, delta: Vec2, other: &dyn Entity) -> Option<Contact> {
+        (self as &dyn Entity).sweep_collision(delta, other)
+    }
+
+    /// Turned by its spin, so players can see which way it will curve.
+    /// `round` draws a circle instead of a square, it collides as one either way.
+    fn draw_shaped(&self, alpha: f32, round: bool) -> () {
+        let pos = self.get_draw_pos(alpha);
+        let size = self.get_size();
+        if round {
+            // A circle doesn't show turning by itself, a line from the middle does
+            let radius = size.x / 2.;
+            let tip = pos + Vec2::from_angle(self.angle) * radius;
+            draw_circle(pos.x, pos.y, radius, self.get_fill_color());
+            draw_circle_lines(pos.x, pos.y, radius, 4., self.get_stroke_color());
+            draw_line(pos.x, pos.y, tip.x, tip.y, 4., self.get_stroke_color());
+            return;
         }
+        let params = |color| DrawRectangleParams {
+            offset: Vec2::splat(0.5),
+            rotation: self.angle,
+            color: color,
+        };
+        draw_rectangle_ex(pos.x, pos.y, size.x, size.y, params(self.get_fill_color()));
+        draw_rectangle_lines_ex(pos.x, pos.y, size.x, size.y, 4., params(self.get_stroke_color()));
+    }
 
-        // Get excited
+    /// The ball's collider if it was at `pos`, for sweeping from where the tick started
+    fn collider_at(&self, pos: Vec2) -> Collider {
+        Collider::Circle { center: pos, radius: self.size.x / 2. }
+    }
+
+    /// Lets the spin bend the flight a little, the speed stays the same
+    fn curve(&mut self) -> () {
+        self.vel = trig::from_angle(self.spin).rotate(self.vel);
+        self.spin *= (1. - self.spin_decay * TICK_DT).max(0.);
+    }
+
+    /// Reflects the velocity off a surface facing `normal`, the ball is expected to already sit on it.
+    /// Spin grips the surface, pushing the ball along it and using up half of the spin.
+    fn bounce(&mut self, normal: Vec2) -> () {
+        self.bounce_off(normal, Vec2::ZERO);
+    }
+
+    /// Reflects the velocity off a surface facing any direction and moving at `surface_vel` arena units per tick.
+    /// The ball bounces relative to the surface, so a surface moving into it throws it back faster.
+    fn bounce_off(&mut self, normal: Vec2, surface_vel: Vec2) -> () {
+        let mut relative = self.vel - surface_vel;
+        let kick = normal.perp() * self.spin * relative.length() * self.spin_bounce;
+        self.spin *= 0.5;
+
+        // Only the part going into the surface turns around, a surface moving away is merely touched
+        let into = relative.dot(normal);
+        if into < 0. {
+            relative -= normal * into * (1. + self.bounciness);
+        }
+        self.vel = relative + surface_vel + kick;
+        self.limit_speed();
         self.trig_excited();
     }
+
+    /// Limit X speed because players could hit ball too fast
+    fn limit_speed(&mut self) -> () {
+        if self.vel.x > self.x_speed_limit {
+            self.vel.x = self.x_speed_limit;
+        } else if self.vel.x < -self.x_speed_limit {
+            self.vel.x = -self.x_speed_limit;
+        }
+    }
 }
 
 impl ExcitedThing for Ball {
@@ -548,6 +994,14 @@ impl ExcitedThing for Ball {
 }
 
 impl Entity for Ball {
+    fn draw(&self, alpha: f32) -> () {
+        self.draw_shaped(alpha, false);
+    }
+
+    fn get_draw_pos(&self, alpha: f32) -> Vec2 {
+        self.prev_pos.lerp(self.pos, alpha)
+    }
+
     fn get_pos(&self) -> Vec2 {
         self.pos
     }
@@ -556,26 +1010,35 @@ impl Entity for Ball {
         self.size
     }
 
+    fn get_collider(&self) -> Collider {
+        self.collider_at(self.pos)
+    }
+
     fn get_stroke_color(&self) -> Color {
-        self.calc_stroke_color()
+        let mut color = self.calc_stroke_color();
+        if self.ghost {
+            color.a *= 0.15;
+        }
+        color
     }
 
     fn get_fill_color(&self) -> Color {
-        self.calc_fill_color()
+        let mut color = self.calc_fill_color();
+        if self.ghost {
+            color.a *= 0.15;
+        }
+        color
     }
 
-    fn resize(&mut self) -> () {
-        self.pos  = Vec2 {
-            x: screen_width() / 2.,
-            y: screen_height() / 2.,
-        };
-        self.vel = Vec2::ZERO;
+    fn resize(&mut self, arena: &Arena, config: &Config) -> () {
+        self.configure(config);
+        self.reset(arena);
     }
 
     fn update(&mut self) -> () {
-        // Update position based on speed
+        // Remember where the tick started, the movement itself is swept by the simulation
         self.prev_pos = self.pos;
-        self.pos += self.vel;
+        self.angle = (self.angle + self.spin * SPIN_ROTATION) % std::f32::consts::TAU;
 
         self.lower_excitement();
     }
@@ -583,9 +1046,15 @@ impl Entity for Ball {
 // --------------------- BALL ---------------------
 
 // ===================== SCORE =====================
+#[derive(Clone)]
 struct Score {
-    left: u32,
-    right: u32,
+    /// Points in the current set, indexed by side
+    points: [u32; 4],
+    /// Sets won, indexed by side
+    sets: [u32; 4],
+    /// Goals each player may still concede in a four player match, unused with two players
+    lives: [u32; 4],
+    four_players: bool,
     color: Color,
     excitement_side: Side,
     excitement: f32,
@@ -594,56 +1063,138 @@ struct Score {
 impl Score {
     fn new() -> Self {
         Score {
-            left: 0,
-            right: 0,
+            points: [0; 4],
+            sets: [0; 4],
+            lives: [0; 4],
+            four_players: false,
             color: lerp_color(&COL_BACKGROUND, &COL_FOREGROUND, 0.5),
             excitement_side: Side::Left,
             excitement: 0.,
         }
     }
 
-    fn increment(&mut self, side: Side) -> () {
-        match side {
-            Side::Left  => self.left  += 1,
-            Side::Right => self.right += 1,
+    /// Score of a four player match, everybody starts with the same number of lives
+    fn four_way(lives: u32) -> Self {
+        Score {
+            lives: [lives; 4],
+            four_players: true,
+            ..Score::new()
         }
+    }
+
+    fn increment(&mut self, side: Side) -> () {
+        self.points[side as usize] += 1;
+        self.excitement_side = side;
+        self.trig_excited();
+    }
+
+    /// Takes a life from a player of a four player match
+    fn lose_life(&mut self, side: Side) -> () {
+        self.lives[side as usize] = self.lives[side as usize].saturating_sub(1);
         self.excitement_side = side;
         self.trig_excited();
     }
 
-    fn draw(&self) -> () {
+    /// Whether a player of a four player match has no lives left
+    fn is_out(&self, side: Side) -> bool {
+        self.four_players && self.lives[side as usize] == 0
+    }
+
+    /// Points of the given side and of its opponent in the current set
+    fn points(&self, side: Side) -> (u32, u32) {
+        (self.points[side as usize], self.points[side.opponent() as usize])
+    }
+
+    /// Sets won by the given side and by its opponent
+    fn sets(&self, side: Side) -> (u32, u32) {
+        (self.sets[side as usize], self.sets[side.opponent() as usize])
+    }
+
+    /// Books the set for the winner and starts the next one from zero
+    fn win_set(&mut self, side: Side) -> () {
+        self.sets[side as usize] += 1;
+        self.points = [0; 4];
+    }
+
+    /// Color of the number of a side, the side that scored last lights up
+    fn side_color(&self, side: Side) -> Color {
+        if side == self.excitement_side {
+            lerp_color(&self.color, &side.color(), self.excitement)
+        } else {
+            self.color
+        }
+    }
+
+    fn draw(&self, arena: &Arena) -> () {
+        if self.four_players {
+            self.draw_lives(arena);
+            return;
+        }
+
         // Assemble text
-        let text_left = self.left.to_string();
-        let text_right = self.right.to_string();
+        let text_left = self.points[Side::Left as usize].to_string();
+        let text_right = self.points[Side::Right as usize].to_string();
         let font_size = 250;
         let text_left_center = get_text_center(&text_left, None, font_size, 1., 0.);
         let text_right_center = get_text_center(&text_right, None, font_size, 1., 0.);
         
         // Get colors
-        let color_left = match self.excitement_side {
-            Side::Left  => lerp_color(&self.color, &COL_LEFT, self.excitement),
-            Side::Right => self.color,
-        };
-        let color_right = match self.excitement_side {
-            Side::Right => lerp_color(&self.color, &COL_RIGHT, self.excitement),
-            Side::Left  => self.color,
-        };
+        let color_left = self.side_color(Side::Left);
+        let color_right = self.side_color(Side::Right);
 
         // Draw
         draw_text(
             &text_left,
-            (screen_width() + text_left_center.y)/ 2. - text_left_center.x * 2.,
-            screen_height() / 2. - text_left_center.y,
+            (arena.width + text_left_center.y)/ 2. - text_left_center.x * 2.,
+            arena.height / 2. - text_left_center.y,
             font_size as f32,
             color_left
         );
         draw_text(
             &text_right,
-            (screen_width() - text_right_center.y) / 2.,
-            screen_height() / 2. - text_right_center.y,
+            (arena.width - text_right_center.y) / 2.,
+            arena.height / 2. - text_right_center.y,
             font_size as f32,
             color_right
         );
+
+        // Sets are only worth showing once somebody won one
+        let (sets_left, sets_right) = self.sets(Side::Left);
+        if sets_left + sets_right > 0 {
+            let text_sets = format!("SETS {} - {}", sets_left, sets_right);
+            let sets_font_size = 40;
+            let text_sets_center = get_text_center(&text_sets, None, sets_font_size, 1., 0.);
+            draw_text(
+                &text_sets,
+                arena.width / 2. - text_sets_center.x,
+                arena.height / 2. + font_size as f32 / 2.,
+                sets_font_size as f32,
+                self.color
+            );
+        }
+    }
+
+    /// Four player matches show the lives left, each number on the side of its player
+    fn draw_lives(&self, arena: &Arena) -> () {
+        let font_size = 150;
+        for side in Side::ALL {
+            let text = self.lives[side as usize].to_string();
+            let text_center = get_text_center(&text, None, font_size, 1., 0.);
+            let offset = match side {
+                Side::Left   => Vec2 { x: -0.2, y: 0. },
+                Side::Right  => Vec2 { x: 0.2, y: 0. },
+                Side::Top    => Vec2 { x: 0., y: -0.2 },
+                Side::Bottom => Vec2 { x: 0., y: 0.2 },
+            };
+            let center = Vec2 { x: arena.width, y: arena.height } * (Vec2::splat(0.5) + offset);
+            draw_text(
+                &text,
+                center.x - text_center.x,
+                center.y - text_center.y,
+                font_size as f32,
+                self.side_color(side)
+            );
+        }
     }
 
     fn update(&mut self) -> () {
@@ -672,118 +1223,240 @@ impl ExcitedThing for Score {
 }
 // --------------------- SCORE ---------------------
 
-// ===================== TIMER =====================
-enum TimerStatus {
-    Ticking,
-    Alarm,
-    Inactive,
+// ===================== SCHEDULE =====================
+/// Everything the simulation does after a delay
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scheduled {
+    /// Serves the ball towards the given side
+    BallStart(Side),
+    /// Puts the ball back in the middle after a goal, then counts down to the serve to the given side
+    BallReset(Side),
+    /// Multi-ball serves another ball
+    ExtraBall,
+    /// The time limit of the match is up
+    TimeUp,
 }
+// --------------------- SCHEDULE ---------------------
+
+// ===================== SIMULATION =====================
+/// The simulation always advances in steps of this length, independent of the frame rate.
+/// Speeds of the entities are given in arena units per tick.
+const TICK_RATE: f32 = 60.;
+const TICK_DT: f32 = 1. / TICK_RATE;
+
+/// Upper limit for a single frame, avoids a spiral of death after the window was stalled
+const MAX_FRAME_TIME: f32 = 0.25;
 
+/// Maximum number of surfaces the ball can bounce off within a single tick
+const MAX_BALL_CONTACTS: usize = 4;
+
+/// The wait before a serve is counted down in this many steps
+const COUNTDOWN_STEPS: u32 = 3;
+
+/// Solid entity the ball ran into
 #[derive(Debug, Copy, Clone)]
-enum TimerFunction {
-    BallStart(Side),
-    BallReset(Side),
+enum Obstacle {
+    Player(usize),
+    Wall,
+    Barrier(usize),
+    Mover(usize),
 }
 
-struct Timer {
-    status: TimerStatus,
-    function: Option<TimerFunction>,
-    time: f32,
+/// Overlaps up to this many arena units are left alone, so a ball resting on a surface doesn't get stuck on it
+const OVERLAP_SLOP: f32 = 0.01;
+
+/// Dimensions of the playing field, the simulation never asks the window for them
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Arena {
+    width: f32,
+    height: f32,
 }
 
-impl Timer {
-    fn new() -> Self {
-        Timer {
-            status: TimerStatus::Inactive,
-            time: 0.,
-            function: None,
+impl Arena {
+    fn new(width: f32, height: f32) -> Self {
+        Arena {
+            width: width,
+            height: height,
         }
     }
 
-    fn start(&mut self, function: TimerFunction) -> () {
-        self.time = 5.;
-        self.function = Some(function);
-        self.status = TimerStatus::Ticking;
+    fn from_config(config: &Config) -> Self {
+        Arena::new(config.arena.width, config.arena.height)
     }
-    
-    fn reset(&mut self) -> () {
-        self.function = None;
-        self.status = TimerStatus::Inactive;
+
+    fn center(&self) -> Vec2 {
+        Vec2 {
+            x: self.width / 2.,
+            y: self.height / 2.,
+        }
     }
+}
 
-    fn get_function_to_execute(&mut self) -> Option<TimerFunction> {
-        match self.status {
-            TimerStatus::Alarm => {
-                let func = self.function;
-                self.reset();
-                func
-            },
-            _ => None
+/// Intended movement of a single paddle during one tick
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+struct PaddleInput {
+    /// -1 moves up at full speed, 1 moves down at full speed, anything in between is slower.
+    /// Top and bottom paddles move left and right instead.
+    axis: f32,
+}
+
+impl PaddleInput {
+    /// Up and down CAN be pressed at the same time, in that case the player remains still
+    fn from_buttons(up: bool, down: bool) -> Self {
+        PaddleInput {
+            axis: (down as i32 - up as i32) as f32,
         }
     }
+}
 
-    fn is_ticking(&self) -> bool {
-        match self.status {
-            TimerStatus::Ticking => true,
-            _ => false
+/// Everything the simulation needs to know from the outside world for one tick
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+struct TickInput {
+    left: PaddleInput,
+    right: PaddleInput,
+    top: PaddleInput,
+    bottom: PaddleInput,
+}
+
+impl TickInput {
+    fn for_side(&self, side: Side) -> PaddleInput {
+        match side {
+            Side::Left   => self.left,
+            Side::Right  => self.right,
+            Side::Top    => self.top,
+            Side::Bottom => self.bottom,
         }
     }
 
-    fn update(&mut self) -> () {
-        match self.status {
-            TimerStatus::Ticking => {
-                if self.time > 0. {
-                    // Decrease time
-                    self.time -= 0.1;
-                } else {
-                    // Set new state
-                    self.status = TimerStatus::Alarm;
-                }
-            },
-            TimerStatus::Alarm => (),
-            TimerStatus::Inactive => (),
+    fn set(&mut self, side: Side, input: PaddleInput) -> () {
+        match side {
+            Side::Left   => self.left = input,
+            Side::Right  => self.right = input,
+            Side::Top    => self.top = input,
+            Side::Bottom => self.bottom = input,
         }
     }
 }
-// --------------------- TIMER ---------------------
 
-// ===================== GAME =====================
-struct MyGame {
+/// Things that happened during a tick, for everything outside the simulation to react to
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum SimEvent {
+    /// The ball was served towards the given side
+    Serve(Side),
+    /// The given side scored a point and the round is over
+    Goal(Side),
+    /// The given side scored a point with one ball while others are still in play
+    Point(Side),
+    /// The given side conceded in a four player match and the round is over
+    LifeLost(Side),
+    /// The given side lost its last life in a four player match, its goal is a wall now
+    Eliminated(Side),
+    MatchOver(Side),
+    /// The given side collected a power-up
+    PowerUp(PowerUpKind, Side),
+    /// The given side hit a ball at the given position, with the speed of the ball afterwards
+    /// (arena units per tick) and how far off center it was hit (-1 to 1)
+    Hit(Side, Vec2, f32, f32),
+    /// A ball bounced off a wall, a barrier or another ball at the given position
+    Bounce(Vec2),
+    /// Steps left before the next serve, counting down to 1
+    Countdown(u32),
+}
+
+/// The game itself without any window, keyboard or rendering attached
+#[derive(Clone)]
+struct Simulation {
+    arena: Arena,
+    /// Layout of the field, `None` is the usual one built from the config
+    level: Option<Level>,
+    config: Config,
+    rules: MatchRules,
+    /// Seed of everything random in this match, stored so a match can be reproduced
+    seed: u64,
     players: Vec<Player>,
     walls: Vec<Wall>,
+    /// Moving obstacles of the level
+    movers: Vec<Mover>,
     goals: Vec<Goal>,
-    ball: Ball,
+    /// Balls in play, there always is at least one
+    balls: Vec<Ball>,
     score: Score,
-    timer: Timer,
-    last_screen_size: Vec2,
+    /// Serves, extra balls and the match clock
+    schedule: Scheduler<Scheduled>,
+    /// Side the last or upcoming serve goes to
+    serving: Side,
+    power_ups: PowerUps,
+    stats: MatchStats,
+    /// Step of the serve countdown during the last tick
+    countdown: Option<u32>,
+    /// Seconds played in this match
+    elapsed: f32,
+    winner: Option<Side>,
+    /// Events of the last tick
+    events: Vec<SimEvent>,
 }
 
-impl MyGame {
-    pub fn new() -> MyGame {
-        let mut my_game = MyGame {
-            players: vec![
-                Player::new(Side::Left, &Controls{ up:KeyCode::W, down:KeyCode::S }),
-                Player::new(Side::Right, &Controls{ up:KeyCode::Up, down:KeyCode::Down })
-            ],
-            walls: vec![
-                Wall::new(WallSide::Top),
-                Wall::new(WallSide::Bottom),
-            ],
-            goals: vec![
-                Goal::new(Side::Left),
-                Goal::new(Side::Right),
+impl Simulation {
+    fn new(arena: Arena, config: Config, rules: MatchRules, seed: u64) -> Self {
+        // Four players replace the top and bottom walls by paddles and goals, only the corners stay solid
+        let sides = match rules.four_players {
+            true  => &Side::ALL[..],
+            false => &Side::ALL[..2],
+        };
+        let walls = match rules.four_players {
+            true => vec![
+                WallSide::Corner(Side::Left, Side::Top),
+                WallSide::Corner(Side::Right, Side::Top),
+                WallSide::Corner(Side::Left, Side::Bottom),
+                WallSide::Corner(Side::Right, Side::Bottom),
             ],
-            ball: Ball::new(),
-            score: Score::new(),
-            timer: Timer::new(),
-            last_screen_size: Vec2::from(screen_size()),
+            false => vec![WallSide::Top, WallSide::Bottom],
         };
 
-        // Start timer for first round
-        my_game.timer.start(TimerFunction::BallStart(Side::Left));
+        let mut sim = Simulation {
+            arena: arena,
+            level: None,
+            rules: rules,
+            seed: seed,
+            players: sides.iter().map(|&side| Player::new(side, &arena, &config)).collect(),
+            walls: walls.into_iter().map(|side| Wall::new(side, &arena, &config)).collect(),
+            movers: Vec::new(),
+            goals: sides.iter().map(|&side| Goal::new(side, &arena, &config, rules.four_players)).collect(),
+            balls: vec![Ball::new(&arena, &config)],
+            score: match rules.four_players {
+                true  => Score::four_way(rules.lives),
+                false => Score::new(),
+            },
+            schedule: Scheduler::new(),
+            serving: Side::Left,
+            power_ups: PowerUps::new(seed, &config, rules.four_players),
+            stats: MatchStats::new(),
+            countdown: None,
+            config: config,
+            elapsed: 0.,
+            winner: None,
+            events: Vec::new(),
+        };
+
+        // Start timer for first round and the match clock
+        sim.schedule.schedule(Scheduled::BallStart(sim.serving), sim.config.timer.duration);
+        if let Some(limit) = rules.time_limit {
+            sim.schedule.schedule(Scheduled::TimeUp, limit);
+        }
 
         // Return
-        my_game
+        sim
+    }
+
+    /// A two player match on a level, which brings its own arena size
+    fn with_level(level: Level, config: Config, rules: MatchRules, seed: u64) -> Self {
+        let mut sim = Simulation::new(level.arena(), config, MatchRules { four_players: false, ..rules }, seed);
+        sim.players = level.players(&sim.config);
+        sim.walls = level.walls();
+        sim.movers = level.movers();
+        sim.goals = level.goals();
+        sim.level = Some(level);
+        sim
     }
 
     fn get_entity_refs<'a>(&'a mut self, entity_refs: &mut Vec<&'a mut dyn Entity>) -> () {
@@ -797,56 +1470,436 @@ impl MyGame {
         for wall in &mut self.walls {
             entity_refs.push(wall as &mut dyn Entity);
         }
+        for mover in &mut self.movers {
+            entity_refs.push(mover as &mut dyn Entity);
+        }
+        for barrier in &mut self.power_ups.barriers {
+            entity_refs.push(barrier as &mut dyn Entity);
+        }
+        for pickup in &mut self.power_ups.pickups {
+            entity_refs.push(pickup as &mut dyn Entity);
+        }
         for player in &mut self.players {
             entity_refs.push(player as &mut dyn Entity);
         }
-        entity_refs.push(&mut self.ball as &mut dyn Entity);
+        for ball in &mut self.balls {
+            entity_refs.push(ball as &mut dyn Entity);
+        }
     }
 
-    /// Resizes and repositions every entity in case of the window being resized.
+    /// Resizes and repositions every entity to fit the new arena.
     /// Resets the current round but the score remains the same.
-    fn resize(&mut self) -> () {
+    fn resize(&mut self, arena: Arena) -> () {
+        self.arena = arena;
+        let config = self.config.clone();
+        self.power_ups.clear();
+        self.balls.truncate(1);
+
         // Collect all entities into a vector
         let mut entity_refs: Vec<&mut dyn Entity> = Vec::new();
         self.get_entity_refs(&mut entity_refs);
 
         // Call resize for each entity
         for entity_ref in entity_refs {
-            entity_ref.resize();
+            entity_ref.resize(&arena, &config);
         }
 
-        // Ball was reset during resize, needs to be started again
-        self.timer.start(TimerFunction::BallStart(Side::Left));
+        // Ball was reset during resize, needs to be started again (unless the match is over)
+        if self.winner.is_none() {
+            self.schedule.cancel_if(|event| matches!(event, Scheduled::BallReset(_) | Scheduled::ExtraBall));
+            self.schedule.schedule(Scheduled::BallStart(self.serving), self.config.timer.duration);
+        }
     }
-}
 
-trait EventHandler {
-    fn update(&mut self) -> ();
-    fn draw(&mut self) -> ();
-}
+    /// Swaps in new tuning values while the match keeps running.
+    /// Unlike a resize, the ball keeps flying and the paddles stay where they are.
+    fn reconfigure(&mut self, config: Config) -> () {
+        for player in &mut self.players {
+            player.configure(&self.arena, &config);
+        }
+        for wall in &mut self.walls {
+            wall.resize(&self.arena, &config);
+        }
+        for goal in &mut self.goals {
+            goal.resize(&self.arena, &config);
+        }
+        for ball in &mut self.balls {
+            ball.configure(&config);
+        }
+        if !config.multi_ball.enabled {
+            self.schedule.cancel(Scheduled::ExtraBall);
+        }
+        if !config.power_ups.enabled {
+            self.power_ups.clear();
+        }
+        self.config = config;
+        self.apply_power_ups();
+    }
 
-impl EventHandler for MyGame {
-    fn update(&mut self) -> () {
-        // Check if window has been resized since las iteration
-        let curr_screen_size = Vec2::from(screen_size());
-        if curr_screen_size != self.last_screen_size {
-            self.resize();
-            self.last_screen_size = curr_screen_size;
-        }
-
-        // Update timer and get events
-        self.timer.update();
-        match self.timer.get_function_to_execute() {
-            Some(TimerFunction::BallStart(side)) => {
-                // Start ball
-                self.ball.start(side)
+    /// Scales paddles and ball by whatever power-ups are running, starting from the config values
+    fn apply_power_ups(&mut self) -> () {
+        let modifiers = self.power_ups.modifiers();
+        for player in &mut self.players {
+            player.set_length(self.config.player.height * modifiers.paddle_height[player.side as usize]);
+            player.speed = self.config.player.speed * modifiers.paddle_speed[player.side as usize];
+        }
+        for ball in &mut self.balls {
+            ball.x_speed_limit = self.config.ball.x_speed_limit * modifiers.ball_speed;
+            ball.ghost = modifiers.ghost;
+        }
+    }
+
+    /// Serves an extra ball from the middle towards the player with the fewest balls coming at them.
+    /// Returns false if there are enough balls already or the middle is taken.
+    fn spawn_ball(&mut self) -> bool {
+        if self.balls.len() >= self.config.multi_ball.max_balls as usize {
+            return false;
+        }
+        let mut ball = Ball::new(&self.arena, &self.config);
+        if self.balls.iter().any(|other| ball.check_collision(other)) {
+            return false;
+        }
+
+        // Four player matches serve to everybody still in, ties go to the first side
+        let sides: &[Side] = match self.rules.four_players {
+            true  => &Side::ALL,
+            false => &[Side::Left, Side::Right],
+        };
+        let heading_at = |side: Side| self.balls.iter().filter(|other| other.vel.dot(side.normal()) < 0.).count();
+        let side = match sides.iter().copied().filter(|&side| !self.score.is_out(side)).min_by_key(|&side| heading_at(side)) {
+            Some(side) => side,
+            None => return false,
+        };
+        ball.start(side);
+        self.balls.push(ball);
+        self.events.push(SimEvent::Serve(side));
+        true
+    }
+
+    /// Collects the pickups the balls flew through this tick, for whoever hit them last
+    fn collect_power_ups(&mut self) -> () {
+        for b in 0..self.balls.len() {
+            let ball = &self.balls[b];
+            let side = match ball.last_hit {
+                Some(side) => side,
+                None => continue,
+            };
+            let travelled = ball.pos - ball.prev_pos;
+            let mut i = 0;
+            while i < self.power_ups.pickups.len() {
+                let pickup = &self.power_ups.pickups[i];
+                if ball.collider_at(ball.prev_pos).sweep(travelled, &pickup.get_collider()).is_none() {
+                    i += 1;
+                    continue;
+                }
+                let kind = self.power_ups.collect(i, side, &self.arena, &self.config);
+                match kind {
+                    PowerUpKind::SpeedUp   => self.balls[b].vel *= powerup::SPEED_UP_FACTOR,
+                    PowerUpKind::ExtraBall => { self.spawn_ball(); },
+                    _ => (),
+                }
+                if let Some(player) = self.players.iter_mut().find(|player| player.side == side) {
+                    player.trig_excited();
+                }
+                self.events.push(SimEvent::PowerUp(kind, side));
+                break;
+            }
+        }
+    }
+
+    /// Four player version of a goal: the side that conceded loses a life and whoever touched the ball
+    /// last gets a point. Losing the last life closes the goal for good.
+    fn lose_life(&mut self, index: usize, side: Side) -> () {
+        let scorer = self.balls[index].last_hit.filter(|&hitter| hitter != side);
+        self.score.lose_life(side);
+        if let Some(scorer) = scorer {
+            self.score.increment(scorer);
+        }
+        let eliminated = self.score.is_out(side);
+        let outcome = self.rules.evaluate(&self.score, self.time_up());
+        if self.balls.len() > 1 && !eliminated && matches!(outcome, Outcome::Continue) {
+            self.balls.remove(index);
+            if let Some(scorer) = scorer {
+                self.events.push(SimEvent::Point(scorer));
+            }
+            return;
+        }
+
+        // Round over, only the ball that scored is left to fly out of the field
+        let ball = self.balls.swap_remove(index);
+        self.balls = vec![ball];
+        self.power_ups.clear();
+        if eliminated {
+            self.eliminate(side);
+            self.events.push(SimEvent::Eliminated(side));
+        } else {
+            self.events.push(SimEvent::LifeLost(side));
+        }
+        match outcome {
+            Outcome::MatchWon(winner) => self.finish(winner),
+            _ => {
+                // The serve rule picks who is next, players who are out pass it on
+                let next = self.rules.serve.next_serve(self.serving, side, scorer, true);
+                self.serving = (0..4)
+                    .map(|i| Side::ALL[(next as usize + i) % 4])
+                    .find(|&candidate| !self.score.is_out(candidate))
+                    .unwrap_or(next);
+                self.end_round();
             },
-            Some(TimerFunction::BallReset(side)) => {
-                // Start ball with some delay
-                self.ball.reset();
-                self.timer.start(TimerFunction::BallStart(side));
+        }
+    }
+
+    /// Takes a player out of a four player match, their goal turns into a wall
+    fn eliminate(&mut self, side: Side) -> () {
+        self.players.retain(|player| player.side != side);
+        self.goals.retain(|goal| goal.side != side);
+        self.walls.push(Wall::new(WallSide::Closed(side), &self.arena, &self.config));
+
+        // The ball would be stuck in the new wall
+        self.balls[0].reset(&self.arena);
+    }
+
+    /// Ends the match, the ball stays frozen in the middle until a rematch is started
+    fn finish(&mut self, winner: Side) -> () {
+        self.winner = Some(winner);
+        self.balls.truncate(1);
+        self.balls[0].reset(&self.arena);
+        self.schedule.clear();
+        self.events.push(SimEvent::MatchOver(winner));
+    }
+
+    /// Sweeps a ball along its velocity and resolves every contact in order of occurrence.
+    /// Returns the side that missed the ball with the edge of its paddle, if the rules count that.
+    fn move_ball(&mut self, index: usize) -> Option<Side> {
+        let mut missed = None;
+        let ball = &mut self.balls[index];
+        ball.curve();
+        let mut remaining = 1.;
+        for _ in 0..MAX_BALL_CONTACTS {
+            let delta = ball.vel * remaining;
+
+            // Find the earliest solid contact, ignoring surfaces the ball is moving away from
+            let mut first: Option<(Contact, Obstacle)> = None;
+            let candidates = self.players.iter().enumerate()
+                .map(|(i, player)| (player as &dyn Entity, Obstacle::Player(i)))
+                .chain(self.walls.iter()
+                    .map(|wall| (wall as &dyn Entity, Obstacle::Wall)))
+                .chain(self.power_ups.barriers.iter().enumerate()
+                    .map(|(i, barrier)| (barrier as &dyn Entity, Obstacle::Barrier(i))));
+            for (entity, obstacle) in candidates {
+                if let Some(contact) = ball.sweep_collision(delta, entity) {
+                    let approaching = delta.dot(contact.normal) < 0.;
+                    let earlier = first.is_none_or(|(f, _)| contact.time < f.time);
+                    if approaching && earlier {
+                        first = Some((contact, obstacle));
+                    }
+                }
+            }
+            // Moving obstacles can also run into a ball that sits still, which pushes it out of them
+            for (i, mover) in self.movers.iter().enumerate() {
+                if let Some(contact) = mover.sweep(&ball.get_collider(), delta, remaining) {
+                    let approaching = mover.relative(delta, remaining).dot(contact.normal) < 0.;
+                    let earlier = first.is_none_or(|(f, _)| contact.time < f.time);
+                    if (approaching || contact.depth > OVERLAP_SLOP) && earlier {
+                        first = Some((contact, Obstacle::Mover(i)));
+                    }
+                }
+            }
+
+            match first {
+                Some((contact, obstacle)) => {
+                    // Travel up to the surface and react to it
+                    ball.pos += delta * contact.time;
+                    remaining *= 1. - contact.time;
+                    match obstacle {
+                        Obstacle::Player(i) => {
+                            // Only the face of the paddle aims, everything else just knocks the ball away
+                            let player = &mut self.players[i];
+                            let face = player.contact_face(contact.normal);
+                            if face == ContactFace::Edge && self.rules.edge_hits == EdgeRule::Miss {
+                                ball.bounce(contact.normal);
+                                missed = Some(player.side);
+                                self.events.push(SimEvent::Bounce(ball.pos));
+                                continue;
+                            }
+                            let rel_diff = match face {
+                                ContactFace::Front => player.hit(ball),
+                                _ => player.deflect(ball, contact.normal),
+                            };
+                            self.stats.record_hit(self.players[i].side, rel_diff);
+                            self.events.push(SimEvent::Hit(self.players[i].side, ball.pos, ball.vel.length(), rel_diff));
+                        },
+                        // Corners can be hit from any direction
+                        Obstacle::Wall => {
+                            ball.bounce(contact.normal);
+                            self.events.push(SimEvent::Bounce(ball.pos));
+                        },
+                        Obstacle::Barrier(i) => {
+                            ball.bounce(contact.normal);
+                            self.power_ups.barriers[i].trig_excited();
+                            self.events.push(SimEvent::Bounce(ball.pos));
+                        },
+                        Obstacle::Mover(i) => {
+                            let mover = &mut self.movers[i];
+                            ball.pos += contact.normal * contact.depth;
+                            let touching = ball.pos - contact.normal * ball.size.x / 2.;
+                            ball.bounce_off(contact.normal, mover.velocity_at(touching));
+                            mover.trig_excited();
+                            self.events.push(SimEvent::Bounce(touching));
+                        },
+                    }
+                },
+                None => {
+                    ball.pos += delta;
+                    break;
+                },
+            }
+        }
+        missed
+    }
+
+    /// Bounces balls that ran into each other during this tick. They weigh the same,
+    /// so they simply trade their speed along the axis they hit each other on.
+    fn collide_balls(&mut self) -> () {
+        for j in 1..self.balls.len() {
+            let (head, tail) = self.balls.split_at_mut(j);
+            let b = &mut tail[0];
+            for a in head {
+                // Sweep `a` relative to `b`, the normal points towards `a`
+                let (moved_a, moved_b) = (a.pos - a.prev_pos, b.pos - b.prev_pos);
+                let contact = match a.collider_at(a.prev_pos).sweep(moved_a - moved_b, &b.collider_at(b.prev_pos)) {
+                    Some(contact) => contact,
+                    None => continue,
+                };
+                let (speed_a, speed_b) = (a.vel.dot(contact.normal), b.vel.dot(contact.normal));
+                if speed_a - speed_b >= 0. {
+                    // Already moving apart
+                    continue;
+                }
+
+                // Both stop where they touched and fly on from there next tick
+                a.pos = a.prev_pos + moved_a * contact.time;
+                b.pos = b.prev_pos + moved_b * contact.time;
+                a.vel += (speed_b - speed_a) * contact.normal;
+                b.vel += (speed_a - speed_b) * contact.normal;
+                a.trig_excited();
+                b.trig_excited();
+                self.events.push(SimEvent::Bounce((a.pos + b.pos) / 2.));
+            }
+        }
+    }
+
+    /// Gives the point for a ball in `side`'s goal to the opponent. The round only ends
+    /// with the last ball, until then a ball that scored simply leaves the field.
+    fn score_goal(&mut self, index: usize, side: Side) -> () {
+        if self.rules.four_players {
+            self.lose_life(index, side);
+            return;
+        }
+
+        // Register score for the opponent and check if that decided anything
+        self.score.increment(side.opponent());
+        let outcome = self.rules.evaluate(&self.score, self.time_up());
+        if self.balls.len() > 1 && matches!(outcome, Outcome::Continue) {
+            self.balls.remove(index);
+            self.events.push(SimEvent::Point(side.opponent()));
+            return;
+        }
+
+        // Round over, only the ball that scored is left to fly out of the field
+        let ball = self.balls.swap_remove(index);
+        self.balls = vec![ball];
+        self.events.push(SimEvent::Goal(side.opponent()));
+        self.power_ups.clear();
+        self.serving = self.rules.serve.next_serve(self.serving, side, Some(side.opponent()), false);
+        match outcome {
+            Outcome::MatchWon(winner) => self.finish(winner),
+            Outcome::SetWon(winner) => {
+                self.score.win_set(winner);
+                self.end_round();
+            },
+            Outcome::Continue => self.end_round(),
+        }
+    }
+
+    /// Stops serving extra balls and brings the ball back for the next serve after a while
+    fn end_round(&mut self) -> () {
+        self.schedule.cancel(Scheduled::ExtraBall);
+        self.schedule.schedule(Scheduled::BallReset(self.serving), self.config.timer.duration);
+    }
+
+    /// Whether the time limit ran out, from then on the leader wins
+    fn time_up(&self) -> bool {
+        self.rules.time_limit.is_some() && !self.schedule.is_scheduled(Scheduled::TimeUp)
+    }
+
+    /// Between a goal and the next serve goals don't count
+    fn waiting_for_serve(&self) -> bool {
+        self.schedule.find(|event| matches!(event, Scheduled::BallStart(_) | Scheduled::BallReset(_))).is_some()
+    }
+
+    /// Side the coming serve goes to and the steps left of the wait before it,
+    /// `None` while no serve is coming up
+    fn serve_countdown(&self) -> Option<(Side, u32)> {
+        match self.schedule.find(|event| matches!(event, Scheduled::BallStart(_))) {
+            Some((Scheduled::BallStart(side), time_left, delay)) => {
+                let step = ((time_left / delay) * COUNTDOWN_STEPS as f32).ceil().max(1.) as u32;
+                Some((side, step))
             },
-            None => (),
+            _ => None,
+        }
+    }
+
+    /// Advances the game by a single tick of `TICK_DT` seconds
+    fn update(&mut self, input: &TickInput) -> () {
+        self.events.clear();
+
+        // Run whatever is due
+        for event in self.schedule.update(TICK_DT) {
+            match event {
+                Scheduled::BallStart(side) => {
+                    // Start ball
+                    self.balls[0].start(side);
+                    self.stats.record_serve();
+                    if self.config.multi_ball.enabled {
+                        self.schedule.schedule(Scheduled::ExtraBall, self.config.multi_ball.spawn_interval);
+                    }
+                    self.events.push(SimEvent::Serve(side));
+                },
+                Scheduled::BallReset(side) => {
+                    // Start ball with some delay
+                    self.balls.truncate(1);
+                    self.balls[0].reset(&self.arena);
+                    self.schedule.schedule(Scheduled::BallStart(side), self.config.timer.duration);
+                },
+                Scheduled::ExtraBall => {
+                    // Try again next tick while the middle is taken
+                    let delay = match self.spawn_ball() || self.balls.len() >= self.config.multi_ball.max_balls as usize {
+                        true  => self.config.multi_ball.spawn_interval,
+                        false => TICK_DT,
+                    };
+                    self.schedule.schedule(Scheduled::ExtraBall, delay);
+                },
+                // Without a leader the next goal decides
+                Scheduled::TimeUp => if let Some(leader) = MatchRules::leader(&self.score) {
+                    self.finish(leader);
+                },
+            }
+        }
+        let countdown = self.serve_countdown().map(|(_, step)| step);
+        if let Some(step) = countdown.filter(|&step| self.countdown != Some(step)) {
+            self.events.push(SimEvent::Countdown(step));
+        }
+        self.countdown = countdown;
+
+        // Power-ups only come and go while the ball is in play
+        self.power_ups.set_paused(self.waiting_for_serve() || self.winner.is_some());
+        self.power_ups.update(&self.arena, &self.config);
+        self.apply_power_ups();
+
+        // Hand over this tick's input to the players
+        for player in &mut self.players {
+            player.input = input.for_side(player.side);
         }
 
         // Collect all entities into a vector
@@ -862,63 +1915,1140 @@ impl EventHandler for MyGame {
         for player in &mut self.players {
             for wall in &self.walls {
                 if player.check_collision(wall) {
-                    let wall_offset = ( wall.size.y + player.size.y ) / 2.;
-                    player.pos.y = if player.pos.y < wall.pos.y {
-                        wall.pos.y - wall_offset
+                    // Push the paddle back along the axis it moves on
+                    let (pos, wall_pos, wall_offset) = match player.side.orientation() {
+                        Orientation::Vertical   => (&mut player.pos.y, wall.pos.y, (wall.size.y + player.size.y) / 2.),
+                        Orientation::Horizontal => (&mut player.pos.x, wall.pos.x, (wall.size.x + player.size.x) / 2.),
+                    };
+                    *pos = if *pos < wall_pos {
+                        wall_pos - wall_offset
                     } else {
-                        wall.pos.y + wall_offset
+                        wall_pos + wall_offset
                     };
                 }
             }
+            player.keep_on_track();
         }
         
-        // Check for hit
-        for player in &mut self.players {
-            if self.ball.check_collision(player) {
-                player.hit(&mut self.ball);
-            }
+        // Move the balls, hitting players and bouncing off walls and each other on the way
+        let mut missed = Vec::new();
+        for i in 0..self.balls.len() {
+            missed.push(self.move_ball(i));
+            self.stats.record_ball_speed(self.balls[i].vel.length());
         }
+        self.collide_balls();
+        self.collect_power_ups();
         
-        // Check for wall bounce
-        for wall in &mut self.walls {
-            if self.ball.check_collision(wall) {
-                self.ball.bounce(&Orientation::Horizontal);
+        // Check for score, every ball counts on its own
+        self.score.update();
+        let waiting = self.waiting_for_serve();
+        let mut scored = Vec::new();
+        for (i, ball) in self.balls.iter().enumerate() {
+            let travelled = ball.pos - ball.prev_pos;
+            // An edge miss counts like a goal, `missed` has no entry for balls `collect_power_ups` added after moving
+            let mut conceding_side = missed.get(i).copied().flatten().filter(|_| !waiting);
+            for goal in &mut self.goals {
+                let crossed = ball.collider_at(ball.prev_pos).sweep(travelled, &goal.get_collider()).is_some();
+                if crossed || ball.check_collision(goal) {
+                    goal.trig_excited();
+                    // Check if the round is over already, if so, the there is nothing to be done
+                    if !waiting {
+                        conceding_side = Some(goal.side);
+                    }
+                }
+            }
+            if let Some(side) = conceding_side {
+                scored.push((i, side));
             }
         }
-        
-        // Check for score
-        self.score.update();
-        for goal in &mut self.goals {
-            if self.ball.check_collision(goal) {
-                goal.trig_excited();
-                // Check if the timer is ticking already, if so, the there is nothing to be done
-                if !self.timer.is_ticking() {
-                    // Register score for the opponent and start timer for ball reset
-                    self.score.increment(match goal.side {
-                        Side::Left => Side::Right,
-                        Side::Right => Side::Left,
-                    });
-                    self.timer.start(TimerFunction::BallReset(goal.side));
+
+        // Balls get removed on the way, so start at the back
+        for (i, side) in scored.into_iter().rev() {
+            if self.waiting_for_serve() || self.winner.is_some() {
+                break;
+            }
+            self.score_goal(i, side);
+        }
+
+        // Count the time played, the schedule ends the match once the time is up
+        if self.winner.is_none() {
+            self.elapsed += TICK_DT;
+        }
+    }
+
+    /// Fingerprint of the game state, two simulations that went the same way have the same checksum
+    fn checksum(&self) -> u64 {
+        // FNV-1a over the raw bits of everything that moves or counts
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |value: u64| {
+            for byte in value.to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        };
+
+        for player in &self.players {
+            feed(player.pos.x.to_bits() as u64);
+            feed(player.pos.y.to_bits() as u64);
+        }
+        for ball in &self.balls {
+            feed(ball.pos.x.to_bits() as u64);
+            feed(ball.pos.y.to_bits() as u64);
+            feed(ball.vel.x.to_bits() as u64);
+            feed(ball.vel.y.to_bits() as u64);
+            feed(ball.spin.to_bits() as u64);
+        }
+        for mover in &self.movers {
+            feed(mover.get_pos().x.to_bits() as u64);
+            feed(mover.get_pos().y.to_bits() as u64);
+        }
+        feed(self.score.points[0] as u64);
+        feed(self.score.points[1] as u64);
+        feed(self.score.sets[0] as u64);
+        feed(self.score.sets[1] as u64);
+        if self.score.four_players {
+            feed(self.score.points[2] as u64);
+            feed(self.score.points[3] as u64);
+            for lives in self.score.lives {
+                feed(lives as u64);
+            }
+        }
+        feed(self.elapsed.to_bits() as u64);
+        self.power_ups.feed_checksum(&mut feed);
+        feed(match self.winner {
+            None => 0,
+            Some(side) => side as u64 + 1,
+        });
+        hash
+    }
+
+    /// Seconds left in a match with a time limit
+    fn time_left(&self) -> Option<f32> {
+        self.rules.time_limit.map(|_| self.schedule.time_left(Scheduled::TimeUp).unwrap_or(0.).max(0.))
+    }
+}
+// --------------------- SIMULATION ---------------------
+
+// ===================== GAME =====================
+/// A running match: the simulation together with whoever controls the paddles
+struct MyGame {
+    sim: Simulation,
+    /// One per paddle, indexed by side
+    controllers: Vec<Box<dyn PaddleController>>,
+    /// Difficulty of the CPU opponent, `None` for two human players
+    cpu: Option<Difficulty>,
+    bindings: Bindings,
+    gamepads: Rc<RefCell<Gamepads>>,
+    /// Replay of the match being recorded
+    recording: Option<Replay>,
+    /// Where recordings go, rematches are recorded next to it
+    record_path: Option<PathBuf>,
+    /// Replay being played instead of asking the controllers
+    playback: Option<Playback>,
+    /// Connection to the other player of a network game
+    net: Option<NetSession>,
+    accumulator: f32,
+    /// Simulation events of all ticks run during the last frame
+    events: Vec<SimEvent>,
+    effects: Effects,
+    /// Draws the balls as circles
+    round_ball: bool,
+}
+
+impl MyGame {
+    pub fn new(
+        config: Config,
+        rules: MatchRules,
+        level: Option<Level>,
+        cpu: Option<Difficulty>,
+        bindings: Bindings,
+        gamepads: Rc<RefCell<Gamepads>>,
+        record_path: Option<PathBuf>
+    ) -> MyGame {
+        let seed = (miniquad::date::now() * 1000.) as u64;
+        // Levels are made for two players, four player matches keep their square field
+        let sim = match level {
+            Some(level) if !rules.four_players => Simulation::with_level(level, config, rules, seed),
+            _ => Simulation::new(Arena::from_config(&config), config, rules, seed),
+        };
+
+        let controllers = match cpu {
+            Some(difficulty) => MyGame::single_player_controllers(&bindings, &gamepads, difficulty, seed),
+            None => Side::ALL.into_iter()
+                .map(|side| MyGame::human_controller(&bindings, &gamepads, side))
+                .collect(),
+        };
+
+        MyGame {
+            recording: record_path.as_ref().map(|_| Replay::new(&sim)),
+            record_path: record_path,
+            playback: None,
+            net: None,
+            sim: sim,
+            controllers: controllers,
+            cpu: cpu,
+            bindings: bindings,
+            gamepads: gamepads,
+            accumulator: 0.,
+            events: Vec::new(),
+            effects: Effects::new(EffectSettings::default()),
+            round_ball: false,
+        }
+    }
+
+    /// Plays a recorded match, the controllers are never asked
+    fn from_replay(replay: Replay, gamepads: Rc<RefCell<Gamepads>>) -> MyGame {
+        let mut my_game = MyGame::new(replay.config.clone(), replay.rules, None, None, Bindings::default(), gamepads, None);
+        my_game.sim = replay.simulation();
+        my_game.playback = Some(Playback::new(replay));
+        my_game
+    }
+
+    /// Plays against somebody on another machine, this side's paddle takes every input device
+    fn network(connection: Connection, bindings: Bindings, gamepads: Rc<RefCell<Gamepads>>, shim: Option<NetShim>) -> MyGame {
+        let setup = connection.setup.clone();
+        let mut my_game = MyGame::new(setup.config.clone(), setup.rules, None, None, bindings, gamepads, None);
+        my_game.sim = setup.simulation();
+
+        let remote = connection.side.opponent();
+        my_game.controllers[connection.side as usize] = MyGame::solo_human_controller(&my_game.bindings, &my_game.gamepads);
+        my_game.controllers[remote as usize] = Box::new(RemoteController);
+        my_game.net = Some(NetSession::from_connection(connection, &my_game.sim, shim, get_time()));
+        my_game
+    }
+
+    /// Network games can only be left, not restarted by one side
+    fn is_network(&self) -> bool {
+        self.net.is_some()
+    }
+
+    fn connection_lost(&self) -> bool {
+        self.net.as_ref().is_some_and(|net| net.is_disconnected(get_time()))
+    }
+
+    /// Keeps talking to the other player while the game isn't updated, e.g. in the pause menu
+    fn keep_alive(&mut self) -> () {
+        if let Some(net) = &mut self.net {
+            net.poll(&mut self.sim, get_time());
+        }
+    }
+
+    /// Lets the other player of a network game know that this one is gone
+    fn leave_network(&mut self) -> () {
+        if let Some(net) = &mut self.net {
+            net.leave(get_time());
+        }
+    }
+
+    /// Same players, same rules, fresh score
+    fn rematch(&self) -> MyGame {
+        match &self.playback {
+            Some(playback) => MyGame::from_replay(playback.replay().clone(), self.gamepads.clone()),
+            None => MyGame::new(
+                self.sim.config.clone(),
+                self.sim.rules,
+                self.sim.level.clone(),
+                self.cpu,
+                self.bindings.clone(),
+                self.gamepads.clone(),
+                self.record_path.clone()
+            ),
+        }
+    }
+
+    /// Applies a reloaded config to the running match, replays keep the config they were recorded with
+    fn reconfigure(&mut self, config: Config) -> () {
+        // Both sides of a network game must keep playing with the same values
+        if self.playback.is_some() || self.net.is_some() {
+            return;
+        }
+        if let Some(replay) = &mut self.recording {
+            replay.record_config(config.clone());
+        }
+        let arena = Arena::from_config(&config);
+        self.sim.reconfigure(config);
+
+        // A new arena size starts the round over, like the serve after a goal. Levels keep their own size.
+        if arena != self.sim.arena && self.sim.level.is_none() {
+            self.sim.resize(arena);
+            if let Some(replay) = &mut self.recording {
+                replay.record_resize(arena);
+            }
+        }
+    }
+
+    /// Writes the recorded replay to disk without overwriting older ones, later calls do nothing
+    fn save_recording(&mut self) -> () {
+        if let (Some(mut replay), Some(path)) = (self.recording.take(), &self.record_path) {
+            replay.finish(&self.sim);
+            let path = replay::free_path(path);
+            match replay.save(&path) {
+                Ok(()) => println!("Replay saved to {}", path.display()),
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+    }
+
+    /// Sums up the finished match for the history, `names` are the profiles sitting at each side.
+    /// Replays are never recorded twice.
+    fn match_record(&self, names: &[String; 4]) -> Option<MatchRecord> {
+        if self.playback.is_some() {
+            return None;
+        }
+        let mut names = names.clone();
+        if let Some(difficulty) = self.cpu {
+            for side in &Side::ALL[1..] {
+                names[*side as usize] = format!("CPU ({:?})", difficulty);
+            }
+        }
+        // Whoever plays on this machine sits at the left seat, no matter which paddle they got
+        if let Some(net) = &self.net {
+            let local = names[Side::Left as usize].clone();
+            names[net.side() as usize] = local;
+            names[net.side().opponent() as usize] = "Opponent".to_string();
+        }
+        Some(MatchRecord::new(&self.sim, &names, miniquad::date::now() as u64))
+    }
+
+    /// Forget about time that passed while the game wasn't running, e.g. during a pause
+    fn skip_time(&mut self) -> () {
+        self.accumulator = 0.;
+    }
+
+    /// Keys, gamepad and touch area of one side
+    fn human_controller(bindings: &Bindings, gamepads: &Rc<RefCell<Gamepads>>, side: Side) -> Box<dyn PaddleController> {
+        let controls = bindings.controls(side);
+        Box::new(CombinedController::new(vec![
+            Box::new(KeyboardController::new(controls.clone())),
+            Box::new(GamepadController::new(gamepads.clone(), controls.gamepad, bindings.dead_zone)),
+            Box::new(TouchController::new(Some(side))),
+        ]))
+    }
+
+    /// The only human playing on this machine may use every key, gamepad and the whole screen
+    fn solo_human_controller(bindings: &Bindings, gamepads: &Rc<RefCell<Gamepads>>) -> Box<dyn PaddleController> {
+        Box::new(CombinedController::new(vec![
+            Box::new(KeyboardController::new(bindings.left.clone())),
+            Box::new(KeyboardController::new(bindings.right.clone())),
+            Box::new(GamepadController::new(gamepads.clone(), None, bindings.dead_zone)),
+            Box::new(TouchController::new(None)),
+        ]))
+    }
+
+    /// The human plays left, the CPU takes every other paddle
+    fn single_player_controllers(bindings: &Bindings, gamepads: &Rc<RefCell<Gamepads>>, difficulty: Difficulty, seed: u64)
+        -> Vec<Box<dyn PaddleController>> {
+        let mut controllers = vec![MyGame::solo_human_controller(bindings, gamepads)];
+        for cpu_seed in seed..seed + 3 {
+            controllers.push(Box::new(AiController::new(difficulty, cpu_seed)));
+        }
+        controllers
+    }
+
+    /// Asks the controllers of all paddles what to do during the next tick
+    fn read_input(&mut self) -> TickInput {
+        let mut input = TickInput::default();
+        for side in Side::ALL {
+            input.set(side, self.controllers[side as usize].control(&self.sim, side));
+        }
+        input
+    }
+
+    /// Ticks of a network game, the session may correct the shown simulation at any time
+    fn update_network(&mut self) -> () {
+        let now = get_time();
+        let net = match &mut self.net {
+            Some(net) => net,
+            None => return,
+        };
+
+        net.poll(&mut self.sim, now);
+        while self.accumulator >= TICK_DT {
+            let local = self.controllers[net.side() as usize].control(&self.sim, net.side());
+            if !net.tick(&mut self.sim, local, now) {
+                // Waiting for the other player, don't rush to catch up once they are back
+                self.accumulator = 0.;
+                break;
+            }
+            self.effects.record_tick(&self.sim.balls);
+            self.accumulator -= TICK_DT;
+        }
+
+        // Only events that can't be rolled back anymore change the screen
+        self.events.extend(net.take_events());
+    }
+
+    /// Runs as many fixed ticks as fit into the time that passed since the last frame
+    fn update_local(&mut self) -> () {
+        while self.accumulator >= TICK_DT {
+            let input = match &mut self.playback {
+                // A finished replay stays on its last tick, without drawing ahead of it
+                Some(playback) if playback.is_finished() => {
+                    self.accumulator = TICK_DT;
+                    break;
+                },
+                Some(playback) => match playback.next(&mut self.sim) {
+                    Some(input) => input,
+                    None => break,
+                },
+                None => self.read_input(),
+            };
+            if let Some(replay) = &mut self.recording {
+                replay.record(input);
+            }
+            self.sim.update(&input);
+            self.events.extend_from_slice(&self.sim.events);
+            self.effects.record_tick(&self.sim.balls);
+            self.accumulator -= TICK_DT;
+
+            if let Some(playback) = &self.playback {
+                if playback.is_finished() {
+                    match playback.verify(&self.sim) {
+                        Some(true)  => println!("Replay finished, the game played out exactly as recorded"),
+                        Some(false) => println!("Replay finished, but the game did NOT play out as recorded"),
+                        None        => println!("Replay finished, it has no checksum to verify against"),
+                    }
                 }
             }
         }
     }
 
+    /// Steps left before a serve in the color of the side it goes to, with an arrow pointing there
+    fn draw_serve_countdown(&self, side: Side, step: u32) -> () {
+        let center = self.sim.arena.center();
+        let color = side.color();
+        let direction = match side {
+            Side::Left   => Vec2 { x: -1., y: 0. },
+            Side::Right  => Vec2 { x: 1., y: 0. },
+            Side::Top    => Vec2 { x: 0., y: -1. },
+            Side::Bottom => Vec2 { x: 0., y: 1. },
+        };
+
+        // Arrow from the ball towards the receiving side
+        let start = center + direction * 20.;
+        let tip = center + direction * 75.;
+        let head = tip - direction * 18.;
+        let across = direction.perp() * 12.;
+        draw_line(start.x, start.y, head.x, head.y, 5., color);
+        draw_triangle(tip, head + across, head - across, color);
+
+        // Number above the ball, or next to it when the arrow points up or down
+        let text = step.to_string();
+        let font_size = 80;
+        let text_center = get_text_center(&text, None, font_size, 1., 0.);
+        let pos = center + Vec2 { x: direction.y.abs(), y: -direction.x.abs() } * 80.;
+        draw_text(&text, pos.x - text_center.x, pos.y - text_center.y, font_size as f32, color);
+    }
+
+    fn draw_time_left(&self, time_left: f32) -> () {
+        let seconds = time_left.ceil() as u32;
+        let text_time = format!("{}:{:02}", seconds / 60, seconds % 60);
+        let font_size = 40;
+        let text_time_center = get_text_center(&text_time, None, font_size, 1., 0.);
+        draw_text(
+            &text_time,
+            self.sim.arena.width / 2. - text_time_center.x,
+            80.,
+            font_size as f32,
+            self.sim.score.color
+        );
+    }
+}
+
+trait EventHandler {
+    fn update(&mut self) -> ();
+    fn draw(&mut self) -> ();
+}
+
+impl EventHandler for MyGame {
+    fn update(&mut self) -> () {
+        // Run the ticks of this frame
+        self.events.clear();
+        let frame_time = get_frame_time().min(MAX_FRAME_TIME);
+        self.accumulator += frame_time;
+        match self.net.is_some() {
+            true  => self.update_network(),
+            false => self.update_local(),
+        }
+
+        // Effects only watch what happened, they never change the simulation
+        self.effects.react(&self.events, &self.sim.arena);
+        self.effects.update(frame_time);
+    }
+
     fn draw(&mut self) -> () {
         // Create canvas to draw on
         clear_background(COL_BACKGROUND);
 
+        // The arena is drawn in its own coordinates, scaled to fit the window.
+        // Shake everything of the game, menus on top of it stay calm.
+        let viewport = Viewport::fit(&self.sim.arena, Vec2::from(screen_size()));
+        set_camera(&viewport.camera(&self.sim.arena, self.effects.shake_offset()));
+
         // Draw score
-        self.score.draw();
+        self.sim.score.draw(&self.sim.arena);
 
-        // Collect all entities into a vector
-        let mut entity_refs: Vec<&mut dyn Entity> = Vec::new();
-        self.get_entity_refs(&mut entity_refs);
-        
-        // Call the draw function for each entity
-        for entity_ref in entity_refs {
-            entity_ref.draw();
+        // Trails go below the balls, particles on top of everything
+        self.effects.draw_trails(&self.sim.balls, self.round_ball);
+
+        // Call the draw function for each entity, interpolating between the last two ticks.
+        // Balls come last, they take the shape picked in the settings.
+        let alpha = self.accumulator / TICK_DT;
+        for goal in &self.sim.goals {
+            goal.draw(alpha);
+        }
+        for wall in &self.sim.walls {
+            wall.draw(alpha);
+        }
+        for mover in &self.sim.movers {
+            mover.draw(alpha);
+        }
+        for barrier in &self.sim.power_ups.barriers {
+            barrier.draw(alpha);
+        }
+        for pickup in &self.sim.power_ups.pickups {
+            pickup.draw(alpha);
         }
+        for player in &self.sim.players {
+            player.draw(alpha);
+        }
+        for ball in &self.sim.balls {
+            ball.draw_shaped(alpha, self.round_ball);
+        }
+        self.effects.draw_particles();
+
+        if let Some((side, step)) = self.sim.serve_countdown() {
+            self.draw_serve_countdown(side, step);
+        }
+        if let Some(time_left) = self.sim.time_left() {
+            self.draw_time_left(time_left);
+        }
+        set_default_camera();
+        viewport.draw_bars(COL_LETTERBOX);
     }
 }
 // --------------------- GAME ---------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rules::ServeRule;
+
+    fn arena() -> Arena {
+        Arena::new(800., 600.)
+    }
+
+    /// Runs the simulation until the serve timer has fired and the ball is moving
+    fn serve(sim: &mut Simulation) -> () {
+        while sim.balls[0].vel == Vec2::ZERO {
+            sim.update(&TickInput::default());
+        }
+    }
+
+    /// Events that change the course of the match, leaving out the ones that are only there for sound
+    fn match_events(sim: &Simulation) -> impl Iterator<Item = SimEvent> + '_ {
+        sim.events.iter().copied()
+            .filter(|event| !matches!(event, SimEvent::Hit(..) | SimEvent::Bounce(_) | SimEvent::Countdown(_)))
+    }
+
+    /// Input that makes a paddle chase the ball vertically
+    fn follow_ball(sim: &Simulation, side: Side) -> PaddleInput {
+        let player = sim.players.iter().find(|p| p.side == side).unwrap();
+        PaddleInput::from_buttons(sim.balls[0].pos.y < player.pos.y - 10., sim.balls[0].pos.y > player.pos.y + 10.)
+    }
+
+    #[test]
+    fn first_serve_goes_left() {
+        let mut sim = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        serve(&mut sim);
+        assert!(sim.balls[0].vel.x < 0.);
+        assert_eq!(sim.balls[0].vel.y, 0.);
+    }
+
+    #[test]
+    fn missed_ball_scores_for_opponent() {
+        let mut sim = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        serve(&mut sim);
+
+        // Move the left paddle out of the way and let the ball pass
+        let input = TickInput {
+            left: PaddleInput::from_buttons(true, false),
+            right: PaddleInput::default(),
+            ..TickInput::default()
+        };
+        for _ in 0..300 {
+            sim.update(&input);
+        }
+
+        assert_eq!(sim.score.points[0], 0);
+        assert_eq!(sim.score.points[1], 1);
+    }
+
+    #[test]
+    fn match_ends_and_freezes_the_ball() {
+        let rules = MatchRules { points_to_win: 2, win_by_two: false, ..MatchRules::default() };
+        let mut sim = Simulation::new(arena(), Config::default(), rules, 0);
+
+        // Left paddle hides in the corner, right wins every point
+        let input = TickInput {
+            left: PaddleInput::from_buttons(true, false),
+            right: PaddleInput::default(),
+            ..TickInput::default()
+        };
+        for _ in 0..2_000 {
+            sim.update(&input);
+        }
+
+        assert_eq!(sim.winner, Some(Side::Right));
+        assert_eq!(sim.score.points[1], 2);
+        assert_eq!(sim.balls[0].pos, arena().center());
+        assert_eq!(sim.balls[0].vel, Vec2::ZERO);
+    }
+
+    #[test]
+    fn serve_and_goal_are_reported_as_events() {
+        let mut sim = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        let mut events = Vec::new();
+
+        let input = TickInput {
+            left: PaddleInput::from_buttons(true, false),
+            right: PaddleInput::default(),
+            ..TickInput::default()
+        };
+        for _ in 0..300 {
+            sim.update(&input);
+            events.extend(match_events(&sim));
+        }
+
+        // The side that conceded gets the next serve
+        assert_eq!(events, vec![SimEvent::Serve(Side::Left), SimEvent::Goal(Side::Right), SimEvent::Serve(Side::Left)]);
+    }
+
+    #[test]
+    fn serve_rule_picks_the_side_after_a_goal_and_a_resize() {
+        let rules = MatchRules { serve: ServeRule::Winner, ..MatchRules::default() };
+        let mut sim = Simulation::new(arena(), Config::default(), rules, 0);
+        let input = TickInput { left: PaddleInput::from_buttons(true, false), ..TickInput::default() };
+        while sim.score.points[1] == 0 {
+            sim.update(&input);
+        }
+        assert_eq!(sim.serving, Side::Right);
+
+        // A resize serves again to the same side, counting down in its color
+        sim.resize(Arena::new(1000., 700.));
+        assert_eq!(sim.serve_countdown(), Some((Side::Right, COUNTDOWN_STEPS)));
+        serve(&mut sim);
+        assert!(sim.balls[0].vel.x > 0.);
+    }
+
+    #[test]
+    fn serve_waits_the_configured_seconds() {
+        let mut config = Config::default();
+        config.timer.duration = 2.;
+        let mut sim = Simulation::new(arena(), config, MatchRules::default(), 0);
+        let mut ticks = 0;
+        while sim.balls[0].vel == Vec2::ZERO {
+            sim.update(&TickInput::default());
+            ticks += 1;
+        }
+        assert!((ticks as f32 * TICK_DT - 2.).abs() <= TICK_DT * 1.01, "{} ticks", ticks);
+    }
+
+    #[test]
+    fn time_limit_ends_the_match() {
+        let rules = MatchRules { time_limit: Some(10.), ..MatchRules::default() };
+        let mut sim = Simulation::new(arena(), Config::default(), rules, 0);
+        sim.score.increment(Side::Left);
+
+        for _ in 0..(10. * TICK_RATE) as usize + 1 {
+            sim.update(&TickInput::default());
+        }
+
+        assert_eq!(sim.winner, Some(Side::Left));
+        assert_eq!(sim.time_left(), Some(0.));
+    }
+
+    #[test]
+    fn resize_keeps_score_and_moves_entities() {
+        let mut sim = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        sim.score.increment(Side::Right);
+        sim.resize(Arena::new(1000., 700.));
+
+        assert_eq!(sim.score.points[1], 1);
+        assert_eq!(sim.balls[0].pos, Vec2 { x: 500., y: 350. });
+        assert_eq!(sim.players[1].pos.x, 900.);
+    }
+
+    #[test]
+    fn reconfigure_keeps_the_ball_flying() {
+        let mut sim = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        serve(&mut sim);
+        for _ in 0..10 {
+            sim.update(&TickInput::default());
+        }
+        let (pos, vel) = (sim.balls[0].pos, sim.balls[0].vel);
+
+        let mut config = Config::default();
+        config.player.height = 200.;
+        config.ball.bounciness = 0.5;
+        sim.reconfigure(config);
+
+        assert_eq!((sim.balls[0].pos, sim.balls[0].vel), (pos, vel));
+        assert_eq!(sim.balls[0].bounciness, 0.5);
+        assert_eq!(sim.players[0].size.y, 200.);
+    }
+
+    #[test]
+    fn off_center_hit_curves_the_ball() {
+        let arena = arena();
+        let mut player = Player::new(Side::Left, &arena, &Config::default());
+        let mut ball = Ball::new(&arena, &Config::default());
+        ball.pos = player.pos + Vec2 { x: 10., y: 40. };
+        ball.vel = Vec2 { x: -3., y: 0. };
+
+        player.hit(&mut ball);
+
+        assert!(ball.vel.x > 0.);
+        assert!(ball.vel.y > 0.);
+        assert_eq!(ball.pos.x, player.pos.x + (player.size.x + ball.size.x) / 2.);
+    }
+
+    #[test]
+    fn wall_bounce_loses_energy() {
+        let mut ball = Ball::new(&arena(), &Config::default());
+        ball.vel = Vec2 { x: 2., y: -4. };
+        ball.bounce(Vec2 { x: 0., y: 1. });
+
+        assert_eq!(ball.vel.x, 2.);
+        assert_eq!(ball.vel.y, 4. * ball.bounciness);
+    }
+
+    #[test]
+    fn moving_paddle_spins_the_ball_towards_its_motion() {
+        let arena = arena();
+        let mut player = Player::new(Side::Left, &arena, &Config::default());
+        let mut ball = Ball::new(&arena, &Config::default());
+        ball.pos = player.pos + Vec2 { x: 10., y: 0. };
+        ball.vel = Vec2 { x: -3., y: 0. };
+
+        // The paddle moved down during this tick
+        player.prev_pos = player.pos - Vec2 { x: 0., y: 7. };
+        player.hit(&mut ball);
+        assert!(ball.spin > 0.);
+
+        // The ball flies right and bends down, keeping its speed
+        let speed = ball.vel.length();
+        let vel_y = ball.vel.y;
+        ball.curve();
+        assert!(ball.vel.y > vel_y);
+        assert!((ball.vel.length() - speed).abs() < 1e-4);
+
+        // A paddle standing still takes the spin away again
+        player.prev_pos = player.pos;
+        ball.vel = Vec2 { x: -3., y: 0. };
+        player.hit(&mut ball);
+        assert_eq!(ball.spin, 0.);
+    }
+
+    #[test]
+    fn spin_pushes_the_ball_along_the_wall() {
+        let mut ball = Ball::new(&arena(), &Config::default());
+        ball.vel = Vec2 { x: 0., y: 4. };
+        ball.spin = 0.01;
+
+        // Hitting the bottom wall spinning clockwise rolls it to the right
+        ball.bounce(Vec2 { x: 0., y: -1. });
+        assert!(ball.vel.x > 0.);
+        assert_eq!(ball.spin, 0.005);
+    }
+
+    #[test]
+    fn same_inputs_give_same_game() {
+        let mut sim_a = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        let mut sim_b = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+
+        for tick in 0..5_000 {
+            let input = TickInput {
+                left: follow_ball(&sim_a, Side::Left),
+                right: PaddleInput::from_buttons(tick % 90 < 45, tick % 90 >= 45),
+                ..TickInput::default()
+            };
+            sim_a.update(&input);
+            sim_b.update(&input);
+        }
+
+        assert_eq!(sim_a.balls[0].pos, sim_b.balls[0].pos);
+        assert_eq!(sim_a.balls[0].vel, sim_b.balls[0].vel);
+        assert_eq!(sim_a.score.points[0], sim_b.score.points[0]);
+        assert_eq!(sim_a.score.points[1], sim_b.score.points[1]);
+    }
+
+    #[test]
+    fn ball_is_interpolated_between_ticks() {
+        let mut ball = Ball::new(&arena(), &Config::default());
+        ball.vel = Vec2 { x: 4., y: 2. };
+        ball.update();
+        ball.pos += ball.vel;
+
+        assert_eq!(ball.get_draw_pos(0.), ball.prev_pos);
+        assert_eq!(ball.get_draw_pos(0.5), ball.prev_pos + Vec2 { x: 2., y: 1. });
+        assert_eq!(ball.get_draw_pos(1.), ball.pos);
+    }
+
+    #[test]
+    fn fast_ball_does_not_tunnel_through_paddle() {
+        let mut sim = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        serve(&mut sim);
+
+        // Way faster than the paddle is wide
+        let paddle_pos = sim.players[0].pos;
+        sim.balls[0].pos = paddle_pos + Vec2 { x: 60., y: 0. };
+        sim.balls[0].vel = Vec2 { x: -100., y: 0. };
+        sim.update(&TickInput::default());
+
+        assert!(sim.balls[0].vel.x > 0.);
+        assert!(sim.balls[0].pos.x > paddle_pos.x);
+        assert_eq!(sim.score.points[1], 0);
+    }
+
+    #[test]
+    fn ball_clipping_a_paddle_corner_glances_off() {
+        let mut sim = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        serve(&mut sim);
+
+        // Only the bottom edge of the ball catches the top corner of the paddle
+        let paddle = &sim.players[0];
+        let radius = sim.balls[0].size.x / 2.;
+        sim.balls[0].pos = paddle.pos + Vec2 { x: 40., y: -paddle.size.y / 2. - radius * 0.6 };
+        sim.balls[0].vel = Vec2 { x: -30., y: 0. };
+        sim.update(&TickInput::default());
+
+        let ball = &sim.balls[0];
+        assert!(ball.vel.x > 0.);
+        assert!(ball.vel.y < 0.);
+        assert_eq!(ball.last_hit, Some(Side::Left));
+    }
+
+    #[test]
+    fn contact_faces_of_a_paddle() {
+        let arena = arena();
+        let left = Player::new(Side::Left, &arena, &Config::default());
+        assert_eq!(left.contact_face(Vec2 { x: 1., y: 0. }), ContactFace::Front);
+        assert_eq!(left.contact_face(Vec2 { x: -1., y: 0. }), ContactFace::Back);
+        assert_eq!(left.contact_face(Vec2 { x: 0., y: -1. }), ContactFace::Edge);
+        assert_eq!(left.contact_face(Vec2 { x: 0., y: 1. }), ContactFace::Edge);
+        assert_eq!(left.contact_face(Vec2 { x: 1., y: -1. }.normalize()), ContactFace::Corner);
+
+        let top = Player::new(Side::Top, &arena, &Config::default());
+        assert_eq!(top.contact_face(Vec2 { x: 0., y: 1. }), ContactFace::Front);
+        assert_eq!(top.contact_face(Vec2 { x: 1., y: 0. }), ContactFace::Edge);
+    }
+
+    /// Drops a ball onto the top end of the left paddle
+    fn hit_paddle_edge(rules: MatchRules) -> Simulation {
+        let mut sim = Simulation::new(arena(), Config::default(), rules, 0);
+        serve(&mut sim);
+        let paddle = &sim.players[0];
+        let radius = sim.balls[0].size.x / 2.;
+        sim.balls[0].pos = paddle.pos + Vec2 { x: 0., y: -paddle.size.y / 2. - radius - 3. };
+        sim.balls[0].vel = Vec2 { x: -1., y: 10. };
+        sim.update(&TickInput::default());
+        sim
+    }
+
+    #[test]
+    fn ball_on_the_end_of_a_paddle_bounces_up() {
+        let sim = hit_paddle_edge(MatchRules::default());
+
+        // Sent back up where it came from, not pulled over to the face
+        let ball = &sim.balls[0];
+        assert!(ball.vel.y < 0.);
+        assert!(ball.vel.x < 0.);
+        assert!(ball.pos.x < sim.players[0].pos.x + sim.players[0].size.x / 2.);
+        assert_eq!(ball.last_hit, Some(Side::Left));
+        assert_eq!(sim.score.points, [0; 4]);
+    }
+
+    #[test]
+    fn edge_hits_can_count_as_a_miss() {
+        let sim = hit_paddle_edge(MatchRules { edge_hits: EdgeRule::Miss, ..MatchRules::default() });
+
+        assert!(sim.balls[0].vel.y < 0.);
+        assert_eq!(sim.balls[0].last_hit, None);
+        assert_eq!(sim.score.points[1], 1);
+    }
+
+    #[test]
+    fn ball_behind_a_paddle_bounces_back_to_the_goal() {
+        let mut sim = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        serve(&mut sim);
+        sim.balls[0].pos = sim.players[0].pos + Vec2 { x: -20., y: 0. };
+        sim.balls[0].vel = Vec2 { x: 10., y: 0. };
+        sim.update(&TickInput::default());
+
+        assert!(sim.balls[0].vel.x < 0.);
+        assert!(sim.balls[0].pos.x < sim.players[0].pos.x);
+    }
+
+    #[test]
+    fn fast_ball_does_not_tunnel_through_wall() {
+        let mut sim = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        serve(&mut sim);
+
+        sim.balls[0].pos = Vec2 { x: 400., y: 100. };
+        sim.balls[0].vel = Vec2 { x: 0., y: -150. };
+        sim.update(&TickInput::default());
+
+        assert!(sim.balls[0].vel.y > 0.);
+        assert!(sim.balls[0].pos.y >= 40. + sim.balls[0].size.y / 2.);
+    }
+
+    #[test]
+    fn long_rallies_stay_inside_the_arena() {
+        let mut sim = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        serve(&mut sim);
+
+        for _ in 0..20_000 {
+            let input = TickInput {
+                left: follow_ball(&sim, Side::Left),
+                right: follow_ball(&sim, Side::Right),
+                ..TickInput::default()
+            };
+            sim.update(&input);
+
+            // Paddles never leave the field and the ball stays between the walls
+            for player in &sim.players {
+                assert!(player.pos.y - player.size.y / 2. >= 40. - 0.01);
+                assert!(player.pos.y + player.size.y / 2. <= 560. + 0.01);
+            }
+            assert!(sim.balls[0].pos.y > 0. && sim.balls[0].pos.y < 600.);
+        }
+    }
+
+    #[test]
+    fn multi_ball_serves_extra_balls() {
+        let mut config = Config::default();
+        config.multi_ball.enabled = true;
+        config.multi_ball.max_balls = 2;
+        config.multi_ball.spawn_interval = 1.;
+        let mut sim = Simulation::new(arena(), config, MatchRules::default(), 0);
+        serve(&mut sim);
+
+        let mut serves = 0;
+        for _ in 0..(3. * TICK_RATE) as usize {
+            let input = TickInput {
+                left: follow_ball(&sim, Side::Left),
+                right: follow_ball(&sim, Side::Right),
+                ..TickInput::default()
+            };
+            sim.update(&input);
+            serves += sim.events.iter().filter(|event| matches!(event, SimEvent::Serve(_))).count();
+        }
+
+        // The first ball flies left, so the extra one goes right, and no more than the limit
+        assert_eq!(sim.balls.len(), 2);
+        assert_eq!(serves, 1);
+    }
+
+    #[test]
+    fn round_only_ends_with_the_last_ball() {
+        let mut sim = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        serve(&mut sim);
+
+        // One ball races into the left goal below the paddle, the other one bounces between the walls
+        sim.balls[0].pos = Vec2 { x: 150., y: 500. };
+        sim.balls[0].vel = Vec2 { x: -8., y: 0. };
+        let mut extra = Ball::new(&arena(), &Config::default());
+        extra.vel = Vec2 { x: 0., y: 3. };
+        sim.balls.push(extra);
+
+        let mut events = Vec::new();
+        for _ in 0..30 {
+            sim.update(&TickInput::default());
+            events.extend(match_events(&sim));
+        }
+        assert_eq!(events, vec![SimEvent::Point(Side::Right)]);
+        assert_eq!(sim.score.points[1], 1);
+        assert_eq!(sim.balls.len(), 1);
+        assert!(!sim.waiting_for_serve());
+
+        // The last ball ends the round
+        sim.balls[0].pos = Vec2 { x: 650., y: 500. };
+        sim.balls[0].vel = Vec2 { x: 8., y: 0. };
+        events.clear();
+        for _ in 0..30 {
+            sim.update(&TickInput::default());
+            events.extend(match_events(&sim));
+        }
+        assert_eq!(events, vec![SimEvent::Goal(Side::Left)]);
+        assert!(sim.waiting_for_serve());
+    }
+
+    fn four_players(lives: u32) -> Simulation {
+        let rules = MatchRules { four_players: true, lives: lives, ..MatchRules::default() };
+        Simulation::new(arena(), Config::default(), rules, 0)
+    }
+
+    #[test]
+    fn four_player_goal_costs_a_life_and_credits_the_hitter() {
+        let mut sim = four_players(3);
+        assert_eq!(sim.players.len(), 4);
+        serve(&mut sim);
+
+        // The top player sends the ball past the left paddle
+        sim.balls[0].pos = Vec2 { x: 150., y: 180. };
+        sim.balls[0].vel = Vec2 { x: -8., y: 0. };
+        sim.balls[0].last_hit = Some(Side::Top);
+        let mut events = Vec::new();
+        for _ in 0..30 {
+            sim.update(&TickInput::default());
+            events.extend(match_events(&sim));
+        }
+
+        assert_eq!(events, vec![SimEvent::LifeLost(Side::Left)]);
+        assert_eq!(sim.score.lives, [2, 3, 3, 3]);
+        assert_eq!(sim.score.points, [0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn extra_balls_go_to_players_still_in_with_the_fewest_balls() {
+        let mut sim = four_players(1);
+        serve(&mut sim);
+        sim.score.lives[Side::Left as usize] = 0;
+        sim.balls[0].pos = Vec2 { x: 100., y: 100. };
+        sim.balls[0].vel = Vec2 { x: 4., y: 0. };
+
+        assert!(sim.spawn_ball());
+        // Out of the way of the next one
+        sim.balls[1].pos.y -= 100.;
+        assert!(sim.spawn_ball());
+        assert_eq!(sim.balls[1].vel.dot(Side::Top.normal()), -sim.balls[1].serve_speed);
+        assert_eq!(sim.balls[2].vel.dot(Side::Bottom.normal()), -sim.balls[2].serve_speed);
+        assert!(sim.balls.iter().all(|ball| ball.vel.dot(Side::Left.normal()) >= 0.));
+    }
+
+    #[test]
+    fn eliminated_goal_turns_into_a_wall() {
+        let mut sim = four_players(1);
+        serve(&mut sim);
+        sim.balls[0].pos = Vec2 { x: 150., y: 180. };
+        sim.balls[0].vel = Vec2 { x: -8., y: 0. };
+        for _ in 0..30 {
+            sim.update(&TickInput::default());
+        }
+        assert!(sim.score.is_out(Side::Left));
+        assert!(sim.players.iter().all(|player| player.side != Side::Left));
+        assert!(sim.goals.iter().all(|goal| goal.side != Side::Left));
+
+        // The closed side now sends the ball back
+        while sim.balls[0].vel == Vec2::ZERO {
+            sim.update(&TickInput::default());
+        }
+        sim.balls[0].pos = Vec2 { x: 150., y: 180. };
+        sim.balls[0].vel = Vec2 { x: -8., y: 0. };
+        for _ in 0..30 {
+            sim.update(&TickInput::default());
+        }
+        assert!(sim.balls[0].vel.x > 0.);
+        assert_eq!(sim.winner, None);
+    }
+
+    #[test]
+    fn level_match_uses_its_tracks_and_obstacles() {
+        let level = Level::parse(include_str!("../levels/pillars.toml")).unwrap();
+        let mut sim = Simulation::with_level(level, Config::default(), MatchRules::default(), 0);
+        assert_eq!(sim.arena, Arena::new(1280., 720.));
+        serve(&mut sim);
+
+        // The paddle stops at the end of its track
+        let input = TickInput { left: PaddleInput::from_buttons(false, true), ..TickInput::default() };
+        for _ in 0..200 {
+            sim.update(&input);
+        }
+        assert_eq!(sim.players[0].pos.y, 360. + (640. - sim.players[0].size.y) / 2.);
+
+        // The upper obstacle sends the ball back
+        sim.balls[0].pos = Vec2 { x: 600., y: 180. };
+        sim.balls[0].vel = Vec2 { x: 8., y: 0. };
+        for _ in 0..5 {
+            sim.update(&TickInput::default());
+        }
+        assert!(sim.balls[0].vel.x < 0.);
+    }
+
+    #[test]
+    fn moving_obstacles_bounce_the_ball_off_their_surface() {
+        let mut level = Level::parse(include_str!("../levels/pillars.toml")).unwrap();
+        let obstacle = |shape, width, height, rotation| level::MovingObstacle {
+            shape: shape,
+            x: 400.,
+            y: 360.,
+            width: width,
+            height: height,
+            angle: 0.,
+            rotation: rotation,
+            motion: level::Motion::Still,
+        };
+        level.moving_obstacles.push(obstacle(level::Shape::Circle, 60., 60., 0.));
+        let mut sim = Simulation::with_level(level.clone(), Config::default(), MatchRules::default(), 0);
+        serve(&mut sim);
+
+        // Hitting the circle above its center sends the ball back and up
+        sim.balls[0].pos = Vec2 { x: 300., y: 345. };
+        sim.balls[0].vel = Vec2 { x: 8., y: 0. };
+        for _ in 0..20 {
+            sim.update(&TickInput::default());
+        }
+        assert!(sim.balls[0].vel.x < 0. && sim.balls[0].vel.y < 0., "{}", sim.balls[0].vel);
+
+        // A bar turning clockwise swings down into a ball resting below its right half
+        level.moving_obstacles[0] = obstacle(level::Shape::Box, 200., 20., 3.);
+        let mut sim = Simulation::with_level(level.clone(), Config::default(), MatchRules::default(), 0);
+        serve(&mut sim);
+        // Start the turn over, it kept turning while waiting for the serve
+        sim.movers = level.movers();
+        sim.balls[0].pos = Vec2 { x: 480., y: 380. };
+        sim.balls[0].vel = Vec2::ZERO;
+        for _ in 0..10 {
+            sim.update(&TickInput::default());
+        }
+        assert!(sim.balls[0].vel.y > 0. && sim.balls[0].pos.y > 380.);
+    }
+
+    #[test]
+    fn horizontal_paddle_sends_the_ball_back() {
+        let arena = arena();
+        let mut player = Player::new(Side::Top, &arena, &Config::default());
+        let mut ball = Ball::new(&arena, &Config::default());
+        ball.pos = player.pos + Vec2 { x: 40., y: 10. };
+        ball.vel = Vec2 { x: 0., y: -3. };
+
+        player.hit(&mut ball);
+
+        assert!(ball.vel.y > 0.);
+        assert!(ball.vel.x > 0.);
+        assert_eq!(ball.pos.y, player.pos.y + (player.size.y + ball.size.y) / 2.);
+        assert_eq!(ball.last_hit, Some(Side::Top));
+    }
+
+    #[test]
+    fn balls_bounce_off_each_other() {
+        let mut sim = Simulation::new(arena(), Config::default(), MatchRules::default(), 0);
+        serve(&mut sim);
+        sim.balls[0].pos = Vec2 { x: 380., y: 300. };
+        sim.balls[0].vel = Vec2 { x: 4., y: 1. };
+        let mut other = Ball::new(&arena(), &Config::default());
+        other.pos = Vec2 { x: 420., y: 300. };
+        other.vel = Vec2 { x: -2., y: 1. };
+        sim.balls.push(other);
+
+        for _ in 0..10 {
+            sim.update(&TickInput::default());
+        }
+
+        // Speeds along the line between their centers are traded, the rest stays
+        assert_eq!(sim.balls[0].vel, Vec2 { x: -2., y: 1. });
+        assert_eq!(sim.balls[1].vel, Vec2 { x: 4., y: 1. });
+        assert!(sim.balls[1].pos.x - sim.balls[0].pos.x >= sim.balls[0].size.x - 0.001);
+    }
+}
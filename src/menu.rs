@@ -0,0 +1,143 @@
+use macroquad::prelude::*;
+
+use crate::{COL_BACKGROUND, COL_FOREGROUND};
+
+const ITEM_FONT_SIZE: u16 = 40;
+const ITEM_HEIGHT: f32 = 60.;
+/// Fraction of the screen height a menu may take before its items shrink
+const MAX_MENU_HEIGHT: f32 = 0.7;
+
+/// What the player did with a menu during this frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MenuAction {
+    Choose(usize),
+    Decrease(usize),
+    Increase(usize),
+    Back,
+}
+
+/// Vertical list of items, navigated with the keyboard or by tapping/clicking an item
+pub struct Menu {
+    pub selected: usize,
+}
+
+impl Menu {
+    pub fn new() -> Self {
+        Menu {
+            selected: 0,
+        }
+    }
+
+    /// Long menus get smaller items so they still fit on the screen
+    fn item_height(item_count: usize) -> f32 {
+        ITEM_HEIGHT.min(screen_height() * MAX_MENU_HEIGHT / item_count.max(1) as f32)
+    }
+
+    /// Screen area of an item, shared by drawing and touch handling so they always agree
+    fn item_rect(index: usize, item_count: usize) -> Rect {
+        let width = screen_width() * 0.6;
+        let height = Menu::item_height(item_count);
+        let top = screen_height() * 0.55 - item_count as f32 * height / 2.;
+        Rect::new((screen_width() - width) / 2., top + index as f32 * height, width, height)
+    }
+
+    pub fn update(&mut self, items: &[String]) -> Option<MenuAction> {
+        if items.is_empty() {
+            return None;
+        }
+        self.selected = self.selected.min(items.len() - 1);
+
+        // Keyboard navigation
+        if is_key_pressed(KeyCode::Up) || is_key_pressed(KeyCode::W) {
+            self.selected = (self.selected + items.len() - 1) % items.len();
+        }
+        if is_key_pressed(KeyCode::Down) || is_key_pressed(KeyCode::S) {
+            self.selected = (self.selected + 1) % items.len();
+        }
+        if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::Space) {
+            return Some(MenuAction::Choose(self.selected));
+        }
+        if is_key_pressed(KeyCode::Left) || is_key_pressed(KeyCode::A) {
+            return Some(MenuAction::Decrease(self.selected));
+        }
+        if is_key_pressed(KeyCode::Right) || is_key_pressed(KeyCode::D) {
+            return Some(MenuAction::Increase(self.selected));
+        }
+        if is_key_pressed(KeyCode::Escape) || is_key_pressed(KeyCode::Backspace) {
+            return Some(MenuAction::Back);
+        }
+
+        // Tapping or clicking an item chooses it right away
+        let mut pressed: Vec<Vec2> = touches().iter()
+            .filter(|touch| touch.phase == TouchPhase::Started)
+            .map(|touch| touch.position)
+            .collect();
+        if is_mouse_button_pressed(MouseButton::Left) {
+            pressed.push(Vec2::from(mouse_position()));
+        }
+        for position in pressed {
+            let hit = (0..items.len()).find(|&i| Menu::item_rect(i, items.len()).contains(position));
+            if let Some(index) = hit {
+                self.selected = index;
+                return Some(MenuAction::Choose(index));
+            }
+        }
+
+        None
+    }
+
+    pub fn draw(&self, items: &[String]) -> () {
+        let font_size = (ITEM_FONT_SIZE as f32 * Menu::item_height(items.len()) / ITEM_HEIGHT) as u16;
+        for (i, item) in items.iter().enumerate() {
+            let rect = Menu::item_rect(i, items.len());
+            let text_center = get_text_center(item, None, font_size, 1., 0.);
+            let color = if i == self.selected {
+                draw_rectangle(rect.x, rect.y, rect.w, rect.h, with_alpha(COL_FOREGROUND, 0.4));
+                WHITE
+            } else {
+                COL_FOREGROUND
+            };
+            draw_text(
+                item,
+                rect.center().x - text_center.x,
+                rect.center().y - text_center.y,
+                font_size as f32,
+                color
+            );
+        }
+    }
+}
+
+/// Big centered headline above the menu
+pub fn draw_headline(text: &str, color: Color) -> () {
+    let font_size = 100;
+    let text_center = get_text_center(text, None, font_size, 1., 0.);
+    draw_text(
+        text,
+        screen_width() / 2. - text_center.x,
+        screen_height() * 0.2 - text_center.y,
+        font_size as f32,
+        color
+    );
+}
+
+/// Centered line of explanation, `y` is a fraction of the screen height
+pub fn draw_hint(text: &str, y: f32, color: Color) -> () {
+    let text_center = get_text_center(text, None, ITEM_FONT_SIZE, 1., 0.);
+    draw_text(
+        text,
+        screen_width() / 2. - text_center.x,
+        screen_height() * y - text_center.y,
+        ITEM_FONT_SIZE as f32,
+        color
+    );
+}
+
+/// Darkens whatever was drawn before, used to put a menu on top of the running game
+pub fn draw_dim() -> () {
+    draw_rectangle(0., 0., screen_width(), screen_height(), with_alpha(COL_BACKGROUND, 0.75));
+}
+
+fn with_alpha(color: Color, alpha: f32) -> Color {
+    Color { a: alpha, ..color }
+}
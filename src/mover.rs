@@ -0,0 +1,176 @@
+use macroquad::prelude::*;
+
+use crate::collision::{Collider, Contact};
+use crate::config::Config;
+use crate::level::{Motion, MovingObstacle, Shape};
+use crate::{lerp_color, Arena, Entity, ExcitedThing, COL_FOREGROUND, TICK_DT};
+
+/// Obstacle of a level that follows its path and turns, one step every tick
+#[derive(Clone)]
+pub struct Mover {
+    shape: Shape,
+    /// Center of the path
+    origin: Vec2,
+    size: Vec2,
+    motion: Motion,
+    start_angle: f32,
+    /// Radians per second
+    rotation: f32,
+    /// Seconds since the match started, everything else follows from it
+    time: f32,
+    pos: Vec2,
+    prev_pos: Vec2,
+    angle: f32,
+    prev_angle: f32,
+    color: Color,
+    excitement: f32,
+}
+
+impl Mover {
+    pub fn new(obstacle: &MovingObstacle) -> Self {
+        let mut mover = Mover {
+            shape: obstacle.shape,
+            origin: obstacle.center(),
+            size: obstacle.size(),
+            motion: obstacle.motion,
+            start_angle: obstacle.angle,
+            rotation: obstacle.rotation,
+            time: 0.,
+            pos: obstacle.center(),
+            prev_pos: obstacle.center(),
+            angle: obstacle.angle,
+            prev_angle: obstacle.angle,
+            color: lerp_color(&COL_FOREGROUND, &WHITE, 0.35),
+            excitement: 0.,
+        };
+        mover.place();
+        mover.prev_pos = mover.pos;
+        mover
+    }
+
+    fn place(&mut self) -> () {
+        self.pos = self.origin + self.motion.offset(self.time);
+        self.angle = self.start_angle + self.rotation * self.time;
+    }
+
+    /// Arena units the center moved during the last tick
+    fn moved(&self) -> Vec2 {
+        self.pos - self.prev_pos
+    }
+
+    /// Arena units per tick a point on the surface moves, turning included
+    pub fn velocity_at(&self, point: Vec2) -> Vec2 {
+        self.moved() + (point - self.pos).perp() * (self.angle - self.prev_angle)
+    }
+
+    /// Sweeps the ball along `delta` against the obstacle. The obstacle still has `remaining`
+    /// of this tick's movement ahead, so the ball is swept relative to it.
+    /// The obstacle is taken at its current angle, turning into the ball shows up as an overlap.
+    pub fn sweep(&self, ball: &Collider, delta: Vec2, remaining: f32) -> Option<Contact> {
+        let ahead = self.moved() * remaining;
+        ball.sweep(self.relative(delta, remaining), &self.get_collider().moved(-ahead))
+    }
+
+    /// Movement of the ball relative to the obstacle, only approaching balls bounce
+    pub fn relative(&self, delta: Vec2, remaining: f32) -> Vec2 {
+        delta - self.moved() * remaining
+    }
+}
+
+impl ExcitedThing for Mover {
+    fn get_base_color(&self) -> Color {
+        self.color
+    }
+
+    fn get_excitement(&self) -> f32 {
+        self.excitement
+    }
+
+    fn get_excitement_ref(&mut self) -> &mut f32 {
+        &mut self.excitement
+    }
+}
+
+impl Entity for Mover {
+    fn draw(&self, alpha: f32) -> () {
+        let pos = self.get_draw_pos(alpha);
+        match self.shape {
+            Shape::Box => {
+                let params = |color| DrawRectangleParams {
+                    offset: Vec2::splat(0.5),
+                    rotation: self.prev_angle + (self.angle - self.prev_angle) * alpha,
+                    color: color,
+                };
+                draw_rectangle_ex(pos.x, pos.y, self.size.x, self.size.y, params(self.get_fill_color()));
+                draw_rectangle_lines_ex(pos.x, pos.y, self.size.x, self.size.y, 4., params(self.get_stroke_color()));
+            },
+            Shape::Circle => {
+                draw_circle(pos.x, pos.y, self.size.x / 2., self.get_fill_color());
+                draw_circle_lines(pos.x, pos.y, self.size.x / 2., 4., self.get_stroke_color());
+            },
+        }
+    }
+
+    fn get_draw_pos(&self, alpha: f32) -> Vec2 {
+        self.prev_pos.lerp(self.pos, alpha)
+    }
+
+    fn get_pos(&self) -> Vec2 {
+        self.pos
+    }
+
+    fn get_size(&self) -> Vec2 {
+        self.size
+    }
+
+    fn get_collider(&self) -> Collider {
+        match self.shape {
+            Shape::Box    => Collider::Obb { center: self.pos, half: self.size / 2., angle: self.angle },
+            Shape::Circle => Collider::Circle { center: self.pos, radius: self.size.x / 2. },
+        }
+    }
+
+    fn get_stroke_color(&self) -> Color {
+        self.calc_stroke_color()
+    }
+
+    fn get_fill_color(&self) -> Color {
+        self.calc_fill_color()
+    }
+
+    /// Levels bring their own arena, nothing to do
+    fn resize(&mut self, _arena: &Arena, _config: &Config) -> () {}
+
+    fn update(&mut self) -> () {
+        self.prev_pos = self.pos;
+        self.prev_angle = self.angle;
+        self.time += TICK_DT;
+        self.place();
+        self.lower_excitement();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surface_moves_with_the_path_and_the_turn() {
+        let obstacle = MovingObstacle {
+            shape: Shape::Box,
+            x: 100.,
+            y: 100.,
+            width: 80.,
+            height: 10.,
+            angle: 0.,
+            rotation: 1.,
+            motion: Motion::Line { dx: 30., dy: 0., period: 2. },
+        };
+        let mut mover = Mover::new(&obstacle);
+        mover.update();
+        assert!((mover.moved() - Vec2::new(60. * TICK_DT, 0.)).length() < 1e-4);
+        // The right tip of the bar swings down on top of moving along
+        let tip = mover.velocity_at(mover.pos + Vec2::new(40., 0.));
+        assert!((tip - Vec2::new(60. * TICK_DT, 40. * TICK_DT)).length() < 1e-4);
+    }
+}
@@ -0,0 +1,734 @@
+use std::collections::VecDeque;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use crate::config::Config;
+use crate::rng::Rng;
+use crate::rules::{EdgeRule, MatchRules, ServeRule};
+use crate::{Arena, PaddleInput, Side, SimEvent, Simulation, TickInput};
+
+/// Packets of other versions are refused, peers have to run the same game
+pub const NET_VERSION: u8 = 8;
+/// Port a host listens on unless told otherwise
+pub const DEFAULT_PORT: u16 = 7878;
+
+const MAGIC: &[u8; 4] = b"RPNG";
+/// Largest packet that is ever sent, the config text is the biggest part
+const MAX_PACKET_SIZE: usize = 4096;
+/// Ticks the local game may run ahead of the last tick both inputs are known for
+const MAX_ROLLBACK_TICKS: usize = 30;
+/// Inputs sent at most in one packet, older unacknowledged inputs follow in the next ones
+const MAX_INPUTS_PER_PACKET: usize = 64;
+/// Seconds without hearing from the peer before the connection counts as lost
+const TIMEOUT: f64 = 5.;
+/// Seconds between two hello packets of a joining player
+const HELLO_INTERVAL: f64 = 0.25;
+/// Confirmed checksums kept to compare with the peer's
+const CHECKSUM_HISTORY: usize = 256;
+
+/// Everything both peers need to start the exact same simulation
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchSetup {
+    pub seed: u64,
+    pub arena: Arena,
+    pub rules: MatchRules,
+    pub config: Config,
+}
+
+impl MatchSetup {
+    pub fn simulation(&self) -> Simulation {
+        Simulation::new(self.arena, self.config.clone(), self.rules, self.seed)
+    }
+}
+
+// ===================== PROTOCOL =====================
+#[derive(Debug, Clone, PartialEq)]
+enum Packet {
+    /// A player wants to join
+    Hello,
+    /// The host accepts and tells how the match is played
+    Welcome(MatchSetup),
+    /// Inputs of the sender starting at `first_tick`, acknowledging the receiver's inputs up to `ack`
+    Inputs {
+        first_tick: u32,
+        inputs: Vec<PaddleInput>,
+        ack: u32,
+        /// Checksum of the sender's simulation after its last confirmed tick
+        confirmed_tick: u32,
+        checksum: u64,
+    },
+    /// The peer left
+    Bye,
+}
+
+impl Packet {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(NET_VERSION);
+        match self {
+            Packet::Hello => bytes.push(0),
+            Packet::Welcome(setup) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&setup.seed.to_le_bytes());
+                bytes.extend_from_slice(&setup.arena.width.to_le_bytes());
+                bytes.extend_from_slice(&setup.arena.height.to_le_bytes());
+                bytes.extend_from_slice(&setup.rules.points_to_win.to_le_bytes());
+                bytes.push(setup.rules.win_by_two as u8);
+                bytes.extend_from_slice(&setup.rules.time_limit.unwrap_or(0.).to_le_bytes());
+                bytes.extend_from_slice(&setup.rules.best_of.to_le_bytes());
+                bytes.push(setup.rules.serve as u8);
+                bytes.push(setup.rules.edge_hits as u8);
+                bytes.extend_from_slice(setup.config.to_text().as_bytes());
+            },
+            Packet::Inputs { first_tick, inputs, ack, confirmed_tick, checksum } => {
+                bytes.push(2);
+                bytes.extend_from_slice(&first_tick.to_le_bytes());
+                bytes.extend_from_slice(&ack.to_le_bytes());
+                bytes.extend_from_slice(&confirmed_tick.to_le_bytes());
+                bytes.extend_from_slice(&checksum.to_le_bytes());
+                bytes.push(inputs.len() as u8);
+                for input in inputs {
+                    bytes.extend_from_slice(&input.axis.to_le_bytes());
+                }
+            },
+            Packet::Bye => bytes.push(3),
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Packet, String> {
+        let mut reader = Reader { bytes: bytes };
+        if reader.take(4) != Some(&MAGIC[..]) {
+            return Err("Not a RustedPong packet".to_string());
+        }
+        let version = reader.u8().ok_or("Packet is too short")?;
+        if version != NET_VERSION {
+            return Err(format!("Peer uses network version {}, expected {}", version, NET_VERSION));
+        }
+
+        let broken = || "Broken packet".to_string();
+        match reader.u8().ok_or_else(broken)? {
+            0 => Ok(Packet::Hello),
+            1 => {
+                let seed = reader.u64().ok_or_else(broken)?;
+                let arena = Arena::new(reader.f32().ok_or_else(broken)?, reader.f32().ok_or_else(broken)?);
+                let rules = MatchRules {
+                    points_to_win: reader.u32().ok_or_else(broken)?,
+                    win_by_two: reader.u8().ok_or_else(broken)? == 1,
+                    time_limit: Some(reader.f32().ok_or_else(broken)?).filter(|&limit| limit > 0.),
+                    best_of: reader.u32().ok_or_else(broken)?,
+                    serve: match reader.u8().ok_or_else(broken)? {
+                        0 => ServeRule::Alternate,
+                        1 => ServeRule::Loser,
+                        2 => ServeRule::Winner,
+                        _ => return Err(broken()),
+                    },
+                    edge_hits: match reader.u8().ok_or_else(broken)? {
+                        0 => EdgeRule::Bounce,
+                        1 => EdgeRule::Miss,
+                        _ => return Err(broken()),
+                    },
+                    // Network matches are always two players
+                    ..MatchRules::default()
+                };
+                rules.validate()?;
+                let text = std::str::from_utf8(reader.bytes).map_err(|_| broken())?;
+                let config = Config::parse(text)?;
+                Ok(Packet::Welcome(MatchSetup { seed: seed, arena: arena, rules: rules, config: config }))
+            },
+            2 => {
+                let first_tick = reader.u32().ok_or_else(broken)?;
+                let ack = reader.u32().ok_or_else(broken)?;
+                let confirmed_tick = reader.u32().ok_or_else(broken)?;
+                let checksum = reader.u64().ok_or_else(broken)?;
+                let count = reader.u8().ok_or_else(broken)?;
+                let inputs = (0..count)
+                    .map(|_| reader.f32().map(|axis| PaddleInput { axis: axis }))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(broken)?;
+                Ok(Packet::Inputs {
+                    first_tick: first_tick,
+                    inputs: inputs,
+                    ack: ack,
+                    confirmed_tick: confirmed_tick,
+                    checksum: checksum,
+                })
+            },
+            3 => Ok(Packet::Bye),
+            _ => Err(broken()),
+        }
+    }
+}
+
+/// Reads little endian numbers from the front of a packet
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < count {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.u32().map(f32::from_bits)
+    }
+}
+// --------------------- PROTOCOL ---------------------
+
+// ===================== TRANSPORT =====================
+/// Sends and receives whole packets, losing some of them is fine
+pub trait Transport {
+    fn send(&mut self, packet: &[u8], now: f64) -> ();
+    fn receive(&mut self, now: f64) -> Option<Vec<u8>>;
+}
+
+/// Talks to one peer over a non-blocking UDP socket
+pub struct UdpTransport {
+    socket: UdpSocket,
+    peer: SocketAddr,
+}
+
+impl UdpTransport {
+    pub fn new(socket: UdpSocket, peer: SocketAddr) -> Self {
+        UdpTransport {
+            socket: socket,
+            peer: peer,
+        }
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, packet: &[u8], _now: f64) -> () {
+        // A packet that can't be sent right now is just another lost packet
+        let _ = self.socket.send_to(packet, self.peer);
+    }
+
+    fn receive(&mut self, _now: f64) -> Option<Vec<u8>> {
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                // Packets of strangers are dropped
+                Ok((size, from)) if from == self.peer => return Some(buffer[..size].to_vec()),
+                Ok(_) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+/// How much worse a `LossyLink` makes the connection
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NetShim {
+    /// Seconds every packet is held back, up to half of it again is added at random
+    pub latency: f64,
+    /// Share of packets that get lost
+    pub loss: f32,
+}
+
+/// Makes a connection worse on purpose: packets are delayed, shuffled and lost.
+/// Wraps the sending side, so both peers need one to make both directions bad.
+pub struct LossyLink<T: Transport> {
+    inner: T,
+    /// Seconds every packet is held back
+    latency: f64,
+    /// Up to this many seconds are added at random, which also reorders packets
+    jitter: f64,
+    /// Share of packets that never arrive
+    loss: f32,
+    rng: Rng,
+    /// Packets waiting to be sent and when
+    queue: Vec<(f64, Vec<u8>)>,
+}
+
+impl<T: Transport> LossyLink<T> {
+    pub fn new(inner: T, latency: f64, jitter: f64, loss: f32, seed: u64) -> Self {
+        LossyLink {
+            inner: inner,
+            latency: latency,
+            jitter: jitter,
+            loss: loss,
+            rng: Rng::new(seed),
+            queue: Vec::new(),
+        }
+    }
+
+    fn flush(&mut self, now: f64) -> () {
+        self.queue.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let due = self.queue.iter().take_while(|(at, _)| *at <= now).count();
+        for (_, packet) in self.queue.drain(..due) {
+            self.inner.send(&packet, now);
+        }
+    }
+}
+
+impl<T: Transport> Transport for LossyLink<T> {
+    fn send(&mut self, packet: &[u8], now: f64) -> () {
+        if self.rng.next_f32() >= self.loss {
+            let delay = self.latency + self.rng.range(0., self.jitter as f32) as f64;
+            self.queue.push((now + delay, packet.to_vec()));
+        }
+        self.flush(now);
+    }
+
+    fn receive(&mut self, now: f64) -> Option<Vec<u8>> {
+        self.flush(now);
+        self.inner.receive(now)
+    }
+}
+// --------------------- TRANSPORT ---------------------
+
+// ===================== CONNECTING =====================
+/// A finished handshake, ready to start a `NetSession`
+pub struct Connection {
+    pub transport: UdpTransport,
+    pub setup: MatchSetup,
+    /// Paddle of this machine, the host plays left
+    pub side: Side,
+}
+
+/// Host waiting for a player or player waiting for the host to answer
+pub struct Connector {
+    socket: UdpSocket,
+    /// Match the host offers, `None` while joining
+    setup: Option<MatchSetup>,
+    /// Host a joining player talks to
+    host: Option<SocketAddr>,
+    last_hello: f64,
+}
+
+impl Connector {
+    /// Listens for a player on the given port, 0 picks a free one
+    pub fn host(port: u16, setup: MatchSetup) -> Result<Connector, String> {
+        let socket = UdpSocket::bind(("0.0.0.0", port)).map_err(|err| format!("Could not listen on port {}: {}", port, err))?;
+        socket.set_nonblocking(true).map_err(|err| err.to_string())?;
+        Ok(Connector {
+            socket: socket,
+            setup: Some(setup),
+            host: None,
+            last_hello: f64::NEG_INFINITY,
+        })
+    }
+
+    /// Joins the host at `address`, the port may be left out
+    pub fn join(address: &str) -> Result<Connector, String> {
+        let with_port = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, DEFAULT_PORT) };
+        let host = with_port.to_socket_addrs().ok()
+            .and_then(|mut addresses| addresses.find(SocketAddr::is_ipv4))
+            .ok_or(format!("Unknown address '{}'", address))?;
+        let socket = UdpSocket::bind(("0.0.0.0", 0)).map_err(|err| err.to_string())?;
+        socket.set_nonblocking(true).map_err(|err| err.to_string())?;
+        Ok(Connector {
+            socket: socket,
+            setup: None,
+            host: Some(host),
+            last_hello: f64::NEG_INFINITY,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.socket.local_addr().map_or(0, |address| address.port())
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.setup.is_some()
+    }
+
+    /// Moves the handshake along, returns the connection once both sides agree
+    pub fn poll(&mut self, now: f64) -> Result<Option<Connection>, String> {
+        // Keep knocking until the host answers, the first hello might get lost
+        if let Some(host) = self.host {
+            if now - self.last_hello >= HELLO_INTERVAL {
+                let _ = self.socket.send_to(&Packet::Hello.encode(), host);
+                self.last_hello = now;
+            }
+        }
+
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        while let Ok((size, from)) = self.socket.recv_from(&mut buffer) {
+            let packet = match Packet::decode(&buffer[..size]) {
+                Ok(packet) => packet,
+                Err(message) if self.host == Some(from) => return Err(message),
+                Err(_) => continue,
+            };
+            match (&self.setup, packet) {
+                (Some(setup), Packet::Hello) => {
+                    let _ = self.socket.send_to(&Packet::Welcome(setup.clone()).encode(), from);
+                    return self.connect(from, setup.clone(), Side::Left).map(Some);
+                },
+                (None, Packet::Welcome(setup)) if self.host == Some(from) => {
+                    return self.connect(from, setup, Side::Right).map(Some);
+                },
+                _ => (),
+            }
+        }
+        Ok(None)
+    }
+
+    fn connect(&self, peer: SocketAddr, setup: MatchSetup, side: Side) -> Result<Connection, String> {
+        let socket = self.socket.try_clone().map_err(|err| err.to_string())?;
+        Ok(Connection {
+            transport: UdpTransport::new(socket, peer),
+            setup: setup,
+            side: side,
+        })
+    }
+}
+// --------------------- CONNECTING ---------------------
+
+// ===================== ROLLBACK =====================
+/// Keeps a simulation in sync with the peer's copy of it.
+///
+/// Every tick runs right away with the local input and a guess for the remote one (the last
+/// input that arrived). When the real remote input turns out different, the game rolls back
+/// to the last tick both inputs were known for and plays the ticks since then again.
+pub struct NetSession {
+    transport: Box<dyn Transport>,
+    side: Side,
+    /// Answer to a hello that arrives again because the first answer got lost
+    welcome: Option<MatchSetup>,
+    /// Simulation after `confirmed_tick` ticks, only ever fed with real inputs
+    confirmed: Simulation,
+    confirmed_tick: usize,
+    confirmed_checksums: VecDeque<(usize, u64)>,
+    /// Ticks played by the shown simulation
+    tick: usize,
+    local_inputs: Vec<PaddleInput>,
+    remote_inputs: Vec<PaddleInput>,
+    /// Remote input each tick was played with
+    used_remote: Vec<PaddleInput>,
+    /// Local inputs the peer has received
+    peer_ack: usize,
+    last_received: f64,
+    peer_left: bool,
+    /// Events of confirmed ticks since the last call of `take_events`
+    events: Vec<SimEvent>,
+    pub rollbacks: usize,
+    pub desync: bool,
+}
+
+impl NetSession {
+    pub fn new(transport: Box<dyn Transport>, side: Side, sim: &Simulation, welcome: Option<MatchSetup>, now: f64) -> Self {
+        NetSession {
+            transport: transport,
+            side: side,
+            welcome: welcome,
+            confirmed: sim.clone(),
+            confirmed_tick: 0,
+            confirmed_checksums: VecDeque::new(),
+            tick: 0,
+            local_inputs: Vec::new(),
+            remote_inputs: Vec::new(),
+            used_remote: Vec::new(),
+            peer_ack: 0,
+            last_received: now,
+            peer_left: false,
+            events: Vec::new(),
+            rollbacks: 0,
+            desync: false,
+        }
+    }
+
+    pub fn from_connection(connection: Connection, sim: &Simulation, shim: Option<NetShim>, now: f64) -> Self {
+        // The host plays left and sent the setup
+        let welcome = match connection.side {
+            Side::Left => Some(connection.setup),
+            _ => None,
+        };
+        let transport: Box<dyn Transport> = match shim {
+            Some(shim) => Box::new(LossyLink::new(connection.transport, shim.latency, shim.latency / 2., shim.loss, sim.seed)),
+            None => Box::new(connection.transport),
+        };
+        NetSession::new(transport, connection.side, sim, welcome, now)
+    }
+
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    fn tick_input(&self, local: PaddleInput, remote: PaddleInput) -> TickInput {
+        let mut input = TickInput::default();
+        input.set(self.side, local);
+        input.set(self.side.opponent(), remote);
+        input
+    }
+
+    /// Best guess for the remote input of a tick
+    fn remote_input(&self, tick: usize) -> PaddleInput {
+        self.remote_inputs.get(tick)
+            .or(self.remote_inputs.last())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Plays the next tick with the local input, unless the peer is too far behind.
+    /// Returns whether a tick was played.
+    pub fn tick(&mut self, sim: &mut Simulation, local: PaddleInput, now: f64) -> bool {
+        if self.tick - self.confirmed_tick >= MAX_ROLLBACK_TICKS {
+            self.send_inputs(now);
+            return false;
+        }
+
+        let remote = self.remote_input(self.tick);
+        self.local_inputs.push(local);
+        self.used_remote.push(remote);
+        sim.update(&self.tick_input(local, remote));
+        self.tick += 1;
+
+        self.send_inputs(now);
+        true
+    }
+
+    /// Handles everything the peer sent, correcting `sim` if a guess was wrong
+    pub fn poll(&mut self, sim: &mut Simulation, now: f64) -> () {
+        let mut mispredicted = false;
+        while let Some(bytes) = self.transport.receive(now) {
+            let packet = match Packet::decode(&bytes) {
+                Ok(packet) => packet,
+                Err(_) => continue,
+            };
+            self.last_received = now;
+            match packet {
+                Packet::Hello => {
+                    if let Some(setup) = &self.welcome {
+                        self.transport.send(&Packet::Welcome(setup.clone()).encode(), now);
+                    }
+                },
+                Packet::Welcome(_) => (),
+                Packet::Inputs { first_tick, inputs, ack, confirmed_tick, checksum } => {
+                    self.peer_ack = self.peer_ack.max(ack as usize);
+                    for (i, input) in inputs.into_iter().enumerate() {
+                        let tick = first_tick as usize + i;
+                        // Inputs arrive in order of ticks, anything after a gap comes again later
+                        if tick != self.remote_inputs.len() {
+                            continue;
+                        }
+                        if tick < self.tick && self.used_remote[tick] != input {
+                            mispredicted = true;
+                        }
+                        self.remote_inputs.push(input);
+                    }
+                    self.compare_checksum(confirmed_tick as usize, checksum);
+                },
+                Packet::Bye => self.peer_left = true,
+            }
+        }
+
+        self.confirm();
+        if mispredicted {
+            self.rollback(sim);
+        }
+        self.send_inputs(now);
+    }
+
+    /// Moves the confirmed simulation up to the last tick both inputs are known for
+    fn confirm(&mut self) -> () {
+        let known = self.remote_inputs.len().min(self.local_inputs.len());
+        while self.confirmed_tick < known {
+            let input = self.tick_input(self.local_inputs[self.confirmed_tick], self.remote_inputs[self.confirmed_tick]);
+            self.confirmed.update(&input);
+            self.events.extend_from_slice(&self.confirmed.events);
+            self.confirmed_tick += 1;
+
+            self.confirmed_checksums.push_back((self.confirmed_tick, self.confirmed.checksum()));
+            if self.confirmed_checksums.len() > CHECKSUM_HISTORY {
+                self.confirmed_checksums.pop_front();
+            }
+        }
+    }
+
+    /// Plays all unconfirmed ticks again from the confirmed state with the newest guesses
+    fn rollback(&mut self, sim: &mut Simulation) -> () {
+        *sim = self.confirmed.clone();
+        for tick in self.confirmed_tick..self.tick {
+            let remote = self.remote_input(tick);
+            self.used_remote[tick] = remote;
+            sim.update(&self.tick_input(self.local_inputs[tick], remote));
+        }
+        self.rollbacks += 1;
+    }
+
+    fn compare_checksum(&mut self, tick: usize, checksum: u64) -> () {
+        let own = self.confirmed_checksums.iter().find(|(at, _)| *at == tick);
+        if let Some((_, own)) = own {
+            if *own != checksum && !self.desync {
+                eprintln!("Network game went out of sync at tick {}", tick);
+                self.desync = true;
+            }
+        }
+    }
+
+    /// Sends every input the peer hasn't acknowledged yet
+    fn send_inputs(&mut self, now: f64) -> () {
+        let first_tick = self.peer_ack.min(self.local_inputs.len());
+        let last_tick = (first_tick + MAX_INPUTS_PER_PACKET).min(self.local_inputs.len());
+        let (confirmed_tick, checksum) = self.confirmed_checksums.back().copied().unwrap_or((0, 0));
+        let packet = Packet::Inputs {
+            first_tick: first_tick as u32,
+            inputs: self.local_inputs[first_tick..last_tick].to_vec(),
+            ack: self.remote_inputs.len() as u32,
+            confirmed_tick: confirmed_tick as u32,
+            checksum: checksum,
+        };
+        self.transport.send(&packet.encode(), now);
+    }
+
+    /// Events of ticks that can't be undone anymore
+    pub fn take_events(&mut self) -> Vec<SimEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn is_disconnected(&self, now: f64) -> bool {
+        self.peer_left || now - self.last_received > TIMEOUT
+    }
+
+    /// Tells the peer that this side is gone
+    pub fn leave(&mut self, now: f64) -> () {
+        for _ in 0..3 {
+            self.transport.send(&Packet::Bye.encode(), now);
+        }
+    }
+}
+// --------------------- ROLLBACK ---------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TICK_DT;
+
+    fn setup() -> MatchSetup {
+        MatchSetup {
+            seed: 99,
+            arena: Arena::new(800., 600.),
+            rules: MatchRules { points_to_win: 3, time_limit: Some(90.), ..MatchRules::default() },
+            config: Config::default(),
+        }
+    }
+
+    /// Input of a player that keeps changing its mind, so guesses are often wrong
+    fn scripted_input(side: Side, tick: usize) -> PaddleInput {
+        let phase = match side {
+            Side::Left => 0.,
+            _ => 1.3,
+        };
+        PaddleInput { axis: ((tick as f32) * 0.07 + phase).sin().round() }
+    }
+
+    fn localhost_pair() -> (UdpTransport, UdpTransport) {
+        let a = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").unwrap();
+        a.set_nonblocking(true).unwrap();
+        b.set_nonblocking(true).unwrap();
+        let (address_a, address_b) = (a.local_addr().unwrap(), b.local_addr().unwrap());
+        (UdpTransport::new(a, address_b), UdpTransport::new(b, address_a))
+    }
+
+    #[test]
+    fn packets_survive_encoding() {
+        let packets = [
+            Packet::Hello,
+            Packet::Welcome(setup()),
+            Packet::Inputs {
+                first_tick: 12,
+                inputs: vec![PaddleInput { axis: -1. }, PaddleInput { axis: 0.25 }],
+                ack: 7,
+                confirmed_tick: 5,
+                checksum: 0xdead_beef,
+            },
+            Packet::Bye,
+        ];
+        for packet in packets {
+            assert_eq!(Packet::decode(&packet.encode()).unwrap(), packet);
+        }
+
+        let mut other_version = Packet::Hello.encode();
+        other_version[4] = NET_VERSION + 1;
+        assert!(Packet::decode(&other_version).unwrap_err().contains("network version"));
+    }
+
+    #[test]
+    fn handshake_on_localhost() {
+        let mut host = Connector::host(0, setup()).unwrap();
+        let mut join = Connector::join(&format!("127.0.0.1:{}", host.port())).unwrap();
+
+        let mut host_connection = None;
+        let mut join_connection = None;
+        for step in 0..500 {
+            let now = step as f64 * 0.01;
+            if host_connection.is_none() {
+                host_connection = host.poll(now).unwrap();
+            }
+            if join_connection.is_none() {
+                join_connection = join.poll(now).unwrap();
+            }
+            if host_connection.is_some() && join_connection.is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        let (host_connection, join_connection) = (host_connection.unwrap(), join_connection.unwrap());
+        assert_eq!(host_connection.side, Side::Left);
+        assert_eq!(join_connection.side, Side::Right);
+        assert_eq!(join_connection.setup, setup());
+    }
+
+    #[test]
+    fn rollback_keeps_peers_in_sync_over_a_bad_link() {
+        const TICKS: usize = 1_500;
+        let (transport_a, transport_b) = localhost_pair();
+        let mut sims = [setup().simulation(), setup().simulation()];
+        let mut sessions = [
+            NetSession::new(Box::new(LossyLink::new(transport_a, 0.08, 0.04, 0.2, 1)), Side::Left, &sims[0], None, 0.),
+            NetSession::new(Box::new(LossyLink::new(transport_b, 0.08, 0.04, 0.2, 2)), Side::Right, &sims[1], None, 0.),
+        ];
+
+        // Both peers play as fast as the link lets them, then wait until everything is confirmed
+        let mut now = 0.;
+        while sessions.iter().any(|session| session.confirmed_tick < TICKS) {
+            now += TICK_DT as f64;
+            for (session, sim) in sessions.iter_mut().zip(sims.iter_mut()) {
+                session.poll(sim, now);
+                if session.tick < TICKS {
+                    let input = scripted_input(session.side(), session.tick);
+                    session.tick(sim, input, now);
+                }
+            }
+            std::thread::sleep(std::time::Duration::from_micros(200));
+            assert!(now < 600., "peers never caught up");
+        }
+
+        // The same match played locally with every input known
+        let mut reference = setup().simulation();
+        for tick in 0..TICKS {
+            reference.update(&TickInput {
+                left: scripted_input(Side::Left, tick),
+                right: scripted_input(Side::Right, tick),
+                ..TickInput::default()
+            });
+        }
+
+        for (session, sim) in sessions.iter().zip(sims.iter()) {
+            assert!(!session.desync);
+            assert!(session.rollbacks > 0, "the link was too good to test anything");
+            assert_eq!(session.confirmed.checksum(), reference.checksum());
+            assert_eq!(sim.checksum(), reference.checksum());
+        }
+    }
+}
@@ -0,0 +1,493 @@
+use macroquad::prelude::*;
+
+use crate::config::Config;
+use crate::rng::Rng;
+use crate::scheduler::Scheduler;
+use crate::{lerp_color, Arena, Entity, ExcitedThing, Goal, Orientation, Side, COL_BACKGROUND, TICK_DT};
+
+/// Most pickups lying on the field at the same time
+const MAX_PICKUPS: usize = 2;
+
+/// Edge length of a pickup
+const PICKUP_SIZE: f32 = 36.;
+
+/// Pickups only appear in this middle part of the arena, away from the paddles
+const SPAWN_AREA: (f32, f32) = (0.3, 0.7);
+
+/// Paddle height while grown
+const GROW_FACTOR: f32 = 1.5;
+
+/// Opponent paddle speed while slowed down
+const SLOW_FACTOR: f32 = 0.5;
+
+/// Ball speed and speed limit while sped up
+pub const SPEED_UP_FACTOR: f32 = 1.4;
+
+/// Width of the extra wall in front of a goal
+const BARRIER_WIDTH: f32 = 12.;
+
+// ===================== POWER-UP =====================
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PowerUpKind {
+    /// The paddle of the collector grows
+    Grow,
+    /// The paddle of the opponent moves slower
+    Slow,
+    /// The ball flies faster
+    SpeedUp,
+    /// The ball is barely visible
+    Ghost,
+    /// A wall guards the goal of the collector
+    ExtraWall,
+    /// Another ball is served
+    ExtraBall,
+}
+
+const KINDS: [PowerUpKind; 6] = [
+    PowerUpKind::Grow,
+    PowerUpKind::Slow,
+    PowerUpKind::SpeedUp,
+    PowerUpKind::Ghost,
+    PowerUpKind::ExtraWall,
+    PowerUpKind::ExtraBall,
+];
+
+impl PowerUpKind {
+    pub fn color(&self) -> Color {
+        match self {
+            PowerUpKind::Grow      => Color::from_rgba(120, 220, 100, 255),
+            PowerUpKind::Slow      => Color::from_rgba(90, 150, 255, 255),
+            PowerUpKind::SpeedUp   => Color::from_rgba(255, 140, 40, 255),
+            PowerUpKind::Ghost     => Color::from_rgba(200, 200, 220, 255),
+            PowerUpKind::ExtraWall => Color::from_rgba(230, 210, 60, 255),
+            PowerUpKind::ExtraBall => Color::from_rgba(240, 90, 200, 255),
+        }
+    }
+
+    fn id(&self) -> u64 {
+        KINDS.iter().position(|kind| kind == self).unwrap() as u64
+    }
+}
+
+/// Pickup lying on the field, the ball collects it after one of the players hit it
+#[derive(Clone)]
+pub struct PowerUp {
+    pub kind: PowerUpKind,
+    pos: Vec2,
+    size: Vec2,
+    excitement: f32,
+}
+
+impl PowerUp {
+    fn new(kind: PowerUpKind, pos: Vec2) -> Self {
+        PowerUp {
+            kind: kind,
+            pos: pos,
+            size: Vec2::splat(PICKUP_SIZE),
+            excitement: 0.,
+        }
+    }
+}
+
+impl ExcitedThing for PowerUp {
+    fn get_base_color(&self) -> Color {
+        self.kind.color()
+    }
+
+    fn get_excitement(&self) -> f32 {
+        self.excitement
+    }
+
+    fn get_excitement_ref(&mut self) -> &mut f32 {
+        &mut self.excitement
+    }
+}
+
+impl Entity for PowerUp {
+    fn get_pos(&self) -> Vec2 {
+        self.pos
+    }
+
+    fn get_size(&self) -> Vec2 {
+        self.size
+    }
+
+    fn get_stroke_color(&self) -> Color {
+        self.calc_stroke_color()
+    }
+
+    fn get_fill_color(&self) -> Color {
+        self.calc_fill_color()
+    }
+
+    fn resize(&mut self, arena: &Arena, _config: &Config) -> () {
+        // Stay inside the arena
+        self.pos = self.pos.min(Vec2 { x: arena.width, y: arena.height });
+    }
+
+    fn update(&mut self) -> () {
+        // Keep pulsing so it stands out from everything else
+        if self.excitement <= 0. {
+            self.trig_excited();
+        } else {
+            self.lower_excitement();
+        }
+    }
+}
+// --------------------- POWER-UP ---------------------
+
+// ===================== BARRIER =====================
+/// Temporary wall between a goal and its paddle
+#[derive(Clone)]
+pub struct Barrier {
+    pub side: Side,
+    pos: Vec2,
+    size: Vec2,
+    four_players: bool,
+    color: Color,
+    excitement: f32,
+}
+
+impl Barrier {
+    fn new(side: Side, arena: &Arena, config: &Config, four_players: bool) -> Self {
+        Barrier {
+            side: side,
+            pos: Barrier::calc_pos(side, arena, config),
+            size: Barrier::calc_size(side, arena, config, four_players),
+            four_players: four_players,
+            color: lerp_color(&PowerUpKind::ExtraWall.color(), &COL_BACKGROUND, 0.3),
+            excitement: 0.,
+        }
+    }
+
+    /// Halfway between the inner edge of the goal and the back of the paddle
+    fn calc_pos(side: Side, arena: &Arena, config: &Config) -> Vec2 {
+        let offset = (config.arena.goal_width / 2. + config.player.edge_distance - config.player.width / 2.) / 2.;
+        match side {
+            Side::Left   => Vec2 { x: offset, y: arena.height / 2. },
+            Side::Right  => Vec2 { x: arena.width - offset, y: arena.height / 2. },
+            Side::Top    => Vec2 { x: arena.width / 2., y: offset },
+            Side::Bottom => Vec2 { x: arena.width / 2., y: arena.height - offset },
+        }
+    }
+
+    /// As long as the goal it guards and thin across
+    fn calc_size(side: Side, arena: &Arena, config: &Config, four_players: bool) -> Vec2 {
+        let goal = Goal::calc_size(side, arena, config, four_players);
+        match side.orientation() {
+            Orientation::Vertical   => Vec2 { x: BARRIER_WIDTH, y: goal.y },
+            Orientation::Horizontal => Vec2 { x: goal.x, y: BARRIER_WIDTH },
+        }
+    }
+}
+
+impl ExcitedThing for Barrier {
+    fn get_base_color(&self) -> Color {
+        self.color
+    }
+
+    fn get_excitement(&self) -> f32 {
+        self.excitement
+    }
+
+    fn get_excitement_ref(&mut self) -> &mut f32 {
+        &mut self.excitement
+    }
+}
+
+impl Entity for Barrier {
+    fn get_pos(&self) -> Vec2 {
+        self.pos
+    }
+
+    fn get_size(&self) -> Vec2 {
+        self.size
+    }
+
+    fn get_stroke_color(&self) -> Color {
+        self.calc_stroke_color()
+    }
+
+    fn get_fill_color(&self) -> Color {
+        self.calc_fill_color()
+    }
+
+    fn resize(&mut self, arena: &Arena, config: &Config) -> () {
+        self.size = Barrier::calc_size(self.side, arena, config, self.four_players);
+        self.pos  = Barrier::calc_pos(self.side, arena, config);
+    }
+
+    fn update(&mut self) -> () {
+        self.lower_excitement();
+    }
+}
+// --------------------- BARRIER ---------------------
+
+// ===================== POWER-UPS =====================
+/// A collected power-up that is still running
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ActiveEffect {
+    pub kind: PowerUpKind,
+    /// Player who collected it
+    pub side: Side,
+}
+
+/// Things that happen to power-ups after a while
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum PowerUpTimer {
+    /// The next pickup appears
+    Spawn,
+    /// An effect runs out
+    Expire(ActiveEffect),
+}
+
+/// Multipliers the running effects apply on top of the config
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Modifiers {
+    /// Indexed by side
+    pub paddle_height: [f32; 4],
+    pub paddle_speed: [f32; 4],
+    pub ball_speed: f32,
+    pub ghost: bool,
+}
+
+/// Spawns pickups, hands out their effects and takes them away again
+#[derive(Clone)]
+pub struct PowerUps {
+    pub pickups: Vec<PowerUp>,
+    pub effects: Vec<ActiveEffect>,
+    pub barriers: Vec<Barrier>,
+    four_players: bool,
+    rng: Rng,
+    timers: Scheduler<PowerUpTimer>,
+}
+
+impl PowerUps {
+    pub fn new(seed: u64, config: &Config, four_players: bool) -> Self {
+        // Own generator so power-ups don't change what else is random in a match
+        let mut rng = Rng::new(seed ^ 0x5057_5550_5355_5053);
+        let mut timers = Scheduler::new();
+        timers.schedule(PowerUpTimer::Spawn, PowerUps::spawn_delay(&mut rng, config));
+        PowerUps {
+            pickups: Vec::new(),
+            effects: Vec::new(),
+            barriers: Vec::new(),
+            four_players: four_players,
+            rng: rng,
+            timers: timers,
+        }
+    }
+
+    /// Seconds until the next pickup
+    fn spawn_delay(rng: &mut Rng, config: &Config) -> f32 {
+        config.power_ups.spawn_interval * rng.range(1., 2.)
+    }
+
+    /// Removes everything from the field and ends all effects
+    pub fn clear(&mut self) -> () {
+        self.pickups.clear();
+        self.effects.clear();
+        self.barriers.clear();
+        self.timers.cancel_if(|timer| matches!(timer, PowerUpTimer::Expire(_)));
+    }
+
+    /// Power-ups only come and go while the ball is in play, their time stands still otherwise
+    pub fn set_paused(&mut self, paused: bool) -> () {
+        self.timers.set_paused(paused);
+    }
+
+    /// Counts down the effects and drops a new pickup on the field from time to time
+    pub fn update(&mut self, arena: &Arena, config: &Config) -> () {
+        for timer in self.timers.update(TICK_DT) {
+            match timer {
+                PowerUpTimer::Expire(expired) => self.effects.retain(|effect| *effect != expired),
+                PowerUpTimer::Spawn => {
+                    let delay = PowerUps::spawn_delay(&mut self.rng, config);
+                    self.timers.schedule(PowerUpTimer::Spawn, delay);
+                    if config.power_ups.enabled && self.pickups.len() < MAX_PICKUPS {
+                        self.spawn(arena, config);
+                    }
+                },
+            }
+        }
+        let effects = &self.effects;
+        self.barriers.retain(|barrier| effects.iter()
+            .any(|effect| effect.kind == PowerUpKind::ExtraWall && effect.side == barrier.side));
+    }
+
+    /// Drops a random pickup somewhere in the middle of the field
+    fn spawn(&mut self, arena: &Arena, config: &Config) -> () {
+        let kind = KINDS[(self.rng.next_u64() % KINDS.len() as u64) as usize];
+        let margin = config.arena.wall_height / 2. + PICKUP_SIZE;
+        let pos = match self.four_players {
+            // Stay away from all four paddles
+            true => Vec2 {
+                x: arena.width * self.rng.range(SPAWN_AREA.0, SPAWN_AREA.1),
+                y: arena.height * self.rng.range(SPAWN_AREA.0, SPAWN_AREA.1),
+            },
+            false => Vec2 {
+                x: arena.width * self.rng.range(SPAWN_AREA.0, SPAWN_AREA.1),
+                y: self.rng.range(margin, (arena.height - margin).max(margin)),
+            },
+        };
+        self.pickups.push(PowerUp::new(kind, pos));
+    }
+
+    /// Hands the effect of a pickup to `side`. Collecting one that is running already restarts it.
+    /// An extra ball has no lasting effect, serving it is up to the simulation.
+    pub fn collect(&mut self, index: usize, side: Side, arena: &Arena, config: &Config) -> PowerUpKind {
+        let kind = self.pickups.remove(index).kind;
+        if kind == PowerUpKind::ExtraBall {
+            return kind;
+        }
+        let effect = ActiveEffect { kind: kind, side: side };
+        if !self.effects.contains(&effect) {
+            self.effects.push(effect);
+        }
+        self.timers.schedule(PowerUpTimer::Expire(effect), config.power_ups.duration);
+        if kind == PowerUpKind::ExtraWall && !self.barriers.iter().any(|barrier| barrier.side == side) {
+            self.barriers.push(Barrier::new(side, arena, config, self.four_players));
+        }
+        kind
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        let mut modifiers = Modifiers {
+            paddle_height: [1.; 4],
+            paddle_speed: [1.; 4],
+            ball_speed: 1.,
+            ghost: false,
+        };
+        for effect in &self.effects {
+            match effect.kind {
+                PowerUpKind::Grow      => modifiers.paddle_height[effect.side as usize] = GROW_FACTOR,
+                // Slows down everybody else
+                PowerUpKind::Slow => for side in Side::ALL.into_iter().filter(|&side| side != effect.side) {
+                    modifiers.paddle_speed[side as usize] = SLOW_FACTOR;
+                },
+                PowerUpKind::SpeedUp   => modifiers.ball_speed = SPEED_UP_FACTOR,
+                PowerUpKind::Ghost     => modifiers.ghost = true,
+                PowerUpKind::ExtraWall | PowerUpKind::ExtraBall => (),
+            }
+        }
+        modifiers
+    }
+
+    /// Feeds everything that affects the match into a checksum, nothing at all while no power-up is around
+    pub fn feed_checksum(&self, feed: &mut impl FnMut(u64)) -> () {
+        for pickup in &self.pickups {
+            feed(pickup.kind.id());
+            feed(pickup.pos.x.to_bits() as u64);
+            feed(pickup.pos.y.to_bits() as u64);
+        }
+        for effect in &self.effects {
+            feed(effect.kind.id());
+            feed(effect.side as u64);
+            let time_left = self.timers.time_left(PowerUpTimer::Expire(*effect)).unwrap_or(0.);
+            feed(time_left.to_bits() as u64);
+        }
+    }
+}
+// --------------------- POWER-UPS ---------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::MatchRules;
+    use crate::{PaddleInput, SimEvent, Simulation, TickInput, TICK_RATE};
+
+    fn simulation(spawn_interval: f32) -> Simulation {
+        let mut config = Config::default();
+        config.power_ups.enabled = true;
+        config.power_ups.spawn_interval = spawn_interval;
+        Simulation::new(Arena::new(800., 600.), config, MatchRules::default(), 3)
+    }
+
+    fn serve(sim: &mut Simulation) -> () {
+        while sim.balls[0].vel == Vec2::ZERO {
+            sim.update(&TickInput::default());
+        }
+    }
+
+    /// Puts a pickup across the whole height of the arena so the ball can't miss it
+    fn block_path(sim: &mut Simulation, kind: PowerUpKind, x: f32) -> () {
+        let mut pickup = PowerUp::new(kind, Vec2 { x: x, y: 300. });
+        pickup.size.y = 600.;
+        sim.power_ups.pickups = vec![pickup];
+    }
+
+    #[test]
+    fn pickups_are_only_collected_after_a_hit() {
+        let mut sim = simulation(100.);
+        serve(&mut sim);
+
+        // The serve itself flies straight through
+        block_path(&mut sim, PowerUpKind::Grow, 300.);
+        for _ in 0..60 {
+            sim.update(&TickInput::default());
+        }
+        assert_eq!(sim.power_ups.pickups.len(), 1);
+        assert_eq!(sim.balls[0].last_hit, None);
+
+        // Once the left paddle returned the ball, it collects
+        let mut collected: Vec<SimEvent> = Vec::new();
+        for _ in 0..300 {
+            sim.update(&TickInput::default());
+            collected.extend(sim.events.iter().filter(|event| matches!(event, SimEvent::PowerUp(..))));
+        }
+        assert_eq!(collected, vec![SimEvent::PowerUp(PowerUpKind::Grow, Side::Left)]);
+        assert_eq!(sim.players[0].size.y, 150. * GROW_FACTOR);
+    }
+
+    #[test]
+    fn effects_run_out() {
+        let mut sim = simulation(100.);
+        serve(&mut sim);
+        block_path(&mut sim, PowerUpKind::Slow, 400.);
+        sim.power_ups.collect(0, Side::Left, &sim.arena.clone(), &sim.config.clone());
+
+        sim.update(&TickInput::default());
+        assert_eq!(sim.players[1].speed, 7. * SLOW_FACTOR);
+        assert_eq!(sim.players[0].speed, 7.);
+
+        for _ in 0..(8. * TICK_RATE) as usize {
+            sim.update(&TickInput::default());
+        }
+        assert!(sim.power_ups.effects.is_empty());
+        assert_eq!(sim.players[1].speed, 7.);
+    }
+
+    #[test]
+    fn extra_wall_guards_the_goal() {
+        let mut sim = simulation(100.);
+        serve(&mut sim);
+        block_path(&mut sim, PowerUpKind::ExtraWall, 400.);
+        sim.power_ups.collect(0, Side::Left, &sim.arena.clone(), &sim.config.clone());
+
+        // The left paddle hides in the corner, but the barrier sends the ball back
+        let input = TickInput {
+            left: PaddleInput::from_buttons(true, false),
+            right: PaddleInput::default(),
+            ..TickInput::default()
+        };
+        for _ in 0..300 {
+            sim.update(&input);
+        }
+        assert_eq!(sim.score.points[1], 0);
+        assert_eq!(sim.power_ups.barriers.len(), 1);
+    }
+
+    #[test]
+    fn spawning_is_deterministic() {
+        let mut a = simulation(0.5);
+        let mut b = simulation(0.5);
+        for _ in 0..200 {
+            a.update(&TickInput::default());
+            b.update(&TickInput::default());
+        }
+        assert!(!a.power_ups.pickups.is_empty());
+        let positions = |sim: &Simulation| sim.power_ups.pickups.iter().map(|p| (p.kind, p.pos)).collect::<Vec<_>>();
+        assert_eq!(positions(&a), positions(&b));
+        assert_eq!(a.checksum(), b.checksum());
+    }
+}
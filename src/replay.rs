@@ -0,0 +1,504 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::level::Level;
+use crate::rules::{EdgeRule, MatchRules, ServeRule};
+use crate::{Arena, PaddleInput, Side, Simulation, TickInput};
+
+/// Version of the file format, replays of other versions are refused instead of played wrong
+pub const REPLAY_VERSION: u32 = 11;
+
+const MAGIC: &str = "RUSTEDPONG-REPLAY";
+
+/// Everything needed to play a match again exactly the way it happened.
+///
+/// The file is plain text so it can be attached to bug reports and diffed. Floats are
+/// stored as the hex of their bits, repeated inputs are stored once with a count.
+/// The config is embedded as TOML with every line prefixed, changes to it while playing
+/// are stored the same way with `reconfigure`. Matches on a level store it the same way with `level`.
+/// Four player matches store four inputs per line,
+/// in the order left, right, top, bottom:
+///
+/// ```text
+/// RUSTEDPONG-REPLAY 11
+/// seed 1234
+/// arena 44480000 44160000
+/// rules 11 1 - 1 0 5 loser bounce
+/// config [player]
+/// config speed = 7.0
+/// config ...
+/// input 120 00000000 bf800000
+/// resize 44800000 44340000
+/// reconfigure [ball]
+/// reconfigure bounciness = 0.8
+/// reconfigure ...
+/// input 3 3f800000 00000000
+/// checksum 8a3e9c1f2b7d4e60
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub arena: Arena,
+    pub rules: MatchRules,
+    pub config: Config,
+    pub level: Option<Level>,
+    pub inputs: Vec<TickInput>,
+    /// Changes to the simulation and the tick before which they happened
+    pub events: Vec<(usize, ReplayEvent)>,
+    /// Checksum of the simulation after the last tick
+    pub checksum: Option<u64>,
+}
+
+/// Something that happened to the simulation from the outside during a match
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayEvent {
+    Resize(Arena),
+    Configure(Config),
+}
+
+impl Replay {
+    /// Starts a recording of a freshly created simulation
+    pub fn new(sim: &Simulation) -> Self {
+        Replay {
+            seed: sim.seed,
+            arena: sim.arena,
+            rules: sim.rules,
+            config: sim.config.clone(),
+            level: sim.level.clone(),
+            inputs: Vec::new(),
+            events: Vec::new(),
+            checksum: None,
+        }
+    }
+
+    pub fn record(&mut self, input: TickInput) -> () {
+        self.inputs.push(input);
+    }
+
+    pub fn record_resize(&mut self, arena: Arena) -> () {
+        self.events.push((self.inputs.len(), ReplayEvent::Resize(arena)));
+    }
+
+    pub fn record_config(&mut self, config: Config) -> () {
+        // Only the last of several changes within the same tick matters
+        let tick = self.inputs.len();
+        if let Some((at, ReplayEvent::Configure(_))) = self.events.last() {
+            if *at == tick {
+                self.events.pop();
+            }
+        }
+        self.events.push((tick, ReplayEvent::Configure(config)));
+    }
+
+    /// Seals the recording with the state the simulation ended up in
+    pub fn finish(&mut self, sim: &Simulation) -> () {
+        self.checksum = Some(sim.checksum());
+    }
+
+    /// The simulation as it was before the first tick
+    pub fn simulation(&self) -> Simulation {
+        match &self.level {
+            Some(level) => Simulation::with_level(level.clone(), self.config.clone(), self.rules, self.seed),
+            None => Simulation::new(self.arena, self.config.clone(), self.rules, self.seed),
+        }
+    }
+
+    /// Plays the whole replay without a window
+    pub fn run(&self) -> Simulation {
+        let mut sim = self.simulation();
+        let mut playback = Playback::new(self.clone());
+        while let Some(input) = playback.next(&mut sim) {
+            sim.update(&input);
+        }
+        sim
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read replay {}: {}", path.display(), err))?;
+        Replay::parse(&text).map_err(|err| format!("Invalid replay {}: {}", path.display(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_text())
+            .map_err(|err| format!("Could not write replay {}: {}", path.display(), err))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![
+            format!("{} {}", MAGIC, REPLAY_VERSION),
+            format!("seed {}", self.seed),
+            format!("arena {}", arena_to_text(&self.arena)),
+            format!(
+                "rules {} {} {} {} {} {} {} {}",
+                self.rules.points_to_win,
+                self.rules.win_by_two as u8,
+                self.rules.time_limit.map_or("-".to_string(), float_to_text),
+                self.rules.best_of,
+                self.rules.four_players as u8,
+                self.rules.lives,
+                format!("{:?}", self.rules.serve).to_lowercase(),
+                format!("{:?}", self.rules.edge_hits).to_lowercase()
+            ),
+        ];
+        push_block(&mut lines, "config", &self.config.to_text());
+        if let Some(level) = &self.level {
+            push_block(&mut lines, "level", &level.to_text());
+        }
+
+        // Group equal inputs into runs, an event always ends a run
+        let mut events = self.events.iter().peekable();
+        let mut tick = 0;
+        while tick <= self.inputs.len() {
+            while let Some((_, event)) = events.next_if(|(at, _)| *at == tick) {
+                match event {
+                    ReplayEvent::Resize(arena) => lines.push(format!("resize {}", arena_to_text(arena))),
+                    ReplayEvent::Configure(config) => push_block(&mut lines, "reconfigure", &config.to_text()),
+                }
+            }
+            let input = match self.inputs.get(tick) {
+                Some(input) => input,
+                None => break,
+            };
+            let next_event = events.peek().map_or(usize::MAX, |(at, _)| *at);
+            let mut count = 1;
+            while tick + count < next_event && self.inputs.get(tick + count) == Some(input) {
+                count += 1;
+            }
+            let mut line = format!("input {} {} {}", count, float_to_text(input.left.axis), float_to_text(input.right.axis));
+            if self.rules.four_players {
+                line += &format!(" {} {}", float_to_text(input.top.axis), float_to_text(input.bottom.axis));
+            }
+            lines.push(line);
+            tick += count;
+        }
+
+        if let Some(checksum) = self.checksum {
+            lines.push(format!("checksum {:016x}", checksum));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    pub fn parse(text: &str) -> Result<Replay, String> {
+        let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+
+        // Header
+        let (_, header) = lines.next().ok_or("File is empty")?;
+        let version = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            [MAGIC, version] => version.parse::<u32>().map_err(|_| "Broken version number")?,
+            _ => return Err("Not a replay file".to_string()),
+        };
+        if version != REPLAY_VERSION {
+            return Err(format!("Replay version {} is not supported, expected {}", version, REPLAY_VERSION));
+        }
+
+        let mut seed = None;
+        let mut arena = None;
+        let mut rules = None;
+        let mut config = None;
+        let mut level = None;
+        let mut replay_inputs = Vec::new();
+        let mut events = Vec::new();
+        let mut checksum = None;
+        // TOML lines of the config or level block being read and the line it started on
+        let mut config_block: Option<(&str, usize, String)> = None;
+
+        for (number, line) in lines {
+            let error = |message: &str| format!("Line {}: {}", number + 1, message);
+
+            // Collect config blocks until another kind of line shows up
+            let block_line = ["config", "reconfigure", "level"].into_iter()
+                .find_map(|keyword| Some((keyword, line.strip_prefix(keyword)?.strip_prefix(' ')?)));
+            if let Some((keyword, toml_line)) = block_line {
+                match &mut config_block {
+                    Some((current, _, text)) if *current == keyword => text.push_str(toml_line),
+                    _ => {
+                        finish_config_block(config_block.take(), &mut config, &mut level, &mut events, replay_inputs.len())?;
+                        config_block = Some((keyword, number, toml_line.to_string()));
+                    },
+                }
+                config_block.as_mut().unwrap().2.push('\n');
+                continue;
+            }
+            finish_config_block(config_block.take(), &mut config, &mut level, &mut events, replay_inputs.len())?;
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                ["seed", value] => seed = Some(value.parse().map_err(|_| error("Broken seed"))?),
+                ["arena", width, height] => arena = Some(arena_from_text(width, height).ok_or_else(|| error("Broken arena"))?),
+                ["rules", points, win_by_two, time_limit, best_of, four_players, lives, serve, edge_hits] => {
+                    let parsed = MatchRules {
+                        points_to_win: points.parse().map_err(|_| error("Broken points to win"))?,
+                        win_by_two: win_by_two == "1",
+                        time_limit: match time_limit {
+                            "-" => None,
+                            value => Some(float_from_text(value).ok_or_else(|| error("Broken time limit"))?),
+                        },
+                        best_of: best_of.parse().map_err(|_| error("Broken best of"))?,
+                        four_players: four_players == "1",
+                        lives: lives.parse().map_err(|_| error("Broken lives"))?,
+                        serve: ServeRule::from_name(serve).ok_or_else(|| error("Broken serve rule"))?,
+                        edge_hits: EdgeRule::from_name(edge_hits).ok_or_else(|| error("Broken edge rule"))?,
+                    };
+                    parsed.validate().map_err(|err| error(&err))?;
+                    rules = Some(parsed);
+                },
+                ["input", count, ref axes @ ..] if axes.len() == 2 || axes.len() == 4 => {
+                    let count: usize = count.parse().map_err(|_| error("Broken input count"))?;
+                    let mut input = TickInput::default();
+                    for (&side, axis) in Side::ALL.iter().zip(axes) {
+                        let axis = float_from_text(axis).ok_or_else(|| error(&format!("Broken {:?} input", side)))?;
+                        input.set(side, PaddleInput { axis: axis });
+                    }
+                    replay_inputs.extend(std::iter::repeat_n(input, count));
+                },
+                ["resize", width, height] => {
+                    let arena = arena_from_text(width, height).ok_or_else(|| error("Broken arena"))?;
+                    events.push((replay_inputs.len(), ReplayEvent::Resize(arena)));
+                },
+                ["checksum", value] => {
+                    checksum = Some(u64::from_str_radix(value, 16).map_err(|_| error("Broken checksum"))?);
+                },
+                _ => return Err(error(&format!("Unexpected '{}'", line.trim()))),
+            }
+        }
+        finish_config_block(config_block.take(), &mut config, &mut level, &mut events, replay_inputs.len())?;
+
+        Ok(Replay {
+            seed: seed.ok_or("Seed is missing")?,
+            arena: arena.ok_or("Arena is missing")?,
+            rules: rules.ok_or("Rules are missing")?,
+            config: config.ok_or("Config is missing")?,
+            level: level,
+            inputs: replay_inputs,
+            events: events,
+            checksum: checksum,
+        })
+    }
+}
+
+/// Feeds a replay into a simulation tick by tick, applying its events at the right moment
+pub struct Playback {
+    replay: Replay,
+    tick: usize,
+    next_event: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Playback {
+            replay: replay,
+            tick: 0,
+            next_event: 0,
+        }
+    }
+
+    /// Input for the next tick, `None` once the replay is over
+    pub fn next(&mut self, sim: &mut Simulation) -> Option<TickInput> {
+        while let Some((at, event)) = self.replay.events.get(self.next_event) {
+            if *at != self.tick {
+                break;
+            }
+            match event {
+                ReplayEvent::Resize(arena) => sim.resize(*arena),
+                ReplayEvent::Configure(config) => sim.reconfigure(config.clone()),
+            }
+            self.next_event += 1;
+        }
+
+        let input = self.replay.inputs.get(self.tick).copied();
+        if input.is_some() {
+            self.tick += 1;
+        }
+        input
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick >= self.replay.inputs.len()
+    }
+
+    /// Whether the simulation ended up exactly where the recording did
+    pub fn verify(&self, sim: &Simulation) -> Option<bool> {
+        self.replay.checksum.map(|checksum| checksum == sim.checksum())
+    }
+}
+
+/// `path` while it is still free, otherwise the first free one of `name-2.rpr`, `name-3.rpr` and so on.
+/// Recording several matches keeps every one of them.
+pub fn free_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    let extension = path.extension().map_or(String::new(), |extension| format!(".{}", extension.to_string_lossy()));
+    (2..)
+        .map(|number| path.with_file_name(format!("{}-{}{}", stem, number, extension)))
+        .find(|candidate| !candidate.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Writes a config or level as TOML, every line prefixed with the keyword
+fn push_block(lines: &mut Vec<String>, keyword: &str, toml: &str) -> () {
+    for toml_line in toml.lines().filter(|line| !line.trim().is_empty()) {
+        lines.push(format!("{} {}", keyword, toml_line));
+    }
+}
+
+/// Turns a finished block of TOML lines into the starting config, the level or a change at `tick`
+fn finish_config_block(
+    block: Option<(&str, usize, String)>,
+    config: &mut Option<Config>,
+    level: &mut Option<Level>,
+    events: &mut Vec<(usize, ReplayEvent)>,
+    tick: usize,
+) -> Result<(), String> {
+    let (keyword, number, text) = match block {
+        Some(block) => block,
+        None => return Ok(()),
+    };
+    if keyword == "level" {
+        *level = Some(Level::parse(&text).map_err(|err| format!("Line {}: Broken level: {}", number + 1, err))?);
+        return Ok(());
+    }
+    let parsed = Config::parse(&text).map_err(|err| format!("Line {}: Broken config: {}", number + 1, err))?;
+    match keyword {
+        "config" => *config = Some(parsed),
+        _ => events.push((tick, ReplayEvent::Configure(parsed))),
+    }
+    Ok(())
+}
+
+fn float_to_text(value: f32) -> String {
+    format!("{:08x}", value.to_bits())
+}
+
+fn float_from_text(text: &str) -> Option<f32> {
+    u32::from_str_radix(text, 16).ok().map(f32::from_bits)
+}
+
+fn arena_to_text(arena: &Arena) -> String {
+    format!("{} {}", float_to_text(arena.width), float_to_text(arena.height))
+}
+
+fn arena_from_text(width: &str, height: &str) -> Option<Arena> {
+    Some(Arena::new(float_from_text(width)?, float_from_text(height)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays a scripted match and records it
+    fn record(ticks: usize) -> (Replay, Simulation) {
+        let mut sim = Simulation::new(Arena::new(800., 600.), Config::default(), MatchRules::default(), 42);
+        let mut replay = Replay::new(&sim);
+        for tick in 0..ticks {
+            if tick == ticks / 3 {
+                let mut config = Config::default();
+                config.ball.bounciness = 0.75;
+                config.player.speed = 9.5;
+                sim.reconfigure(config.clone());
+                replay.record_config(config);
+            }
+            if tick == ticks / 2 {
+                let arena = Arena::new(900., 650.);
+                sim.resize(arena);
+                replay.record_resize(arena);
+            }
+            let input = TickInput {
+                left: PaddleInput::from_buttons(tick % 120 < 50, tick % 120 >= 70),
+                right: PaddleInput { axis: ((tick as f32) * 0.05).sin() },
+                ..TickInput::default()
+            };
+            replay.record(input);
+            sim.update(&input);
+        }
+        replay.finish(&sim);
+        (replay, sim)
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let (replay, _) = record(2_000);
+        let parsed = Replay::parse(&replay.to_text()).unwrap();
+        assert_eq!(parsed, replay);
+    }
+
+    #[test]
+    fn playback_is_bit_exact() {
+        let (replay, sim) = record(3_000);
+        let replayed = Replay::parse(&replay.to_text()).unwrap().run();
+        assert_eq!(replayed.checksum(), sim.checksum());
+        assert_eq!(replayed.balls[0].pos, sim.balls[0].pos);
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let (replay, _) = record(10);
+        let text = replay.to_text().replacen(&format!("{} {}", MAGIC, REPLAY_VERSION), &format!("{} 999", MAGIC), 1);
+        assert!(Replay::parse(&text).unwrap_err().contains("version 999"));
+        assert!(Replay::parse("hello").is_err());
+    }
+
+    #[test]
+    fn broken_lines_are_reported() {
+        let (replay, _) = record(10);
+        let text = replay.to_text().replace("seed 42", "seed forty-two");
+        assert_eq!(Replay::parse(&text).unwrap_err(), "Line 2: Broken seed");
+    }
+
+    #[test]
+    fn recordings_never_overwrite_each_other() {
+        let dir = std::env::temp_dir().join(format!("rusted_pong_replay_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("match.rpr");
+        assert_eq!(free_path(&path), path);
+
+        let (replay, _) = record(10);
+        replay.save(&free_path(&path)).unwrap();
+        assert_eq!(free_path(&path), dir.join("match-2.rpr"));
+        replay.save(&free_path(&path)).unwrap();
+        assert_eq!(free_path(&path), dir.join("match-3.rpr"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn level_is_stored_with_the_replay() {
+        let level = Level::parse(include_str!("../levels/pillars.toml")).unwrap();
+        let mut sim = Simulation::with_level(level, Config::default(), MatchRules::default(), 3);
+        let mut replay = Replay::new(&sim);
+        let input = TickInput { left: PaddleInput::from_buttons(true, false), ..TickInput::default() };
+        for _ in 0..600 {
+            replay.record(input);
+            sim.update(&input);
+        }
+        replay.finish(&sim);
+
+        let parsed = Replay::parse(&replay.to_text()).unwrap();
+        assert_eq!(parsed, replay);
+        assert_eq!(Some(parsed.run().checksum()), replay.checksum);
+    }
+
+    /// Every replay checked into `replays/` is a regression test
+    #[test]
+    fn stored_replays_still_play_the_same() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("replays");
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "rpr") {
+                let replay = Replay::load(&path).unwrap();
+                let sim = replay.run();
+                assert_eq!(Some(sim.checksum()), replay.checksum, "{} plays differently", path.display());
+            }
+        }
+    }
+}
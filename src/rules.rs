@@ -0,0 +1,273 @@
+use crate::{Score, Side};
+
+/// How a match is won
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MatchRules {
+    /// Points needed to win a set
+    pub points_to_win: u32,
+    /// A set only ends once the winner leads by at least two points
+    pub win_by_two: bool,
+    /// Length of the match in seconds, after that the leader wins (or the next goal decides)
+    pub time_limit: Option<f32>,
+    /// Number of sets played at most, the first to win more than half of them wins the match
+    pub best_of: u32,
+    /// Every side of the arena gets a paddle, players drop out when they run out of lives
+    pub four_players: bool,
+    /// Goals a player can concede in a four player match
+    pub lives: u32,
+    /// Who the ball is served to after a goal
+    pub serve: ServeRule,
+    /// What the ball does when it hits the top or bottom of a paddle
+    pub edge_hits: EdgeRule,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules {
+            points_to_win: 11,
+            win_by_two: true,
+            time_limit: None,
+            best_of: 1,
+            four_players: false,
+            lives: 5,
+            serve: ServeRule::Loser,
+            edge_hits: EdgeRule::Bounce,
+        }
+    }
+}
+
+/// Who gets the ball after a goal, the first serve of a match always goes left
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ServeRule {
+    /// Everybody in turn
+    Alternate,
+    /// The side that conceded the goal
+    Loser,
+    /// The side that scored, or the one that conceded if nobody touched the ball
+    Winner,
+}
+
+impl ServeRule {
+    pub fn from_name(name: &str) -> Option<ServeRule> {
+        match name.to_lowercase().as_str() {
+            "alternate" => Some(ServeRule::Alternate),
+            "loser"     => Some(ServeRule::Loser),
+            "winner"    => Some(ServeRule::Winner),
+            _ => None,
+        }
+    }
+
+    /// Side the next serve goes to. Four player matches still have to skip players who are out.
+    pub fn next_serve(&self, last_serve: Side, conceded: Side, scorer: Option<Side>, four_players: bool) -> Side {
+        match self {
+            ServeRule::Alternate if four_players => Side::ALL[(last_serve as usize + 1) % 4],
+            ServeRule::Alternate => last_serve.opponent(),
+            ServeRule::Loser => conceded,
+            ServeRule::Winner => scorer.unwrap_or(conceded),
+        }
+    }
+}
+
+/// What happens to a ball that hits the end of a paddle instead of its face
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EdgeRule {
+    /// Bounces off the end, still counts as a hit
+    Bounce,
+    /// The paddle missed it, the point goes to the opponent
+    Miss,
+}
+
+impl EdgeRule {
+    pub fn from_name(name: &str) -> Option<EdgeRule> {
+        match name.to_lowercase().as_str() {
+            "bounce" => Some(EdgeRule::Bounce),
+            "miss"   => Some(EdgeRule::Miss),
+            _ => None,
+        }
+    }
+}
+
+/// What a goal meant for the match
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Outcome {
+    Continue,
+    SetWon(Side),
+    MatchWon(Side),
+}
+
+impl MatchRules {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.points_to_win == 0 {
+            return Err("Points to win must be at least 1".to_string());
+        }
+        if self.best_of.is_multiple_of(2) {
+            return Err(format!("Best of {} sets can end in a draw, use an odd number", self.best_of));
+        }
+        if self.lives == 0 {
+            return Err("Lives must be at least 1".to_string());
+        }
+        if let Some(limit) = self.time_limit {
+            if limit <= 0. || !limit.is_finite() {
+                return Err(format!("Time limit of {} seconds is not possible", limit));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn sets_to_win(&self) -> u32 {
+        self.best_of / 2 + 1
+    }
+
+    /// Checks the score right after a goal was registered, `time_up` once the time limit ran out
+    pub fn evaluate(&self, score: &Score, time_up: bool) -> Outcome {
+        if self.four_players {
+            return self.evaluate_four_way(score, time_up);
+        }
+
+        let set_winner = [Side::Left, Side::Right].into_iter().find(|&side| {
+            let (own, other) = score.points(side);
+            let margin = if self.win_by_two { 2 } else { 1 };
+            own >= self.points_to_win && own >= other + margin
+        });
+
+        match set_winner {
+            Some(side) => {
+                let (sets, _) = score.sets(side);
+                if sets + 1 >= self.sets_to_win() {
+                    Outcome::MatchWon(side)
+                } else {
+                    Outcome::SetWon(side)
+                }
+            },
+            // Once the time is up the next goal breaks any tie
+            None if time_up => {
+                MatchRules::leader(score).map_or(Outcome::Continue, Outcome::MatchWon)
+            },
+            None => Outcome::Continue,
+        }
+    }
+
+    /// Four player matches have no sets, the last one with lives left wins
+    fn evaluate_four_way(&self, score: &Score, time_up: bool) -> Outcome {
+        let mut alive = Side::ALL.into_iter().filter(|&side| !score.is_out(side));
+        match (alive.next(), alive.next()) {
+            (Some(side), None) => Outcome::MatchWon(side),
+            _ if time_up => {
+                MatchRules::leader(score).map_or(Outcome::Continue, Outcome::MatchWon)
+            },
+            _ => Outcome::Continue,
+        }
+    }
+
+    /// Side that is ahead, first by sets then by points.
+    /// In a four player match lives count first, a tie for the lead has no leader.
+    pub fn leader(score: &Score) -> Option<Side> {
+        if score.four_players {
+            let standing = |side: Side| (score.lives[side as usize], score.points[side as usize]);
+            let best = Side::ALL.into_iter().map(standing).max()?;
+            let mut leaders = Side::ALL.into_iter().filter(|&side| standing(side) == best);
+            return match (leaders.next(), leaders.next()) {
+                (Some(side), None) => Some(side),
+                _ => None,
+            };
+        }
+
+        let (sets_left, sets_right) = score.sets(Side::Left);
+        let (points_left, points_right) = score.points(Side::Left);
+        match (sets_left, points_left).cmp(&(sets_right, points_right)) {
+            std::cmp::Ordering::Greater => Some(Side::Left),
+            std::cmp::Ordering::Less    => Some(Side::Right),
+            std::cmp::Ordering::Equal   => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(left: u32, right: u32) -> Score {
+        let mut score = Score::new();
+        score.points[0] = left;
+        score.points[1] = right;
+        score
+    }
+
+    #[test]
+    fn first_to_n_wins() {
+        let rules = MatchRules { points_to_win: 5, win_by_two: false, ..MatchRules::default() };
+        assert_eq!(rules.evaluate(&score(4, 3), false), Outcome::Continue);
+        assert_eq!(rules.evaluate(&score(5, 4), false), Outcome::MatchWon(Side::Left));
+        assert_eq!(rules.evaluate(&score(2, 5), false), Outcome::MatchWon(Side::Right));
+    }
+
+    #[test]
+    fn win_by_two_extends_the_set() {
+        let rules = MatchRules { points_to_win: 5, ..MatchRules::default() };
+        assert_eq!(rules.evaluate(&score(5, 4), false), Outcome::Continue);
+        assert_eq!(rules.evaluate(&score(7, 6), false), Outcome::Continue);
+        assert_eq!(rules.evaluate(&score(8, 6), false), Outcome::MatchWon(Side::Left));
+    }
+
+    #[test]
+    fn best_of_three_needs_two_sets() {
+        let rules = MatchRules { points_to_win: 3, win_by_two: false, best_of: 3, ..MatchRules::default() };
+        let mut score = score(0, 3);
+        assert_eq!(rules.evaluate(&score, false), Outcome::SetWon(Side::Right));
+
+        score.win_set(Side::Right);
+        score.points[0] = 3;
+        assert_eq!(rules.evaluate(&score, false), Outcome::SetWon(Side::Left));
+
+        score.win_set(Side::Left);
+        score.points[1] = 3;
+        assert_eq!(rules.evaluate(&score, false), Outcome::MatchWon(Side::Right));
+    }
+
+    #[test]
+    fn time_limit_goes_to_the_leader() {
+        let rules = MatchRules { time_limit: Some(60.), ..MatchRules::default() };
+        assert_eq!(rules.evaluate(&score(3, 2), false), Outcome::Continue);
+        assert_eq!(rules.evaluate(&score(3, 2), true), Outcome::MatchWon(Side::Left));
+        assert_eq!(rules.evaluate(&score(3, 3), true), Outcome::Continue);
+    }
+
+    #[test]
+    fn four_players_play_until_one_is_left() {
+        let rules = MatchRules { four_players: true, lives: 1, ..MatchRules::default() };
+        let mut score = Score::four_way(1);
+        score.lose_life(Side::Left);
+        score.lose_life(Side::Top);
+        assert_eq!(rules.evaluate(&score, false), Outcome::Continue);
+        score.lose_life(Side::Bottom);
+        assert_eq!(rules.evaluate(&score, false), Outcome::MatchWon(Side::Right));
+    }
+
+    #[test]
+    fn four_player_leader_has_the_most_lives() {
+        let mut score = Score::four_way(3);
+        assert_eq!(MatchRules::leader(&score), None);
+        score.lose_life(Side::Left);
+        score.lose_life(Side::Right);
+        score.increment(Side::Top);
+        assert_eq!(MatchRules::leader(&score), Some(Side::Top));
+    }
+
+    #[test]
+    fn serve_rules_pick_the_next_server() {
+        assert_eq!(ServeRule::Alternate.next_serve(Side::Left, Side::Left, Some(Side::Right), false), Side::Right);
+        assert_eq!(ServeRule::Alternate.next_serve(Side::Right, Side::Left, None, true), Side::Top);
+        assert_eq!(ServeRule::Loser.next_serve(Side::Left, Side::Right, Some(Side::Left), false), Side::Right);
+        assert_eq!(ServeRule::Winner.next_serve(Side::Left, Side::Right, Some(Side::Left), false), Side::Left);
+        assert_eq!(ServeRule::Winner.next_serve(Side::Left, Side::Top, None, true), Side::Top);
+        assert_eq!(ServeRule::from_name("Winner"), Some(ServeRule::Winner));
+    }
+
+    #[test]
+    fn even_best_of_is_rejected() {
+        assert!(MatchRules { best_of: 2, ..MatchRules::default() }.validate().is_err());
+        assert!(MatchRules { points_to_win: 0, ..MatchRules::default() }.validate().is_err());
+        assert!(MatchRules { lives: 0, ..MatchRules::default() }.validate().is_err());
+        assert!(MatchRules::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,152 @@
+/// Events waiting to happen after a delay in seconds of game time.
+///
+/// Every event is its own name: scheduling one that is already waiting starts it over and it
+/// can be cancelled by value. Events due in the same update come out in the order they are due,
+/// ties in the order they were scheduled, so a match plays out the same on every machine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheduler<E> {
+    entries: Vec<Entry<E>>,
+    /// Counts up with every scheduled event to break ties
+    next_order: u64,
+    paused: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry<E> {
+    event: E,
+    /// Seconds until it's due
+    time_left: f32,
+    /// Seconds it was scheduled for
+    delay: f32,
+    order: u64,
+}
+
+impl<E: Copy + PartialEq> Scheduler<E> {
+    pub fn new() -> Self {
+        Scheduler {
+            entries: Vec::new(),
+            next_order: 0,
+            paused: false,
+        }
+    }
+
+    /// Lets `event` happen in `delay` seconds, replacing the same event if it was waiting already
+    pub fn schedule(&mut self, event: E, delay: f32) -> () {
+        self.cancel(event);
+        self.entries.push(Entry {
+            event: event,
+            time_left: delay,
+            delay: delay,
+            order: self.next_order,
+        });
+        self.next_order += 1;
+    }
+
+    /// Returns whether the event was waiting
+    pub fn cancel(&mut self, event: E) -> bool {
+        let count = self.entries.len();
+        self.entries.retain(|entry| entry.event != event);
+        self.entries.len() != count
+    }
+
+    pub fn cancel_if(&mut self, mut condition: impl FnMut(&E) -> bool) -> () {
+        self.entries.retain(|entry| !condition(&entry.event));
+    }
+
+    pub fn clear(&mut self) -> () {
+        self.entries.clear();
+    }
+
+    pub fn is_scheduled(&self, event: E) -> bool {
+        self.entries.iter().any(|entry| entry.event == event)
+    }
+
+    /// First waiting event matching the condition, with its seconds left and the delay it was scheduled for
+    pub fn find(&self, mut condition: impl FnMut(&E) -> bool) -> Option<(E, f32, f32)> {
+        self.entries.iter()
+            .find(|entry| condition(&entry.event))
+            .map(|entry| (entry.event, entry.time_left, entry.delay))
+    }
+
+    pub fn time_left(&self, event: E) -> Option<f32> {
+        self.find(|&other| other == event).map(|(_, time_left, _)| time_left)
+    }
+
+    /// A paused scheduler keeps its events but time stands still for them
+    pub fn set_paused(&mut self, paused: bool) -> () {
+        self.paused = paused;
+    }
+
+    /// Lets `dt` seconds pass and returns the events that are due, earliest first
+    pub fn update(&mut self, dt: f32) -> Vec<E> {
+        if self.paused {
+            return Vec::new();
+        }
+        for entry in &mut self.entries {
+            entry.time_left -= dt;
+        }
+
+        let mut due: Vec<Entry<E>> = Vec::new();
+        let mut i = 0;
+        while i < self.entries.len() {
+            if self.entries[i].time_left <= 0. {
+                due.push(self.entries.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        due.sort_by(|a, b| a.time_left.total_cmp(&b.time_left).then(a.order.cmp(&b.order)));
+        due.into_iter().map(|entry| entry.event).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    enum Alarm {
+        A,
+        B,
+        C(u32),
+    }
+
+    #[test]
+    fn events_come_out_in_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(Alarm::C(1), 0.3);
+        scheduler.schedule(Alarm::A, 0.2);
+        scheduler.schedule(Alarm::B, 0.3);
+        assert_eq!(scheduler.update(0.1), vec![]);
+        assert_eq!(scheduler.update(0.5), vec![Alarm::A, Alarm::C(1), Alarm::B]);
+        assert_eq!(scheduler.find(|_| true), None);
+    }
+
+    #[test]
+    fn scheduling_again_restarts_and_cancel_removes() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(Alarm::A, 1.);
+        scheduler.schedule(Alarm::C(1), 1.);
+        scheduler.schedule(Alarm::C(2), 1.);
+        scheduler.update(0.5);
+        scheduler.schedule(Alarm::A, 1.);
+        assert_eq!(scheduler.time_left(Alarm::A), Some(1.));
+        assert_eq!(scheduler.find(|alarm| matches!(alarm, Alarm::C(_))), Some((Alarm::C(1), 0.5, 1.)));
+
+        assert!(scheduler.cancel(Alarm::C(1)));
+        assert!(!scheduler.cancel(Alarm::B));
+        scheduler.cancel_if(|alarm| matches!(alarm, Alarm::C(_)));
+        assert_eq!(scheduler.update(1.), vec![Alarm::A]);
+    }
+
+    #[test]
+    fn paused_time_stands_still() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(Alarm::A, 0.5);
+        scheduler.set_paused(true);
+        assert_eq!(scheduler.update(1.), vec![]);
+        assert_eq!(scheduler.time_left(Alarm::A), Some(0.5));
+        scheduler.set_paused(false);
+        assert_eq!(scheduler.update(0.5), vec![Alarm::A]);
+    }
+}
@@ -0,0 +1,283 @@
+// Without the `audio` feature the sounds are still synthesized by the tests, but never played
+#![cfg_attr(not(feature = "audio"), allow(dead_code))]
+
+use crate::rng::Rng;
+use crate::SimEvent;
+
+/// Samples per second of every generated sound
+pub const SAMPLE_RATE: u32 = 22_050;
+
+/// Hit sounds are rendered at this many pitches, fast and off center hits sound higher
+pub const HIT_PITCHES: usize = 8;
+/// Pitch of the slowest and straightest hit, the fastest one is an octave higher
+const HIT_FREQUENCY: f32 = 330.;
+
+/// Shape of a tone
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Wave {
+    Square,
+    Triangle,
+    Noise,
+}
+
+/// A single tone that slides from `start` to `end` Hz while it fades out
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tone {
+    pub wave: Wave,
+    pub start: f32,
+    pub end: f32,
+    /// Seconds
+    pub length: f32,
+    /// Seconds until the tone reaches full volume, avoids clicks at the start
+    pub attack: f32,
+    pub volume: f32,
+}
+
+impl Tone {
+    /// Renders the tone to samples between -1 and 1
+    pub fn render(&self, rng: &mut Rng) -> Vec<f32> {
+        let count = (self.length * SAMPLE_RATE as f32) as usize;
+        let mut phase: f32 = 0.;
+        let mut noise = 0.;
+        (0..count)
+            .map(|i| {
+                let progress = i as f32 / count as f32;
+                let frequency = self.start + (self.end - self.start) * progress;
+                let last_phase = phase;
+                phase = (phase + frequency / SAMPLE_RATE as f32).fract();
+                let value = match self.wave {
+                    Wave::Square => if phase < 0.5 { 1. } else { -1. },
+                    Wave::Triangle => 1. - 4. * (phase - 0.5).abs(),
+                    // A new random value every period gives noise of roughly that pitch
+                    Wave::Noise => {
+                        if phase < last_phase {
+                            noise = rng.range(-1., 1.);
+                        }
+                        noise
+                    },
+                };
+                let seconds = i as f32 / SAMPLE_RATE as f32;
+                let envelope = (seconds / self.attack).min(1.) * (1. - progress).powi(2);
+                value * envelope * self.volume
+            })
+            .collect()
+    }
+}
+
+/// Every sound the game makes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Effect {
+    /// Paddle hit at one of the `HIT_PITCHES` pitches, 0 is the lowest
+    Hit(usize),
+    Bounce,
+    Goal,
+    /// Tick before a serve, the last one is higher
+    Countdown { last: bool },
+}
+
+impl Effect {
+    pub fn all() -> Vec<Effect> {
+        let mut effects: Vec<Effect> = (0..HIT_PITCHES).map(Effect::Hit).collect();
+        effects.extend([Effect::Bounce, Effect::Goal, Effect::Countdown { last: false }, Effect::Countdown { last: true }]);
+        effects
+    }
+
+    /// Tones of the effect, played one after the other
+    fn tones(&self) -> Vec<Tone> {
+        let blip = |wave, start, end, length, volume| Tone {
+            wave: wave,
+            start: start,
+            end: end,
+            length: length,
+            attack: 0.003,
+            volume: volume,
+        };
+        match *self {
+            Effect::Hit(pitch) => {
+                let frequency = HIT_FREQUENCY * 2f32.powf(pitch as f32 / (HIT_PITCHES - 1) as f32);
+                vec![blip(Wave::Square, frequency, frequency * 0.9, 0.07, 0.35)]
+            },
+            Effect::Bounce => vec![blip(Wave::Triangle, 260., 200., 0.05, 0.6)],
+            // Rising jingle followed by a short rush of noise
+            Effect::Goal => vec![
+                blip(Wave::Square, 523., 523., 0.08, 0.3),
+                blip(Wave::Square, 659., 659., 0.08, 0.3),
+                blip(Wave::Square, 784., 784., 0.16, 0.3),
+                blip(Wave::Noise, 3000., 800., 0.2, 0.2),
+            ],
+            Effect::Countdown { last: false } => vec![blip(Wave::Square, 880., 880., 0.04, 0.25)],
+            Effect::Countdown { last: true } => vec![blip(Wave::Square, 1320., 1320., 0.1, 0.25)],
+        }
+    }
+
+    /// Renders the effect to samples between -1 and 1, always the same ones
+    pub fn render(&self) -> Vec<f32> {
+        let mut rng = Rng::new(0);
+        self.tones().iter().flat_map(|tone| tone.render(&mut rng)).collect()
+    }
+
+    /// Sound for something that happened in the simulation, if it makes one.
+    /// `speed_limit` is the speed at which hits reach the highest pitch.
+    pub fn for_event(event: &SimEvent, speed_limit: f32) -> Option<Effect> {
+        match *event {
+            SimEvent::Hit(_, _, speed, rel_diff) => Some(Effect::Hit(hit_pitch(speed, rel_diff, speed_limit))),
+            SimEvent::Bounce(_) => Some(Effect::Bounce),
+            SimEvent::Goal(_) | SimEvent::Point(_) | SimEvent::LifeLost(_) | SimEvent::Eliminated(_)
+                | SimEvent::MatchOver(_) => Some(Effect::Goal),
+            SimEvent::Countdown(step) => Some(Effect::Countdown { last: step == 1 }),
+            SimEvent::Serve(_) | SimEvent::PowerUp(..) => None,
+        }
+    }
+}
+
+/// Picks the pitch of a hit, mostly by the speed of the ball and a bit by how far off center it was hit
+pub fn hit_pitch(speed: f32, rel_diff: f32, speed_limit: f32) -> usize {
+    let amount = 0.7 * (speed / speed_limit).min(1.) + 0.3 * rel_diff.abs().min(1.);
+    (amount.clamp(0., 1.) * (HIT_PITCHES - 1) as f32).round() as usize
+}
+
+/// Wraps samples into a 16 bit mono WAV file
+pub fn to_wav(samples: &[f32]) -> Vec<u8> {
+    let data_size = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, one channel
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&((sample.clamp(-1., 1.) * i16::MAX as f32) as i16).to_le_bytes());
+    }
+    wav
+}
+
+/// Plays the effects, all of them synthesized once at startup.
+/// Without the `audio` feature there is nothing to play them with.
+pub struct Sounds {
+    #[cfg(feature = "audio")]
+    loaded: Vec<(Effect, macroquad::audio::Sound)>,
+    /// 0 is silent, 1 is full volume
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Sounds {
+    #[cfg(feature = "audio")]
+    pub async fn load(volume: f32, muted: bool) -> Self {
+        let mut loaded = Vec::new();
+        for effect in Effect::all() {
+            match macroquad::audio::load_sound_from_bytes(&to_wav(&effect.render())).await {
+                Ok(sound) => loaded.push((effect, sound)),
+                Err(err) => eprintln!("Could not load the {:?} sound: {}", effect, err),
+            }
+        }
+        Sounds {
+            loaded: loaded,
+            volume: volume,
+            muted: muted,
+        }
+    }
+
+    #[cfg(not(feature = "audio"))]
+    pub async fn load(volume: f32, muted: bool) -> Self {
+        Sounds {
+            volume: volume,
+            muted: muted,
+        }
+    }
+
+    /// Plays the sounds of a tick, every effect only once even if it happened several times
+    pub fn play_events(&self, events: &[SimEvent], speed_limit: f32) -> () {
+        if self.muted || self.volume <= 0. {
+            return;
+        }
+        let mut played: Vec<Effect> = Vec::new();
+        for effect in events.iter().filter_map(|event| Effect::for_event(event, speed_limit)) {
+            if !played.contains(&effect) {
+                self.play(effect);
+                played.push(effect);
+            }
+        }
+    }
+
+    #[cfg(feature = "audio")]
+    fn play(&self, effect: Effect) -> () {
+        use macroquad::audio::{play_sound, PlaySoundParams};
+
+        if let Some((_, sound)) = self.loaded.iter().find(|(loaded, _)| *loaded == effect) {
+            play_sound(sound, PlaySoundParams { looped: false, volume: self.volume });
+        }
+    }
+
+    #[cfg(not(feature = "audio"))]
+    fn play(&self, _effect: Effect) -> () {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Side;
+    use macroquad::math::Vec2;
+
+    /// Number of times the samples go from negative to positive, twice the pitch for twice the frequency
+    fn rising_crossings(samples: &[f32]) -> usize {
+        samples.windows(2).filter(|pair| pair[0] < 0. && pair[1] >= 0.).count()
+    }
+
+    #[test]
+    fn effects_render_to_the_expected_length() {
+        for effect in Effect::all() {
+            let samples = effect.render();
+            let length: f32 = effect.tones().iter().map(|tone| tone.length).sum();
+            assert!(samples.len().abs_diff((length * SAMPLE_RATE as f32) as usize) <= effect.tones().len());
+            assert!(samples.iter().all(|sample| (-1. ..=1.).contains(sample)), "{:?} clips", effect);
+            assert!(samples.iter().any(|&sample| sample != 0.), "{:?} is silent", effect);
+        }
+    }
+
+    #[test]
+    fn rendering_is_deterministic() {
+        assert_eq!(Effect::Goal.render(), Effect::Goal.render());
+    }
+
+    #[test]
+    fn faster_hits_sound_higher() {
+        let slow = hit_pitch(3., 0., 8.);
+        let fast = hit_pitch(8., 0., 8.);
+        let curved = hit_pitch(3., 0.9, 8.);
+        assert_eq!(slow, 2);
+        assert_eq!(fast, 5);
+        assert!(curved > slow);
+        assert_eq!(hit_pitch(50., -3., 8.), HIT_PITCHES - 1);
+
+        let low = rising_crossings(&Effect::Hit(0).render());
+        let high = rising_crossings(&Effect::Hit(HIT_PITCHES - 1).render());
+        assert!(high.abs_diff(low * 2) <= 2, "{} vs {}", low, high);
+    }
+
+    #[test]
+    fn wav_has_a_valid_header() {
+        let samples = Effect::Bounce.render();
+        let wav = to_wav(&samples);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()) as usize, wav.len() - 8);
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), SAMPLE_RATE);
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()) as usize, samples.len() * 2);
+    }
+
+    #[test]
+    fn events_map_to_effects() {
+        assert_eq!(Effect::for_event(&SimEvent::Hit(Side::Left, Vec2::ZERO, 8., 1.), 8.), Some(Effect::Hit(HIT_PITCHES - 1)));
+        assert_eq!(Effect::for_event(&SimEvent::Countdown(1), 8.), Some(Effect::Countdown { last: true }));
+        assert_eq!(Effect::for_event(&SimEvent::Serve(Side::Left), 8.), None);
+    }
+}
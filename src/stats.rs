@@ -0,0 +1,73 @@
+use crate::{Side, TICK_RATE};
+
+/// Numbers collected while a match is played. They never change how the match plays,
+/// so they are left out of the checksum.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MatchStats {
+    /// Paddle hits of the whole match
+    pub hits: u32,
+    /// Paddle hits since the last serve
+    rally: u32,
+    pub longest_rally: u32,
+    /// Fastest any ball flew, in arena units per second
+    pub fastest_ball: f32,
+    /// Paddle hits per side
+    pub side_hits: [u32; 4],
+    /// Sum of how far off center each hit of a side was, 0 is the center and 1 the edge
+    curve_sum: [f32; 4],
+}
+
+impl MatchStats {
+    pub fn new() -> Self {
+        MatchStats::default()
+    }
+
+    /// `rel_diff` is the offset of the ball from the center of the paddle, as used for the curve
+    pub fn record_hit(&mut self, side: Side, rel_diff: f32) -> () {
+        self.hits += 1;
+        self.rally += 1;
+        self.longest_rally = self.longest_rally.max(self.rally);
+        self.side_hits[side as usize] += 1;
+        self.curve_sum[side as usize] += rel_diff.abs().min(1.);
+    }
+
+    pub fn record_serve(&mut self) -> () {
+        self.rally = 0;
+    }
+
+    /// `speed` is in arena units per tick like every velocity in the simulation
+    pub fn record_ball_speed(&mut self, speed: f32) -> () {
+        self.fastest_ball = self.fastest_ball.max(speed * TICK_RATE);
+    }
+
+    /// How much a side curved the ball on average, from 0 (always straight) to 1 (always the edge)
+    pub fn curve_usage(&self, side: Side) -> f32 {
+        match self.side_hits[side as usize] {
+            0 => 0.,
+            hits => self.curve_sum[side as usize] / hits as f32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rallies_restart_with_every_serve() {
+        let mut stats = MatchStats::new();
+        for _ in 0..3 {
+            stats.record_hit(Side::Left, 0.);
+        }
+        stats.record_serve();
+        stats.record_hit(Side::Right, 0.5);
+        stats.record_hit(Side::Right, -1.5);
+
+        assert_eq!(stats.hits, 5);
+        assert_eq!(stats.longest_rally, 3);
+        assert_eq!(stats.side_hits, [3, 2, 0, 0]);
+        assert_eq!(stats.curve_usage(Side::Left), 0.);
+        assert_eq!(stats.curve_usage(Side::Right), 0.75);
+        assert_eq!(stats.curve_usage(Side::Top), 0.);
+    }
+}
@@ -0,0 +1,45 @@
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use macroquad::math::Vec2;
+
+/// Sine and cosine of `angle` radians from plain arithmetic. The platform's `sin` and `cos`
+/// may differ in the last bit between systems, which would make replays and network games drift apart.
+pub fn sin_cos(angle: f32) -> (f32, f32) {
+    // Down to -π..π, then folded onto -π/2..π/2 where the series is accurate
+    let mut x = angle - (angle / TAU).round() * TAU;
+    let mut flip = 1.;
+    if x > FRAC_PI_2 {
+        x = PI - x;
+        flip = -1.;
+    } else if x < -FRAC_PI_2 {
+        x = -PI - x;
+        flip = -1.;
+    }
+
+    let x2 = x * x;
+    let sin = x * (1. - x2 / 6. * (1. - x2 / 20. * (1. - x2 / 42. * (1. - x2 / 72. * (1. - x2 / 110. * (1. - x2 / 156.))))));
+    let cos = 1. - x2 / 2. * (1. - x2 / 12. * (1. - x2 / 30. * (1. - x2 / 56. * (1. - x2 / 90. * (1. - x2 / 132.)))));
+    (sin, cos * flip)
+}
+
+/// Unit vector pointing at `angle`, like `Vec2::from_angle`
+pub fn from_angle(angle: f32) -> Vec2 {
+    let (sin, cos) = sin_cos(angle);
+    Vec2 { x: cos, y: sin }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_platform_closely() {
+        for i in -2000..=2000 {
+            let angle = i as f32 * 0.01;
+            let (sin, cos) = sin_cos(angle);
+            assert!((sin - angle.sin()).abs() < 1e-6, "sin {}", angle);
+            assert!((cos - angle.cos()).abs() < 1e-6, "cos {}", angle);
+        }
+        assert_eq!(from_angle(0.), Vec2::new(1., 0.));
+    }
+}
@@ -0,0 +1,74 @@
+use macroquad::prelude::*;
+
+use crate::Arena;
+
+/// Where the arena ends up in the window: scaled as large as it fits without stretching,
+/// with bars filling whatever is left on two of the sides
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Viewport {
+    /// Screen pixels per arena unit
+    pub scale: f32,
+    /// Top left corner of the arena on the screen
+    pub offset: Vec2,
+    /// Size of the arena on the screen
+    pub size: Vec2,
+    screen: Vec2,
+}
+
+impl Viewport {
+    pub fn fit(arena: &Arena, screen: Vec2) -> Self {
+        let scale = (screen.x / arena.width).min(screen.y / arena.height);
+        let size = Vec2 { x: arena.width, y: arena.height } * scale;
+        Viewport {
+            scale: scale,
+            offset: (screen - size) / 2.,
+            size: size,
+            screen: screen,
+        }
+    }
+
+    /// Arena coordinates of a point on the screen, like the mouse
+    pub fn arena_pos(&self, screen_pos: Vec2) -> Vec2 {
+        (screen_pos - self.offset) / self.scale
+    }
+
+    /// Camera drawing arena coordinates into the viewport, moved by `shake` arena units
+    pub fn camera(&self, arena: &Arena, shake: Vec2) -> Camera2D {
+        Camera2D {
+            target: arena.center() - shake,
+            zoom: Vec2 { x: 2. * self.scale / self.screen.x, y: 2. * self.scale / self.screen.y },
+            ..Camera2D::default()
+        }
+    }
+
+    /// Covers everything outside the arena, in screen coordinates
+    pub fn draw_bars(&self, color: Color) -> () {
+        let far = self.offset + self.size;
+        draw_rectangle(0., 0., self.screen.x, self.offset.y, color);
+        draw_rectangle(0., far.y, self.screen.x, self.screen.y - far.y, color);
+        draw_rectangle(0., 0., self.offset.x, self.screen.y, color);
+        draw_rectangle(far.x, 0., self.screen.x - far.x, self.screen.y, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_windows_get_bars_left_and_right() {
+        let viewport = Viewport::fit(&Arena::new(1280., 720.), Vec2::new(1920., 720.));
+        assert_eq!(viewport.scale, 1.);
+        assert_eq!(viewport.offset, Vec2::new(320., 0.));
+        assert_eq!(viewport.size, Vec2::new(1280., 720.));
+    }
+
+    #[test]
+    fn tall_windows_get_bars_above_and_below() {
+        let viewport = Viewport::fit(&Arena::new(1280., 720.), Vec2::new(640., 720.));
+        assert_eq!(viewport.scale, 0.5);
+        assert_eq!(viewport.offset, Vec2::new(0., 180.));
+        assert_eq!(viewport.size, Vec2::new(640., 360.));
+        assert_eq!(viewport.arena_pos(Vec2::new(320., 360.)), Vec2::new(640., 360.));
+    }
+}